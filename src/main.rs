@@ -1,33 +1,45 @@
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::{sse::Event, sse::KeepAlive, sse::Sse, IntoResponse},
-    routing::{get, post},
+    body::Bytes,
+    extract::{DefaultBodyLimit, Path, Query, Request, State},
+    http::{HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{sse::Event, sse::KeepAlive, sse::Sse, Html, IntoResponse, Response},
+    routing::{get, patch, post},
     Json, Router,
 };
 use chrono::Utc;
-use futures_util::StreamExt;
+use futures_util::{future::BoxFuture, stream, StreamExt};
+use glob_match::glob_match;
+use metrics::{counter, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::{
     borrow::Cow,
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     convert::Infallible,
     error::Error,
     path::{Path as StdPath, PathBuf},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
     time::Duration,
 };
 use thiserror::Error;
 use tokio::{
     fs,
-    io::AsyncWriteExt,
+    io::{AsyncReadExt, AsyncWriteExt},
     process::Command,
-    sync::{broadcast, RwLock},
+    sync::{broadcast, Mutex, OwnedSemaphorePermit, RwLock, Semaphore},
 };
 use tokio_stream::wrappers::BroadcastStream;
-use tower_http::cors::{Any, CorsLayer};
-use tracing::{error, info, warn};
+use tower_http::{
+    cors::{Any, CorsLayer},
+    limit::RequestBodyLimitLayer,
+};
+use tracing::{error, info, info_span, warn, Instrument};
+use tracing_subscriber::EnvFilter;
 use uuid::Uuid;
 
 const EMBEDDING_DIM: usize = 768;
@@ -37,24 +49,249 @@ const HF_DEFAULT_BASE_URL: &str = "https://router.huggingface.co/hf-inference/mo
 const HF_DEFAULT_MAX_RETRIES: usize = 3;
 const HF_DEFAULT_BACKOFF_MS: u64 = 500;
 const HF_DEFAULT_BACKOFF_MAX_MS: u64 = 8000;
+const OPENAI_DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+const OPENAI_DEFAULT_EMBEDDING_MODEL: &str = "text-embedding-3-small";
 const HF_DEFAULT_SUMMARY_MODEL: &str = "sshleifer/distilbart-cnn-12-6";
 const HF_DEFAULT_SUMMARY_MAX_CHARS: usize = 3200;
 const HF_DEFAULT_SUMMARY_TOP_FILES: usize = 60;
+const HF_DEFAULT_SUMMARY_CONCURRENCY: usize = 2;
+const SUMMARY_MAX_SECTIONS: usize = 12;
+const DEFAULT_COMMIT_INDEX_LIMIT: usize = 200;
+const DOC_TYPE_CODE: &str = "code";
+const DOC_TYPE_COMMIT: &str = "commit";
+const DOC_TYPE_RELEASE: &str = "release";
+const DEFAULT_RELEASE_INDEX_LIMIT: usize = 10;
+const MAX_RELEASE_INDEX_LIMIT: usize = 100;
+const DEFAULT_CONTEXT_LINES: usize = 10;
+const MAX_CONTEXT_LINES: usize = 200;
+const DEFAULT_SNIPPET_LINES: usize = 8;
+const MAX_SNIPPET_LINES: usize = 50;
+const EVENT_HISTORY_LIMIT: usize = 100;
+const MAX_STATUS_WAIT_SECS: u64 = 60;
+const GENERATED_AVG_LINE_LENGTH: usize = 400;
+const GENERATED_HEADER_LINES: usize = 10;
+const DEFAULT_VESPA_CONNECT_TIMEOUT_SECS: u64 = 5;
+const DEFAULT_VESPA_REQUEST_TIMEOUT_SECS: u64 = 60;
+const LANGUAGE_CACHE_TTL: Duration = Duration::from_secs(60);
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_BATCH_QUERIES: usize = 100;
+const DEFAULT_SEARCH_HITS: usize = 10;
+const MAX_SEARCH_HITS: usize = 100;
+const MAX_SEARCH_OFFSET: usize = 1000;
+const DEFAULT_MAX_QUERY_CHARS: usize = 500;
+const DEFAULT_CHUNK_MAX_LINES: usize = 200;
+const DEFAULT_CHUNK_OVERLAP_LINES: usize = 20;
+const DEFAULT_VESPA_FEED_CONCURRENCY: usize = 8;
+const DEFAULT_EMBED_CONCURRENCY: usize = 4;
+const MAX_SYMBOL_DEPTH: usize = 4;
+const MAX_RECORDED_FEED_FAILURES: usize = 100;
+const DEFAULT_MAX_CHUNKS_PER_FILE: usize = 1000;
+const DEFAULT_MAX_QUERY_TERMS: usize = 32;
+const DEFAULT_VESPA_SUMMARY_CLASSES: &str = "default,paths";
+const IDEMPOTENCY_KEY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const IDEMPOTENCY_MAX_KEYS: usize = 1000;
+const DEFAULT_MIN_FREE_DISK_BYTES: u64 = 1024 * 1024 * 1024;
+const DEFAULT_ARCHIVE_MAX_BYTES: u64 = 512 * 1024 * 1024;
+const ARCHIVE_DOWNLOAD_TIMEOUT_SECS: u64 = 600;
+const ARCHIVE_BRANCH: &str = "release";
+const DEFAULT_EMBEDDING_MAX_CONCURRENCY: usize = 8;
+const SEARCH_UI_HTML: &str = include_str!("ui.html");
+const VERIFY_FEED_SAMPLE_SIZE: usize = 5;
+const VERIFY_FEED_TOLERANCE: f64 = 0.05;
+const VERIFY_FEED_ATTEMPTS: usize = 3;
+const VERIFY_FEED_RETRY_SECS: u64 = 2;
+const GITHUB_RATE_LIMIT_RETRIES: usize = 3;
+const GITHUB_RATE_LIMIT_MAX_WAIT_SECS: u64 = 300;
+const DEFAULT_MAX_CONTENT_BYTES: u64 = 200_000;
+const DEFAULT_MAX_CONCURRENT_INGESTS: usize = 2;
+const DEFAULT_QUERY_EMBED_CACHE_SIZE: usize = 512;
+const BATCH_SEARCH_CONCURRENCY: usize = 4;
 const SUMMARY_PROVIDER_HF: &str = "huggingface";
 const SUMMARY_PROVIDER_COLAB: &str = "colab";
+const DEFAULT_MAX_REQUEST_BODY_BYTES: usize = 256 * 1024;
+const DEFAULT_MAX_BULK_REQUEST_BODY_BYTES: usize = 16 * 1024 * 1024;
+const PROGRESS_PERSIST_INTERVAL: usize = 25;
+const COMMIT_TIME_SCAN_LIMIT: usize = 10_000;
+const DEBUG_EMBEDDINGS_SAMPLE_EVERY: usize = 10;
+const DEBUG_EMBEDDINGS_MAX_BYTES: u64 = 16 * 1024 * 1024;
+const GITHUB_HOST: &str = "github.com";
+const GITLAB_HOST: &str = "gitlab.com";
+const LICENSE_FILE_NAMES: [&str; 10] = [
+    "LICENSE",
+    "LICENSE.md",
+    "LICENSE.txt",
+    "LICENCE",
+    "LICENCE.md",
+    "COPYING",
+    "COPYING.md",
+    "COPYING.txt",
+    "LICENSE-MIT",
+    "LICENSE-APACHE",
+];
+const LICENSE_PATTERNS: &[(&str, &[&str])] = &[
+    ("MPL-2.0", &["mozilla public license", "2.0"]),
+    ("EPL-2.0", &["eclipse public license", "2.0"]),
+    ("Apache-2.0", &["apache license", "version 2.0"]),
+    (
+        "AGPL-3.0",
+        &["gnu affero general public license", "version 3"],
+    ),
+    (
+        "LGPL-3.0",
+        &["gnu lesser general public license", "version 3"],
+    ),
+    (
+        "LGPL-2.1",
+        &["gnu lesser general public license", "version 2.1"],
+    ),
+    ("GPL-3.0", &["gnu general public license", "version 3"]),
+    ("GPL-2.0", &["gnu general public license", "version 2"]),
+    ("BSL-1.0", &["boost software license", "1.0"]),
+    (
+        "Unlicense",
+        &["free and unencumbered software released into the public domain"],
+    ),
+    ("CC0-1.0", &["cc0 1.0 universal"]),
+    (
+        "ISC",
+        &["permission to use, copy, modify, and/or distribute this software for any"],
+    ),
+    ("MIT", &["permission is hereby granted, free of charge"]),
+    (
+        "Zlib",
+        &[
+            "provided 'as-is'",
+            "altered source versions must be plainly marked",
+        ],
+    ),
+    (
+        "BSD-3-Clause",
+        &[
+            "redistribution and use in source and binary forms",
+            "endorse or promote",
+        ],
+    ),
+    (
+        "BSD-2-Clause",
+        &["redistribution and use in source and binary forms"],
+    ),
+];
+const DOCUMENT_RESPONSE_FIELDS: &str = "repo_id,file_path,line_start,line_end,content,doc_type,\
+language,license_spdx,commit_sha,blob_sha,committed_at,embedding_model,symbol_names,also_in";
+const RANK_FEATURE_PREFIXES: [&str; 5] = [
+    "bm25(",
+    "closeness(",
+    "distance(",
+    "nativeRank",
+    "firstPhase",
+];
+const SEARCH_LATENCY_BUCKETS: [f64; 10] = [0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+const METRICS_UPKEEP_INTERVAL: Duration = Duration::from_secs(5);
+const DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 30;
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(250);
+const SHUTDOWN_CONNECTION_GRACE: Duration = Duration::from_secs(5);
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct RepoRecord {
     id: String,
     repo_url: String,
+    #[serde(default = "default_repo_host")]
+    host: String,
     owner: String,
     name: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    include_generated: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    embedding_model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    branch: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    commit: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct GitHubRepo {
     name: String,
-    default_branch: String,
+    #[serde(default)]
+    default_branch: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitHubRepoDetails {
+    name: String,
+    owner: GitHubOwner,
+    #[serde(default)]
+    default_branch: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct GitHubRelease {
+    tag_name: String,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    body: Option<String>,
+    #[serde(default)]
+    published_at: Option<String>,
+    #[serde(default)]
+    draft: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitHubTree {
+    #[serde(default)]
+    tree: Vec<GitHubTreeEntry>,
+    #[serde(default)]
+    truncated: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitHubTreeEntry {
+    path: String,
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+#[derive(Debug, Serialize)]
+struct DocumentResponse {
+    doc_id: String,
+    repo_id: String,
+    file_path: String,
+    line_start: usize,
+    line_end: usize,
+    content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    doc_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    license_spdx: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    commit_sha: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blob_sha: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    committed_at: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    embedding_model: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    symbol_names: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    also_in: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct RepoFilesResponse {
+    repo_id: String,
+    source: String,
+    files: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitHubOwner {
+    login: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -68,9 +305,49 @@ struct GitHubRepoState {
 #[derive(Debug, Serialize, Deserialize)]
 struct RepoRequest {
     repo_url: String,
+    #[serde(default)]
+    branch: Option<String>,
+    #[serde(default)]
+    commit: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
+struct IndexRepoRequest {
+    #[serde(default)]
+    branch: Option<String>,
+    #[serde(default)]
+    commit: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitHubPushEvent {
+    #[serde(rename = "ref", default)]
+    git_ref: String,
+    #[serde(default)]
+    deleted: bool,
+    repository: GitHubPushRepository,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitHubPushRepository {
+    full_name: String,
+    #[serde(default)]
+    default_branch: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RepoPatchRequest {
+    tags: Option<Vec<String>>,
+    include_generated: Option<bool>,
+    embedding_model: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListReposQuery {
+    tag: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct RepoResponse {
     id: String,
     repo_url: String,
@@ -79,10 +356,44 @@ struct RepoResponse {
     path: String,
 }
 
+#[derive(Debug, Serialize)]
+struct HealthResponse {
+    vespa: String,
+    github: String,
+}
+
+#[derive(Debug, Serialize)]
+struct RepoDeleteResponse {
+    repo_id: String,
+    documents_removed: u64,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct StatusResponse {
     status: String,
     message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    progress: Option<IngestProgress>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    summary_error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct IngestProgress {
+    phase: String,
+    processed: usize,
+    total: usize,
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct EventsQuery {
+    since: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StatusQuery {
+    wait: Option<u64>,
+    current: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -90,6 +401,8 @@ struct IngestEvent {
     repo_id: String,
     status: String,
     message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    progress: Option<IngestProgress>,
     timestamp: i64,
 }
 
@@ -97,21 +410,134 @@ struct IngestEvent {
 struct SearchRequest {
     query: String,
     repo_filter: Option<String>,
+    repo_tags: Option<Vec<String>>,
+    doc_types: Option<Vec<String>>,
     search_mode: Option<String>,
+    include_embedding_model: Option<bool>,
+    snippet_mode: Option<String>,
+    snippet_lines: Option<usize>,
+    query_embedding: Option<Vec<f32>>,
+    explain: Option<bool>,
+    commit_sha: Option<String>,
+    rich: Option<bool>,
+    recency_half_life_days: Option<f64>,
+    summary_class: Option<String>,
+    min_score: Option<f64>,
+    hits: Option<usize>,
+    offset: Option<usize>,
+    language: Option<String>,
+    languages: Option<Vec<String>>,
+    return_rank_features: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct SearchResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    doc_id: Option<String>,
     repo_id: String,
     file_path: String,
     line_start: usize,
     line_end: usize,
     snippet: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    doc_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    commit_sha: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blob_sha: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    committed_at: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    embedding_model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    explanation: Option<ResultExplanation>,
+    #[serde(skip)]
+    score: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lines: Option<Vec<SnippetLine>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    highlights: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    relevance: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rank_features: Option<BTreeMap<String, f64>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    also_in: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SnippetLine {
+    number: usize,
+    text: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ResultExplanation {
+    relevance: f64,
+    features: BTreeMap<String, f64>,
+    summary: String,
+}
+
+impl ResultExplanation {
+    fn new(relevance: f64, features: BTreeMap<String, f64>) -> Self {
+        let summary = if features.is_empty() {
+            format!("relevance {relevance:.4} (rank profile exposes no match-features)")
+        } else {
+            let signals = features
+                .iter()
+                .map(|(name, value)| format!("{name} = {value:.4}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("relevance {relevance:.4} from {signals}")
+        };
+        Self {
+            relevance,
+            features,
+            summary,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchSearchRequest {
+    queries: Vec<SearchRequest>,
+}
+
+/// One query's outcome in a batch: its response, or the error it would have returned on its own.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum BatchSearchResult {
+    Ok(SearchResponse),
+    Err { error: String },
+}
+
+impl From<Result<SearchResponse, AppError>> for BatchSearchResult {
+    fn from(result: Result<SearchResponse, AppError>) -> Self {
+        match result {
+            Ok(response) => BatchSearchResult::Ok(response),
+            Err(err) => BatchSearchResult::Err {
+                error: err.to_string(),
+            },
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct SearchResponse {
     results: Vec<SearchResult>,
+    #[serde(default)]
+    total_count: u64,
+    /// False when `total_count` is only an upper bound (see `min_score_total_count`).
+    #[serde(default = "default_true")]
+    total_count_exact: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SearchPage {
+    hits: usize,
+    offset: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -120,9 +546,13 @@ struct SummaryEntry {
     created_at: i64,
     summary: String,
     long_summary: String,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    detail: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 struct SummaryStore {
     entries: Vec<SummaryEntry>,
 }
@@ -133,15 +563,281 @@ impl SummaryStore {
     }
 
     fn next_version(&self) -> u32 {
-        self.entries.last().map(|entry| entry.version + 1).unwrap_or(1)
+        self.entries
+            .last()
+            .map(|entry| entry.version + 1)
+            .unwrap_or(1)
     }
 }
 
+#[derive(Debug, Deserialize, Default)]
+struct WikiRegenerateRequest {
+    detail: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct VectorsGcReport {
+    removed_unreferenced: usize,
+    removed_for_size: usize,
+    bytes_reclaimed: u64,
+    remaining_bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct WikiInputResponse {
+    repo_id: String,
+    detail: String,
+    max_chars: usize,
+    char_count: usize,
+    section_count: usize,
+    input: String,
+}
+
+#[derive(Debug, Serialize)]
+struct FailedRepo {
+    repo_id: String,
+    owner: String,
+    name: String,
+    message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct RegistryImportResponse {
+    imported: usize,
+    skipped: usize,
+    total: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    rejected: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RetryFailedRequest {
+    repo_ids: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ReconcileRequest {
+    #[serde(default)]
+    purge_orphans: bool,
+    #[serde(default)]
+    flag_empty: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct ReconciledRepo {
+    repo_id: String,
+    owner: String,
+    name: String,
+    document_count: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct OrphanedRepo {
+    repo_id: String,
+    document_count: u64,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct ReconcileReport {
+    repos: Vec<ReconciledRepo>,
+    empty_repos: Vec<String>,
+    orphans: Vec<OrphanedRepo>,
+    purged_documents: u64,
+    flagged_repos: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct DiffIndexResponse {
+    repo_id: String,
+    added: Vec<String>,
+    modified: Vec<String>,
+    deleted: Vec<String>,
+    unchanged_count: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReembedQuery {
+    from_model: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct ReembedReport {
+    updated: usize,
+    skipped: usize,
+    failed: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct ContextRequest {
+    file_path: String,
+    line_start: usize,
+    line_end: Option<usize>,
+    before: Option<usize>,
+    after: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct ContextLine {
+    number: usize,
+    text: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ContextResponse {
+    repo_id: String,
+    file_path: String,
+    line_start: usize,
+    line_end: usize,
+    source: String,
+    lines: Vec<ContextLine>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct WikiResponse {
     summary: String,
     long_summary: String,
     history: Vec<SummaryEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    markdown: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct LanguageCount {
+    language: String,
+    document_count: usize,
+    repo_count: usize,
+}
+
+#[derive(Debug, Default)]
+struct KeyedLocks {
+    locks: std::sync::Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl KeyedLocks {
+    fn acquire(&self, key: &str) -> Arc<tokio::sync::Mutex<()>> {
+        let mut locks = self.locks.lock().unwrap_or_else(|err| err.into_inner());
+        locks.entry(key.to_string()).or_default().clone()
+    }
+
+    fn release(&self, key: &str, lock: Arc<tokio::sync::Mutex<()>>) {
+        let mut locks = self.locks.lock().unwrap_or_else(|err| err.into_inner());
+        if Arc::strong_count(&lock) <= 2 {
+            locks.remove(key);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct IdempotentRepoCreation {
+    created_at: std::time::Instant,
+    response: RepoResponse,
+}
+
+#[derive(Debug)]
+struct LanguageCache {
+    fetched_at: std::time::Instant,
+    languages: Vec<LanguageCount>,
+}
+
+#[derive(Debug)]
+struct QueryEmbeddingCache {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<(String, String), (Vec<f32>, u64)>,
+}
+
+impl QueryEmbeddingCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            tick: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    fn get(&mut self, model: &str, query: &str) -> Option<Vec<f32>> {
+        self.tick += 1;
+        let (values, used_at) = self
+            .entries
+            .get_mut(&(model.to_string(), query.to_string()))?;
+        *used_at = self.tick;
+        Some(values.clone())
+    }
+
+    fn insert(&mut self, model: &str, query: &str, values: Vec<f32>) {
+        self.tick += 1;
+        let key = (model.to_string(), query.to_string());
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, used_at))| *used_at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(key, (values, self.tick));
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+struct FeedStats {
+    indexed: usize,
+    added: usize,
+    updated: usize,
+    skipped: usize,
+    deleted: usize,
+    failed: usize,
+    skipped_generated: usize,
+    encoding_converted: usize,
+    deduplicated: usize,
+    overflowed: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    overflow_files: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    failures: Vec<FeedFailure>,
+}
+
+#[derive(Debug, Serialize)]
+struct FeedFailure {
+    file_path: String,
+    line_start: usize,
+    line_end: usize,
+    error: String,
+}
+
+struct FedChunk {
+    file_path: String,
+    line_start: usize,
+    line_end: usize,
+    content_sha: String,
+    entry: String,
+    updated: bool,
+    unchanged: bool,
+}
+
+struct ChunkJob {
+    doc_id: String,
+    chunk_id: String,
+    content: String,
+    language: String,
+    symbol_names: Vec<String>,
+    blob_sha: Option<String>,
+    committed_at: Option<i64>,
+}
+
+struct FeedContext<'a> {
+    state: &'a AppState,
+    record: &'a RepoRecord,
+    vv_path: &'a StdPath,
+    embedding_model: &'a str,
+    commit_sha: Option<String>,
+    branch: String,
+    embed_permits: Semaphore,
+    feed_permits: Semaphore,
+    keep_embeddings: bool,
+    license_spdx: &'a str,
 }
 
 #[derive(Debug, Serialize)]
@@ -151,13 +847,17 @@ struct VespaPut {
 
 #[derive(Debug, Serialize)]
 struct VespaFields {
+    doc_type: String,
     repo_id: String,
     repo_url: String,
     repo_name: String,
     repo_owner: String,
     commit_sha: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blob_sha: Option<String>,
     branch: String,
     file_path: String,
+    path_tokens: String,
     language: String,
     license_spdx: String,
     chunk_id: String,
@@ -168,25 +868,149 @@ struct VespaFields {
     content: String,
     content_sha: String,
     embedding: VespaEmbedding,
+    embedding_model: String,
     last_indexed_at: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    committed_at: Option<i64>,
 }
 
 #[derive(Debug, Serialize)]
 struct VespaEmbedding {
-    values: Vec<f32>,
+    values: VespaTensorValues,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum VespaTensorValues {
+    Floats(Vec<f32>),
+    Hex(String),
+}
+
+impl VespaEmbedding {
+    fn new(values: Vec<f32>, encoding: VespaTensorEncoding) -> Self {
+        let values = match encoding {
+            VespaTensorEncoding::Json => VespaTensorValues::Floats(values),
+            VespaTensorEncoding::Hex => VespaTensorValues::Hex(
+                values
+                    .iter()
+                    .map(|value| format!("{:08X}", value.to_bits()))
+                    .collect(),
+            ),
+        };
+        Self { values }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct LocalDocument {
+    doc_type: String,
+    repo_id: String,
+    file_path: String,
+    line_start: usize,
+    line_end: usize,
+    content: String,
+    commit_sha: Option<String>,
+    blob_sha: Option<String>,
+    committed_at: Option<i64>,
+    embedding: Option<Vec<f32>>,
+    embedding_model: String,
+    term_count: usize,
+    also_in: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+struct LocalIndex {
+    documents: HashMap<String, LocalDocument>,
+    postings: HashMap<String, HashMap<String, u32>>,
+}
+
+impl LocalIndex {
+    fn upsert(&mut self, doc_id: String, mut document: LocalDocument) {
+        self.remove(&doc_id);
+        let mut term_frequencies: HashMap<String, u32> = HashMap::new();
+        for term in tokenize_for_local_index(&document.content) {
+            *term_frequencies.entry(term).or_insert(0) += 1;
+        }
+        document.term_count = term_frequencies.values().map(|count| *count as usize).sum();
+        for (term, count) in term_frequencies {
+            self.postings
+                .entry(term)
+                .or_default()
+                .insert(doc_id.clone(), count);
+        }
+        self.documents.insert(doc_id, document);
+    }
+
+    fn remove(&mut self, doc_id: &str) {
+        if self.documents.remove(doc_id).is_none() {
+            return;
+        }
+        self.postings.retain(|_, docs| {
+            docs.remove(doc_id);
+            !docs.is_empty()
+        });
+    }
+
+    fn contains_repo(&self, repo_id: &str) -> bool {
+        self.documents
+            .values()
+            .any(|document| document.repo_id == repo_id)
+    }
+
+    fn bm25_scores(&self, query: &str) -> HashMap<String, f64> {
+        const K1: f64 = 1.2;
+        const B: f64 = 0.75;
+        let total_docs = self.documents.len() as f64;
+        if total_docs == 0.0 {
+            return HashMap::new();
+        }
+        let average_length = self
+            .documents
+            .values()
+            .map(|document| document.term_count as f64)
+            .sum::<f64>()
+            / total_docs;
+        let mut scores: HashMap<String, f64> = HashMap::new();
+        for term in tokenize_for_local_index(query) {
+            let Some(docs) = self.postings.get(&term) else {
+                continue;
+            };
+            let doc_frequency = docs.len() as f64;
+            let idf = (1.0 + (total_docs - doc_frequency + 0.5) / (doc_frequency + 0.5)).ln();
+            for (doc_id, frequency) in docs {
+                let length = self
+                    .documents
+                    .get(doc_id)
+                    .map(|document| document.term_count as f64)
+                    .unwrap_or(average_length);
+                let frequency = *frequency as f64;
+                let score = idf * (frequency * (K1 + 1.0))
+                    / (frequency + K1 * (1.0 - B + B * length / average_length.max(1.0)));
+                *scores.entry(doc_id.clone()).or_insert(0.0) += score;
+            }
+        }
+        scores
+    }
 }
 
 #[derive(Clone)]
 struct AppState {
     registry_path: PathBuf,
     repos_path: PathBuf,
+    repo_path_layout: RepoPathLayout,
     registry: Arc<RwLock<Vec<RepoRecord>>>,
     status_tx: broadcast::Sender<IngestEvent>,
+    event_history: Arc<RwLock<HashMap<String, VecDeque<IngestEvent>>>>,
+    language_cache: Arc<RwLock<Option<LanguageCache>>>,
+    idempotency_keys: Arc<Mutex<HashMap<String, IdempotentRepoCreation>>>,
     github_org: Option<String>,
     github_token: Option<String>,
+    github_webhook_secrets: Vec<String>,
     huggingface_token: Option<String>,
     huggingface_model: String,
     huggingface_max_chars: usize,
+    embedding_max_tokens: Option<usize>,
+    embedding_tokenizer: Option<Arc<tokenizers::Tokenizer>>,
     huggingface_base_url: String,
     huggingface_max_retries: usize,
     huggingface_backoff_ms: u64,
@@ -194,7 +1018,39 @@ struct AppState {
     huggingface_summary_model: String,
     huggingface_summary_max_chars: usize,
     huggingface_summary_top_files: usize,
+    summary_concurrency: usize,
+    index_commits: bool,
+    index_releases: bool,
+    release_index_limit: usize,
+    commit_index_limit: usize,
+    git_bare_clone: bool,
+    min_free_disk_bytes: u64,
     summary_provider: SummaryProvider,
+    summary_failure_mode: SummaryFailureMode,
+    wiki_markdown_mode: WikiMarkdownMode,
+    vespa_tensor_encoding: VespaTensorEncoding,
+    embedding_hash_mode: EmbeddingHashMode,
+    allow_archive_ingest: bool,
+    sync_all_org_repos: bool,
+    verify_feed: bool,
+    index_commit_history: bool,
+    debug_dump_embeddings: bool,
+    max_content_bytes: u64,
+    detect_encoding: bool,
+    normalize_embeddings: bool,
+    dedup_chunks: bool,
+    max_query_chars: usize,
+    max_query_terms: usize,
+    chunk_max_lines: usize,
+    chunk_overlap_lines: usize,
+    chunk_lines_by_language: HashMap<String, ChunkSize>,
+    max_chunks_per_file: usize,
+    max_chunks_overflow: ChunkOverflowPolicy,
+    vespa_feed_concurrency: usize,
+    embed_concurrency: usize,
+    vespa_summary_classes: Vec<String>,
+    vectors_cache_max_bytes: Option<u64>,
+    archive_max_bytes: u64,
     colab_summary_url: Option<String>,
     colab_summary_token: Option<String>,
     colab_summary_auth_header: String,
@@ -203,8 +1059,25 @@ struct AppState {
     vespa_cluster: String,
     vespa_namespace: String,
     vespa_document_type: String,
+    vespa_search_path: String,
+    vespa_query_profile: Option<String>,
+    default_search_mode: SearchMode,
+    search_backend: SearchBackend,
+    local_index: Arc<RwLock<LocalIndex>>,
+    detected_embedding_dim: Arc<AtomicUsize>,
+    embedding_locks: Arc<KeyedLocks>,
+    embedding_semaphore: Arc<Semaphore>,
+    query_embedding_cache: Arc<Mutex<QueryEmbeddingCache>>,
+    max_concurrent_ingests: usize,
+    ingest_semaphore: Arc<Semaphore>,
+    active_ingests: Arc<Mutex<HashSet<String>>>,
+    shutting_down: Arc<AtomicBool>,
+    metrics: PrometheusHandle,
+    embedding_max_concurrency: usize,
     http_client: reqwest::Client,
     hf_client: reqwest::Client,
+    embedding_provider: Arc<dyn EmbeddingProvider>,
+    index_ignore_patterns: Vec<String>,
 }
 
 #[derive(Error, Debug)]
@@ -213,64 +1086,184 @@ enum AppError {
     InvalidRepoUrl,
     #[error("repo not found")]
     RepoNotFound,
+    #[error("document not found: {0}")]
+    DocumentNotFound(String),
+    #[error("file not found: {0}")]
+    FileNotFound(String),
+    #[error("invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("conflict: {0}")]
+    Conflict(String),
+    #[error("missing or invalid API key")]
+    Unauthorized,
+    #[error("missing or invalid webhook signature")]
+    InvalidSignature,
+    #[error("server is shutting down")]
+    ShuttingDown,
+    #[error("too many ingests in progress (MAX_CONCURRENT_INGESTS={0}); retry later")]
+    TooManyIngests(usize),
+    #[error("request body too large (limit {0} bytes)")]
+    PayloadTooLarge(usize),
     #[error("config error: {0}")]
     Config(String),
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
     #[error("serde error: {0}")]
     Serde(#[from] serde_json::Error),
-    #[error("vespa request error: {0}")]
+    #[error("vespa request error: {}", describe_vespa_error(.0))]
     VespaRequest(#[from] reqwest::Error),
     #[error("vespa rejected request: {0}")]
     VespaRejected(String),
     #[error("github error: {0}")]
     GitHub(String),
+    #[error("github rate limit exceeded{}", describe_rate_limit_reset(*.0))]
+    GitHubRateLimited(Option<i64>),
     #[error("huggingface error: {0}")]
     HuggingFace(String),
+    #[error("openai error: {0}")]
+    OpenAi(String),
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> axum::response::Response {
         let status = match self {
-            AppError::InvalidRepoUrl => StatusCode::BAD_REQUEST,
-            AppError::RepoNotFound => StatusCode::NOT_FOUND,
+            AppError::InvalidRepoUrl | AppError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::RepoNotFound | AppError::DocumentNotFound(_) | AppError::FileNotFound(_) => {
+                StatusCode::NOT_FOUND
+            }
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::Unauthorized | AppError::InvalidSignature => StatusCode::UNAUTHORIZED,
+            AppError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::GitHubRateLimited(_) | AppError::TooManyIngests(_) => {
+                StatusCode::TOO_MANY_REQUESTS
+            }
             AppError::Config(_) | AppError::Io(_) | AppError::Serde(_) => {
                 StatusCode::INTERNAL_SERVER_ERROR
             }
+            AppError::ShuttingDown => StatusCode::SERVICE_UNAVAILABLE,
             AppError::VespaRequest(_)
             | AppError::VespaRejected(_)
             | AppError::GitHub(_)
-            | AppError::HuggingFace(_) => {
-                StatusCode::BAD_GATEWAY
-            }
+            | AppError::HuggingFace(_)
+            | AppError::OpenAi(_) => StatusCode::BAD_GATEWAY,
         };
         let body = Json(serde_json::json!({"error": self.to_string()}));
         (status, body).into_response()
     }
 }
 
-fn normalize_pem(value: &str) -> String {
-    value.replace("\\n", "\n")
+async fn require_api_key(
+    State(api_key): State<Arc<str>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    // The search page itself holds no data; it sends the key it is given on its API calls.
+    if matches!(request.uri().path(), "/" | "/healthz" | "/webhooks/github") {
+        return Ok(next.run(request).await);
+    }
+    let provided = request
+        .headers()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .unwrap_or_default();
+    if !constant_time_eq(provided.as_bytes(), api_key.as_bytes()) {
+        return Err(AppError::Unauthorized);
+    }
+    Ok(next.run(request).await)
 }
 
-fn read_pem_from_path(path: &PathBuf, label: &str) -> Result<String, AppError> {
-    std::fs::read_to_string(path).map_err(|err| {
-        AppError::Config(format!(
-            "failed to read {label} at {}: {err}",
-            path.display()
-        ))
-    })
+fn constant_time_eq(left: &[u8], right: &[u8]) -> bool {
+    left.len() == right.len()
+        && left
+            .iter()
+            .zip(right)
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
 }
 
-fn load_pem_from_env_or_path(
-    value_env: &str,
-    path_env: &str,
-    default_path: Option<PathBuf>,
-    label: &str,
-) -> Result<(Option<String>, String), AppError> {
-    if let Ok(value) = std::env::var(value_env) {
-        if value.contains("-----BEGIN") {
-            return Ok((Some(value), value_env.to_string()));
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut block = [0u8; 64];
+    if key.len() > block.len() {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::new();
+    inner.update(block.map(|byte| byte ^ 0x36));
+    inner.update(message);
+    let mut outer = Sha256::new();
+    outer.update(block.map(|byte| byte ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().into()
+}
+
+/// Splits `GITHUB_WEBHOOK_SECRET` on commas so the old and new secret can both be listed while a
+/// rotation is in progress.
+fn parse_webhook_secrets(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|secret| secret.trim().to_string())
+        .filter(|secret| !secret.is_empty())
+        .collect()
+}
+
+/// Checks an `X-Hub-Signature-256` header (`sha256=<hex>`) against every configured secret and
+/// accepts the body if any of them signed it.
+fn verify_github_signature(secrets: &[String], body: &[u8], header: &str) -> bool {
+    let Some(signature) = header.strip_prefix("sha256=") else {
+        return false;
+    };
+    let signature = signature.to_ascii_lowercase();
+    secrets.iter().any(|secret| {
+        let expected = hex::encode(hmac_sha256(secret.as_bytes(), body));
+        constant_time_eq(expected.as_bytes(), signature.as_bytes())
+    })
+}
+
+fn payload_too_large_as_json(response: Response, limit: usize) -> Response {
+    if response.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        return AppError::PayloadTooLarge(limit).into_response();
+    }
+    response
+}
+
+/// Caps request bodies on `router` at `limit` bytes, answering oversized ones with a JSON 413.
+fn limit_request_body<S>(router: Router<S>, limit: usize) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router
+        .layer(DefaultBodyLimit::max(limit))
+        .layer(RequestBodyLimitLayer::new(limit))
+        .layer(middleware::map_response(
+            move |response: Response| async move { payload_too_large_as_json(response, limit) },
+        ))
+}
+
+fn normalize_pem(value: &str) -> String {
+    value.replace("\\n", "\n")
+}
+
+fn read_pem_from_path(path: &PathBuf, label: &str) -> Result<String, AppError> {
+    std::fs::read_to_string(path).map_err(|err| {
+        AppError::Config(format!(
+            "failed to read {label} at {}: {err}",
+            path.display()
+        ))
+    })
+}
+
+fn load_pem_from_env_or_path(
+    value_env: &str,
+    path_env: &str,
+    default_path: Option<PathBuf>,
+    label: &str,
+) -> Result<(Option<String>, String), AppError> {
+    if let Ok(value) = std::env::var(value_env) {
+        if value.contains("-----BEGIN") {
+            return Ok((Some(value), value_env.to_string()));
         }
         let path = PathBuf::from(value);
         return Ok((
@@ -319,8 +1312,22 @@ fn build_http_client() -> Result<reqwest::Client, AppError> {
         "Vespa client key",
     )?;
 
-    let mut builder = reqwest::Client::builder();
+    let connect_timeout = std::env::var("VESPA_CONNECT_TIMEOUT_SECS")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_VESPA_CONNECT_TIMEOUT_SECS);
+    let request_timeout = std::env::var("VESPA_REQUEST_TIMEOUT_SECS")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_VESPA_REQUEST_TIMEOUT_SECS);
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(connect_timeout))
+        .timeout(Duration::from_secs(request_timeout));
 
+    info!(
+        "vespa timeouts: connect={}s, request={}s",
+        connect_timeout, request_timeout
+    );
     info!(
         "vespa tls sources: ca={}, cert={}, key={}",
         ca_source, cert_source, key_source
@@ -360,29 +1367,85 @@ fn build_hf_client() -> Result<reqwest::Client, AppError> {
 
 #[tokio::main]
 async fn main() -> Result<(), AppError> {
-    tracing_subscriber::fmt().with_env_filter("info").init();
-
-    let base_path = std::env::current_dir()?;
-    let data_root = std::env::var("DATA_DIR")
-        .map(PathBuf::from)
-        .unwrap_or_else(|_| {
-            let default_data = PathBuf::from("/data");
-            if default_data.is_dir() {
-                default_data
-            } else {
-                base_path.clone()
-            }
-        });
+    let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let log_format = std::env::var("LOG_FORMAT").unwrap_or_else(|_| "text".to_string());
+    match log_format.trim().to_lowercase().as_str() {
+        "json" => tracing_subscriber::fmt()
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .with_env_filter(env_filter)
+            .init(),
+        "text" | "" => tracing_subscriber::fmt().with_env_filter(env_filter).init(),
+        other => {
+            return Err(AppError::Config(format!(
+                "invalid LOG_FORMAT {other:?} (expected \"text\" or \"json\")"
+            )))
+        }
+    }
+
+    let prefer_data_volume = std::env::var("PREFER_DATA_VOLUME")
+        .map(|value| matches!(value.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false);
+    let (data_root, data_root_source) = match std::env::var("DATA_DIR") {
+        Ok(value) if !value.trim().is_empty() => (PathBuf::from(value.trim()), "DATA_DIR"),
+        _ if prefer_data_volume => (PathBuf::from("/data"), "PREFER_DATA_VOLUME"),
+        _ => (std::env::current_dir()?, "current directory"),
+    };
+    info!(
+        "data root: {} (from {})",
+        data_root.display(),
+        data_root_source
+    );
     let registry_path = data_root.join("data/registry.json");
     let repos_path = data_root.join("repos");
-    let vespa_endpoint = std::env::var("VESPA_ENDPOINT").unwrap_or_default();
-    let vespa_document_endpoint =
-        std::env::var("VESPA_DOCUMENT_ENDPOINT").unwrap_or_else(|_| vespa_endpoint.clone());
-    let vespa_cluster =
-        std::env::var("VESPA_CLUSTER").unwrap_or_else(|_| "codesearch".into());
-    let vespa_namespace = std::env::var("VESPA_NAMESPACE").unwrap_or_else(|_| "codesearch".into());
-    let vespa_document_type =
-        std::env::var("VESPA_DOCUMENT_TYPE").unwrap_or_else(|_| "codesearch".into());
+    prepare_data_root(&data_root, &registry_path, &repos_path).await?;
+    let repo_path_layout = match std::env::var("REPO_PATH_LAYOUT") {
+        Ok(value) if !value.trim().is_empty() => {
+            RepoPathLayout::parse(&value).ok_or_else(|| {
+                AppError::Config(format!(
+                    "REPO_PATH_LAYOUT must be one of owner-name, flat, id (got {value:?})"
+                ))
+            })?
+        }
+        _ => RepoPathLayout::OwnerName,
+    };
+    let vespa_endpoint = validate_vespa_endpoint(
+        "VESPA_ENDPOINT",
+        &std::env::var("VESPA_ENDPOINT").unwrap_or_default(),
+    )?;
+    let vespa_document_endpoint = match std::env::var("VESPA_DOCUMENT_ENDPOINT") {
+        Ok(value) => validate_vespa_endpoint("VESPA_DOCUMENT_ENDPOINT", &value)?,
+        Err(_) => vespa_endpoint.clone(),
+    };
+    let vespa_cluster = validate_vespa_identifier(
+        "VESPA_CLUSTER",
+        &std::env::var("VESPA_CLUSTER").unwrap_or_else(|_| "codesearch".into()),
+    )?;
+    let vespa_namespace = validate_vespa_identifier(
+        "VESPA_NAMESPACE",
+        &std::env::var("VESPA_NAMESPACE").unwrap_or_else(|_| "codesearch".into()),
+    )?;
+    let vespa_document_type = validate_vespa_identifier(
+        "VESPA_DOCUMENT_TYPE",
+        &std::env::var("VESPA_DOCUMENT_TYPE").unwrap_or_else(|_| "codesearch".into()),
+    )?;
+    let vespa_search_path = match std::env::var("VESPA_SEARCH_PATH") {
+        Ok(value) if !value.trim().is_empty() => {
+            let value = value.trim().trim_matches('/');
+            if value.contains(|ch: char| ch.is_whitespace() || ch == '?' || ch == '#') {
+                return Err(AppError::Config(format!(
+                    "VESPA_SEARCH_PATH must be a plain URL path (got {value:?})"
+                )));
+            }
+            format!("/{value}/")
+        }
+        _ => "/search/".to_string(),
+    };
+    let vespa_query_profile = std::env::var("VESPA_QUERY_PROFILE")
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty());
     let github_org = std::env::var("GITHUB_ORG").ok();
     let github_token = std::env::var("GITHUB_TOKEN").ok();
     let huggingface_token = std::env::var("HUGGINGFACE_TOKEN")
@@ -408,8 +1471,8 @@ async fn main() -> Result<(), AppError> {
         .ok()
         .and_then(|value| value.parse::<u64>().ok())
         .unwrap_or(HF_DEFAULT_BACKOFF_MAX_MS);
-    let huggingface_summary_model =
-        std::env::var("HUGGINGFACE_SUMMARY_MODEL").unwrap_or_else(|_| HF_DEFAULT_SUMMARY_MODEL.into());
+    let huggingface_summary_model = std::env::var("HUGGINGFACE_SUMMARY_MODEL")
+        .unwrap_or_else(|_| HF_DEFAULT_SUMMARY_MODEL.into());
     let huggingface_summary_max_chars = std::env::var("HUGGINGFACE_SUMMARY_MAX_CHARS")
         .ok()
         .and_then(|value| value.parse::<usize>().ok())
@@ -418,29 +1481,356 @@ async fn main() -> Result<(), AppError> {
         .ok()
         .and_then(|value| value.parse::<usize>().ok())
         .unwrap_or(HF_DEFAULT_SUMMARY_TOP_FILES);
+    let embedding_max_concurrency = std::env::var("EMBEDDING_MAX_CONCURRENCY")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_EMBEDDING_MAX_CONCURRENCY)
+        .max(1);
+    let metrics = PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Full("search_duration_seconds".into()),
+            &SEARCH_LATENCY_BUCKETS,
+        )
+        .and_then(PrometheusBuilder::install_recorder)
+        .map_err(|err| AppError::Config(format!("failed to install metrics recorder: {err}")))?;
+    let upkeep_handle = metrics.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(METRICS_UPKEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            upkeep_handle.run_upkeep();
+        }
+    });
+    let max_concurrent_ingests = std::env::var("MAX_CONCURRENT_INGESTS")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(DEFAULT_MAX_CONCURRENT_INGESTS);
+    let query_embed_cache_size = std::env::var("QUERY_EMBED_CACHE_SIZE")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(DEFAULT_QUERY_EMBED_CACHE_SIZE);
+    let summary_concurrency = std::env::var("SUMMARY_CONCURRENCY")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(HF_DEFAULT_SUMMARY_CONCURRENCY)
+        .max(1);
+    let index_commits = std::env::var("INDEX_COMMITS")
+        .map(|value| matches!(value.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false);
+    let commit_index_limit = std::env::var("COMMIT_INDEX_LIMIT")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_COMMIT_INDEX_LIMIT);
+    let index_releases = std::env::var("INDEX_RELEASES")
+        .map(|value| matches!(value.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false);
+    let release_index_limit = std::env::var("RELEASE_INDEX_LIMIT")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_RELEASE_INDEX_LIMIT)
+        .clamp(1, MAX_RELEASE_INDEX_LIMIT);
+    let git_bare_clone = std::env::var("GIT_BARE_CLONE")
+        .map(|value| matches!(value.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false);
+    let min_free_disk_bytes = std::env::var("MIN_FREE_DISK_BYTES")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_MIN_FREE_DISK_BYTES);
+    let allow_archive_ingest = std::env::var("ALLOW_ARCHIVE_INGEST")
+        .map(|value| matches!(value.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false);
+    let max_content_bytes = std::env::var("MAX_CONTENT_BYTES")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_MAX_CONTENT_BYTES);
+    let vespa_summary_classes: Vec<String> = std::env::var("VESPA_SUMMARY_CLASSES")
+        .unwrap_or_else(|_| DEFAULT_VESPA_SUMMARY_CLASSES.to_string())
+        .split(',')
+        .map(|class| class.trim().to_string())
+        .filter(|class| !class.is_empty())
+        .collect();
+    let detect_encoding = std::env::var("DETECT_ENCODING")
+        .map(|value| matches!(value.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false);
+    let normalize_embeddings = std::env::var("NORMALIZE_EMBEDDINGS")
+        .map(|value| matches!(value.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(true);
+    let dedup_chunks = std::env::var("DEDUP_CHUNKS")
+        .map(|value| matches!(value.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false);
+    let max_query_chars = std::env::var("MAX_QUERY_CHARS")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(DEFAULT_MAX_QUERY_CHARS);
+    let max_query_terms = std::env::var("MAX_QUERY_TERMS")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(DEFAULT_MAX_QUERY_TERMS);
+    let chunk_max_lines = std::env::var("CHUNK_MAX_LINES")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(DEFAULT_CHUNK_MAX_LINES);
+    let chunk_overlap_lines = std::env::var("CHUNK_OVERLAP_LINES")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_CHUNK_OVERLAP_LINES)
+        .min(chunk_max_lines - 1);
+    let chunk_lines_by_language = match std::env::var("CHUNK_LINES_BY_LANGUAGE") {
+        Ok(value) => parse_chunk_lines_by_language(&value).map_err(|entry| {
+            AppError::Config(format!(
+                "CHUNK_LINES_BY_LANGUAGE entries must be language=lines, language=file or markdown=headings (got {entry:?})"
+            ))
+        })?,
+        Err(_) => HashMap::new(),
+    };
+    let max_chunks_per_file = std::env::var("MAX_CHUNKS_PER_FILE")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(DEFAULT_MAX_CHUNKS_PER_FILE);
+    let max_chunks_overflow = match std::env::var("MAX_CHUNKS_OVERFLOW") {
+        Ok(value) if !value.trim().is_empty() => {
+            parse_chunk_overflow_policy(&value).ok_or_else(|| {
+                AppError::Config(format!(
+                    "MAX_CHUNKS_OVERFLOW must be one of truncate, skip (got {value:?})"
+                ))
+            })?
+        }
+        _ => ChunkOverflowPolicy::Truncate,
+    };
+    let vespa_feed_concurrency = std::env::var("VESPA_FEED_CONCURRENCY")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(DEFAULT_VESPA_FEED_CONCURRENCY);
+    let embed_concurrency = std::env::var("EMBED_CONCURRENCY")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(DEFAULT_EMBED_CONCURRENCY);
+    let vectors_cache_max_bytes = std::env::var("VECTORS_CACHE_MAX_BYTES")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .filter(|value| *value > 0);
+    let verify_feed = std::env::var("VERIFY_FEED")
+        .map(|value| matches!(value.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false);
+    let index_commit_history = std::env::var("INDEX_COMMIT_HISTORY")
+        .map(|value| matches!(value.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false);
+    let debug_dump_embeddings = std::env::var("DEBUG_DUMP_EMBEDDINGS")
+        .map(|value| matches!(value.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false);
+    let sync_all_org_repos = std::env::var("SYNC_ALL_ORG_REPOS")
+        .map(|value| matches!(value.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false);
+    let archive_max_bytes = std::env::var("ARCHIVE_MAX_BYTES")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_ARCHIVE_MAX_BYTES);
     let summary_provider =
         resolve_summary_provider(std::env::var("SUMMARY_PROVIDER").ok().as_deref());
+    let summary_failure_mode = match std::env::var("SUMMARY_FAILURE_MODE") {
+        Ok(value) if !value.trim().is_empty() => {
+            parse_summary_failure_mode(&value).ok_or_else(|| {
+                AppError::Config(format!(
+                    "SUMMARY_FAILURE_MODE must be one of ignore, warn_status, fail (got {value:?})"
+                ))
+            })?
+        }
+        _ => SummaryFailureMode::Ignore,
+    };
+    let wiki_markdown_mode = match std::env::var("WIKI_MARKDOWN_MODE") {
+        Ok(value) if !value.trim().is_empty() => {
+            parse_wiki_markdown_mode(&value).ok_or_else(|| {
+                AppError::Config(format!(
+                    "WIKI_MARKDOWN_MODE must be one of overwrite, preserve, append (got {value:?})"
+                ))
+            })?
+        }
+        _ => WikiMarkdownMode::Overwrite,
+    };
+    let vespa_tensor_encoding = match std::env::var("VESPA_TENSOR_ENCODING") {
+        Ok(value) if !value.trim().is_empty() => {
+            parse_vespa_tensor_encoding(&value).ok_or_else(|| {
+                AppError::Config(format!(
+                    "VESPA_TENSOR_ENCODING must be one of json, hex (got {value:?})"
+                ))
+            })?
+        }
+        _ => VespaTensorEncoding::Json,
+    };
+    let embedding_hash_mode = match std::env::var("EMBEDDING_HASH_MODE") {
+        Ok(value) if !value.trim().is_empty() => {
+            parse_embedding_hash_mode(&value).ok_or_else(|| {
+                AppError::Config(format!(
+                    "EMBEDDING_HASH_MODE must be one of raw, normalized (got {value:?})"
+                ))
+            })?
+        }
+        _ => EmbeddingHashMode::Raw,
+    };
     let colab_summary_url = std::env::var("COLAB_SUMMARY_URL").ok();
     let colab_summary_token = std::env::var("COLAB_SUMMARY_TOKEN").ok();
-    let colab_summary_auth_header = std::env::var("COLAB_SUMMARY_AUTH_HEADER")
-        .unwrap_or_else(|_| "Authorization".into());
-
-    fs::create_dir_all(registry_path.parent().unwrap()).await?;
-    fs::create_dir_all(&repos_path).await?;
+    let colab_summary_auth_header =
+        std::env::var("COLAB_SUMMARY_AUTH_HEADER").unwrap_or_else(|_| "Authorization".into());
+    let default_search_mode = match std::env::var("DEFAULT_SEARCH_MODE") {
+        Ok(value) if !value.trim().is_empty() => parse_search_mode(&value).ok_or_else(|| {
+            AppError::Config(format!(
+                "DEFAULT_SEARCH_MODE must be one of hybrid, semantic, bm25, path (got {value:?})"
+            ))
+        })?,
+        _ => SearchMode::Bm25,
+    };
+    info!("default search mode: {}", default_search_mode.name());
+    let search_backend = match std::env::var("SEARCH_BACKEND") {
+        Ok(value) if !value.trim().is_empty() => parse_search_backend(&value).ok_or_else(|| {
+            AppError::Config(format!(
+                "SEARCH_BACKEND must be one of vespa, local (got {value:?})"
+            ))
+        })?,
+        _ => SearchBackend::Vespa,
+    };
+    let embedding_max_tokens = std::env::var("EMBEDDING_MAX_TOKENS")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|value| *value > 0);
+    let http_client = build_http_client()?;
+    let hf_client = build_hf_client()?;
+    let embedding_provider_kind =
+        parse_embedding_provider(std::env::var("EMBEDDING_PROVIDER").ok().as_deref())?;
+    let embedding_tokenizer = match embedding_max_tokens {
+        Some(_) if !matches!(embedding_provider_kind, EmbeddingProviderKind::HuggingFace) => {
+            warn!("EMBEDDING_MAX_TOKENS only applies to the huggingface embedding provider; ignoring it");
+            None
+        }
+        Some(max_tokens) => match load_embedding_tokenizer(
+            &hf_client,
+            &huggingface_model,
+            huggingface_token.as_deref(),
+        )
+        .await
+        {
+            Ok(tokenizer) => {
+                info!(
+                    "embedding inputs truncated to {} tokens using the {} tokenizer",
+                    max_tokens, huggingface_model
+                );
+                Some(Arc::new(tokenizer))
+            }
+            Err(err) => {
+                warn!(
+                    "failed to load tokenizer for {} ({}); falling back to {} char truncation",
+                    huggingface_model, err, huggingface_max_chars
+                );
+                None
+            }
+        },
+        None => None,
+    };
+    let embedding_retry = EmbeddingRetryPolicy {
+        max_retries: huggingface_max_retries,
+        backoff_ms: huggingface_backoff_ms,
+        backoff_max_ms: huggingface_backoff_max_ms,
+    };
+    let embedding_provider: Arc<dyn EmbeddingProvider> = match embedding_provider_kind {
+        EmbeddingProviderKind::HuggingFace => Arc::new(HuggingFaceProvider {
+            client: hf_client.clone(),
+            base_url: huggingface_base_url.clone(),
+            token: huggingface_token.clone(),
+            model: huggingface_model.clone(),
+            dimension: std::env::var("HUGGINGFACE_EMBEDDING_DIMENSIONS")
+                .ok()
+                .and_then(|value| value.parse::<usize>().ok())
+                .filter(|value| *value > 0)
+                .unwrap_or(EMBEDDING_DIM),
+            retry: embedding_retry,
+        }),
+        EmbeddingProviderKind::OpenAi => {
+            let api_key = std::env::var("OPENAI_API_KEY")
+                .ok()
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+                .ok_or_else(|| {
+                    AppError::Config(
+                        "OPENAI_API_KEY must be set when EMBEDDING_PROVIDER=openai".into(),
+                    )
+                })?;
+            Arc::new(OpenAiProvider {
+                client: hf_client.clone(),
+                base_url: std::env::var("OPENAI_BASE_URL")
+                    .unwrap_or_else(|_| OPENAI_DEFAULT_BASE_URL.into()),
+                api_key,
+                model: std::env::var("OPENAI_EMBEDDING_MODEL")
+                    .unwrap_or_else(|_| OPENAI_DEFAULT_EMBEDDING_MODEL.into()),
+                dimension: std::env::var("OPENAI_EMBEDDING_DIMENSIONS")
+                    .ok()
+                    .and_then(|value| value.parse::<usize>().ok())
+                    .filter(|value| *value > 0)
+                    .unwrap_or(EMBEDDING_DIM),
+                retry: embedding_retry,
+            })
+        }
+    };
+    info!(
+        "embedding provider: {} (model {}, {} dimensions)",
+        embedding_provider.name(),
+        embedding_provider.default_model(),
+        embedding_provider.dimension()
+    );
+    let index_ignore_patterns: Vec<String> = std::env::var("INDEX_IGNORE")
+        .unwrap_or_default()
+        .split(',')
+        .map(|pattern| pattern.trim().to_string())
+        .filter(|pattern| !pattern.is_empty())
+        .collect();
+    let max_request_body_bytes = std::env::var("MAX_REQUEST_BODY_BYTES")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_MAX_REQUEST_BODY_BYTES);
+    let max_bulk_request_body_bytes = std::env::var("MAX_BULK_REQUEST_BODY_BYTES")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_MAX_BULK_REQUEST_BODY_BYTES);
 
-    let registry = load_registry(&registry_path).await.unwrap_or_default();
+    let api_key: Option<Arc<str>> = std::env::var("API_KEY")
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .map(Arc::from);
+    let github_webhook_secrets =
+        parse_webhook_secrets(&std::env::var("GITHUB_WEBHOOK_SECRET").unwrap_or_default());
+    let serve_ui = std::env::var("SERVE_UI")
+        .map(|value| matches!(value.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(true);
+
+    let registry = load_registry(&registry_path, &repos_path, repo_path_layout)
+        .await
+        .unwrap_or_default();
     let (status_tx, _status_rx) = broadcast::channel(200);
 
     let state = AppState {
         registry_path,
         repos_path,
+        repo_path_layout,
         registry: Arc::new(RwLock::new(registry)),
         status_tx,
+        event_history: Arc::new(RwLock::new(HashMap::new())),
+        language_cache: Arc::new(RwLock::new(None)),
+        idempotency_keys: Arc::new(Mutex::new(HashMap::new())),
         github_org,
         github_token,
+        github_webhook_secrets,
         huggingface_token,
         huggingface_model,
         huggingface_max_chars,
+        embedding_max_tokens,
+        embedding_tokenizer,
         huggingface_base_url,
         huggingface_max_retries,
         huggingface_backoff_ms,
@@ -448,7 +1838,39 @@ async fn main() -> Result<(), AppError> {
         huggingface_summary_model,
         huggingface_summary_max_chars,
         huggingface_summary_top_files,
+        summary_concurrency,
+        index_commits,
+        index_releases,
+        release_index_limit,
+        commit_index_limit,
+        git_bare_clone,
+        min_free_disk_bytes,
         summary_provider,
+        summary_failure_mode,
+        wiki_markdown_mode,
+        vespa_tensor_encoding,
+        embedding_hash_mode,
+        allow_archive_ingest,
+        sync_all_org_repos,
+        verify_feed,
+        index_commit_history,
+        debug_dump_embeddings,
+        max_content_bytes,
+        detect_encoding,
+        normalize_embeddings,
+        dedup_chunks,
+        max_query_chars,
+        max_query_terms,
+        chunk_max_lines,
+        chunk_overlap_lines,
+        chunk_lines_by_language,
+        max_chunks_per_file,
+        max_chunks_overflow,
+        vespa_feed_concurrency,
+        embed_concurrency,
+        vespa_summary_classes,
+        vectors_cache_max_bytes,
+        archive_max_bytes,
         colab_summary_url,
         colab_summary_token,
         colab_summary_auth_header,
@@ -457,29 +1879,109 @@ async fn main() -> Result<(), AppError> {
         vespa_cluster,
         vespa_namespace,
         vespa_document_type,
-        http_client: build_http_client()?,
-        hf_client: build_hf_client()?,
+        vespa_search_path,
+        vespa_query_profile,
+        default_search_mode,
+        search_backend,
+        local_index: Arc::new(RwLock::new(LocalIndex::default())),
+        detected_embedding_dim: Arc::new(AtomicUsize::new(0)),
+        embedding_locks: Arc::new(KeyedLocks::default()),
+        embedding_semaphore: Arc::new(Semaphore::new(embedding_max_concurrency)),
+        query_embedding_cache: Arc::new(Mutex::new(QueryEmbeddingCache::new(
+            query_embed_cache_size,
+        ))),
+        max_concurrent_ingests,
+        ingest_semaphore: Arc::new(Semaphore::new(max_concurrent_ingests)),
+        active_ingests: Arc::new(Mutex::new(HashSet::new())),
+        shutting_down: Arc::new(AtomicBool::new(false)),
+        metrics,
+        embedding_max_concurrency,
+        http_client,
+        hf_client,
+        embedding_provider,
+        index_ignore_patterns,
     };
 
     if let Err(err) = sync_registry_from_github(&state).await {
         warn!("failed to bootstrap registry from GitHub: {err}");
     }
 
-    let app = Router::new()
+    let records = state.registry.read().await.clone();
+    for record in records {
+        let vv_path = repo_dir(&state, &record).join("vv");
+        match read_status(&vv_path).await {
+            Ok(status) if is_busy_status(&status.status) => {
+                warn!(
+                    "repo {} was left {} by a previous run; marking it interrupted",
+                    record.id, status.status
+                );
+                let message = Some("Interrupted by a server restart".to_string());
+                write_status(&state, &vv_path, &record.id, "interrupted", message).await?;
+            }
+            _ => {}
+        }
+    }
+
+    if state.search_backend == SearchBackend::Local {
+        let loaded = rebuild_local_index(&state).await;
+        info!("local search backend enabled ({} documents loaded)", loaded);
+    }
+
+    let mut app = Router::new()
+        .route("/healthz", get(healthz))
+        .route("/metrics", get(metrics_endpoint))
         .route("/repos", post(create_repo).get(list_repos))
+        .route("/repos/:id", patch(update_repo).delete(delete_repo))
         .route("/repos/:id/index", post(index_repo))
+        .route("/repos/:id/mirror", post(mirror_repo))
         .route("/repos/:id/status", get(repo_status))
         .route("/repos/:id/events", get(repo_events))
         .route("/repos/:id/wiki", get(repo_wiki))
         .route("/repos/:id/wiki/summary", post(update_repo_summary))
+        .route("/repos/:id/wiki/regenerate", post(regenerate_repo_wiki))
+        .route("/repos/:id/wiki/input", get(repo_wiki_input))
+        .route("/repos/:id/context", post(repo_context))
+        .route("/repos/:id/files", get(repo_files))
+        .route("/repos/:id/diff-index", get(repo_diff_index))
+        .route("/repos/:id/reembed", post(reembed_repo))
+        .route(
+            "/repos/:id/embedding-cache/gc",
+            post(gc_repo_embedding_cache),
+        )
         .route("/search", post(search))
-        .with_state(state)
-        .layer(
-            CorsLayer::new()
-                .allow_origin(Any)
-                .allow_methods(Any)
-                .allow_headers(Any),
-        );
+        .route("/documents/:doc_id", get(get_document))
+        .route("/languages", get(list_languages))
+        .route("/admin/wiki/regenerate-all", post(regenerate_all_wikis))
+        .route("/admin/failed", get(list_failed_repos))
+        .route("/admin/failed/retry", post(retry_failed_repos))
+        .route("/admin/registry/export", get(export_registry))
+        .route("/admin/reconcile", post(reconcile_index))
+        .route("/webhooks/github", post(github_webhook));
+    if serve_ui {
+        app = app.route("/", get(search_ui));
+    }
+    let bulk_routes = Router::new()
+        .route("/search/batch", post(search_batch))
+        .route("/admin/registry/import", post(import_registry));
+    let app = limit_request_body(app, max_request_body_bytes)
+        .merge(limit_request_body(bulk_routes, max_bulk_request_body_bytes));
+    let shutdown_timeout = std::env::var("SHUTDOWN_TIMEOUT_SECS")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(Duration::from_secs(DEFAULT_SHUTDOWN_TIMEOUT_SECS));
+    let shutdown_state = state.clone();
+    let mut app = app.with_state(state);
+    if let Some(api_key) = api_key {
+        info!("API key authentication enabled");
+        app = app.layer(middleware::from_fn_with_state(api_key, require_api_key));
+    }
+    let app = app.layer(
+        CorsLayer::new()
+            .allow_origin(Any)
+            .allow_methods(Any)
+            .allow_headers(Any),
+    );
 
     let port = std::env::var("PORT")
         .ok()
@@ -488,22 +1990,184 @@ async fn main() -> Result<(), AppError> {
     let listen_address = format!("0.0.0.0:{port}");
     let listener = tokio::net::TcpListener::bind(&listen_address).await?;
     info!("backend listening on {}", listen_address);
-    axum::serve(listener, app).await.map_err(AppError::Io)?;
+    let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+    let mut server = tokio::spawn(async move {
+        axum::serve(listener, app)
+            .with_graceful_shutdown(async move {
+                let _ = stop_rx.await;
+            })
+            .await
+    });
+    tokio::select! {
+        result = &mut server => {
+            return match result {
+                Ok(result) => result.map_err(AppError::Io),
+                Err(err) => Err(AppError::Io(std::io::Error::other(err))),
+            };
+        }
+        _ = shutdown_signal() => {}
+    }
+
+    finish_ingests(&shutdown_state, shutdown_timeout).await;
+    let _ = stop_tx.send(());
+    match tokio::time::timeout(SHUTDOWN_CONNECTION_GRACE, server).await {
+        Ok(Ok(result)) => result.map_err(AppError::Io)?,
+        Ok(Err(err)) => return Err(AppError::Io(std::io::Error::other(err))),
+        Err(_) => warn!("open connections did not close in time; exiting anyway"),
+    }
+    info!("shutdown complete");
     Ok(())
 }
 
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            warn!("failed to listen for ctrl-c: {err}");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(err) => {
+                warn!("failed to listen for SIGTERM: {err}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+async fn finish_ingests(state: &AppState, timeout: Duration) {
+    state.shutting_down.store(true, Ordering::SeqCst);
+    let active = state.active_ingests.lock().await.len();
+    if active == 0 {
+        return;
+    }
+    info!(
+        "shutting down; waiting up to {:?} for {} ingest(s) to reach a safe point",
+        timeout, active
+    );
+    let deadline = tokio::time::Instant::now() + timeout;
+    while tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(SHUTDOWN_POLL_INTERVAL).await;
+        if state.active_ingests.lock().await.is_empty() {
+            info!("all ingests stopped");
+            return;
+        }
+    }
+
+    let remaining: Vec<String> = state.active_ingests.lock().await.iter().cloned().collect();
+    for repo_id in remaining {
+        let Ok(record) = find_repo_by_id(state, &repo_id).await else {
+            continue;
+        };
+        warn!("ingest for repo {} did not finish before shutdown", repo_id);
+        let vv_path = repo_dir(state, &record).join("vv");
+        let message = Some("Interrupted by server shutdown".to_string());
+        let _ = write_status(state, &vv_path, &repo_id, "interrupted", message).await;
+    }
+}
+
+async fn search_ui() -> Html<&'static str> {
+    Html(SEARCH_UI_HTML)
+}
+
 async fn create_repo(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(payload): Json<RepoRequest>,
 ) -> Result<Json<RepoResponse>, AppError> {
-    let (owner, name) = parse_repo_url(&payload.repo_url)?;
+    let idempotency_key = headers
+        .get("idempotency-key")
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(str::to_string);
+    let Some(key) = idempotency_key else {
+        return register_repo(&state, payload).await.map(Json);
+    };
+
+    let mut keys = state.idempotency_keys.lock().await;
+    keys.retain(|_, entry| entry.created_at.elapsed() < IDEMPOTENCY_KEY_TTL);
+    if let Some(entry) = keys.get(&key) {
+        if entry.response.repo_url != payload.repo_url {
+            return Err(AppError::InvalidRequest(
+                "Idempotency-Key was already used for a different repo_url".into(),
+            ));
+        }
+        return Ok(Json(entry.response.clone()));
+    }
+    let response = register_repo(&state, payload).await?;
+    if keys.len() >= IDEMPOTENCY_MAX_KEYS {
+        let oldest = keys
+            .iter()
+            .min_by_key(|(_, entry)| entry.created_at)
+            .map(|(key, _)| key.clone());
+        if let Some(oldest) = oldest {
+            keys.remove(&oldest);
+        }
+    }
+    keys.insert(
+        key,
+        IdempotentRepoCreation {
+            created_at: std::time::Instant::now(),
+            response: response.clone(),
+        },
+    );
+    Ok(Json(response))
+}
+
+async fn register_repo(state: &AppState, payload: RepoRequest) -> Result<RepoResponse, AppError> {
+    let branch = validate_git_ref("branch", payload.branch.as_deref())?;
+    let commit = validate_git_ref("commit", payload.commit.as_deref())?;
+    let (host, owner, name) = if is_archive_url(&payload.repo_url) {
+        if !state.allow_archive_ingest {
+            return Err(AppError::InvalidRequest(
+                "archive ingest is disabled (set ALLOW_ARCHIVE_INGEST=true)".into(),
+            ));
+        }
+        if branch.is_some() || commit.is_some() {
+            return Err(AppError::InvalidRequest(
+                "branch and commit are not supported for archive repos".into(),
+            ));
+        }
+        let host = reqwest::Url::parse(payload.repo_url.trim())
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_else(default_repo_host);
+        let (owner, name) = parse_archive_url(&payload.repo_url)?;
+        (host, owner, name)
+    } else {
+        let (host, owner, name) = parse_repo_url(&payload.repo_url)?;
+        if host == GITHUB_HOST {
+            let (owner, name) = resolve_canonical_repo_name(state, owner, name).await;
+            (host, owner, name)
+        } else {
+            (host, owner, name)
+        }
+    };
     let id = Uuid::new_v4().to_string();
 
     let record = RepoRecord {
         id: id.clone(),
         repo_url: payload.repo_url.clone(),
+        host,
         owner: owner.clone(),
         name: name.clone(),
+        tags: Vec::new(),
+        include_generated: None,
+        embedding_model: None,
+        branch,
+        commit,
     };
 
     {
@@ -512,84 +2176,554 @@ async fn create_repo(
         save_registry(&state.registry_path, &registry).await?;
     }
 
-    let repo_path = state.repos_path.join(&owner).join(&name);
+    let repo_path = repo_dir(state, &record);
 
-    Ok(Json(RepoResponse {
+    Ok(RepoResponse {
         id,
         repo_url: payload.repo_url,
         owner,
         name,
         path: repo_path.to_string_lossy().to_string(),
-    }))
+    })
 }
 
-async fn list_repos(State(state): State<AppState>) -> Result<Json<Vec<RepoRecord>>, AppError> {
+async fn list_repos(
+    State(state): State<AppState>,
+    Query(query): Query<ListReposQuery>,
+) -> Result<Json<Vec<RepoRecord>>, AppError> {
     let registry = state.registry.read().await;
-    Ok(Json(registry.clone()))
+    let tag = query
+        .tag
+        .as_deref()
+        .map(normalize_tag)
+        .filter(|tag| !tag.is_empty());
+    let repos = match tag {
+        Some(tag) => registry
+            .iter()
+            .filter(|record| record.tags.contains(&tag))
+            .cloned()
+            .collect(),
+        None => registry.clone(),
+    };
+    Ok(Json(repos))
 }
 
-async fn index_repo(
+async fn update_repo(
     State(state): State<AppState>,
     Path(id): Path<String>,
-) -> Result<Json<StatusResponse>, AppError> {
-    let record = find_repo_by_id(&state, &id).await?;
+    Json(payload): Json<RepoPatchRequest>,
+) -> Result<Json<RepoRecord>, AppError> {
+    find_repo_by_id(&state, &id).await?;
+    let mut registry = state.registry.write().await;
+    let record = registry
+        .iter_mut()
+        .find(|record| record.id == id)
+        .ok_or(AppError::RepoNotFound)?;
+    if let Some(tags) = payload.tags {
+        let mut normalized: Vec<String> = Vec::new();
+        for tag in tags.iter().map(|tag| normalize_tag(tag)) {
+            if !tag.is_empty() && !normalized.contains(&tag) {
+                normalized.push(tag);
+            }
+        }
+        record.tags = normalized;
+    }
+    if let Some(include_generated) = payload.include_generated {
+        record.include_generated = Some(include_generated);
+    }
+    if let Some(embedding_model) = payload.embedding_model {
+        let embedding_model = embedding_model.trim();
+        record.embedding_model = if embedding_model.is_empty() {
+            None
+        } else {
+            Some(embedding_model.to_string())
+        };
+    }
+    let updated = record.clone();
+    save_registry(&state.registry_path, &registry).await?;
+    Ok(Json(updated))
+}
 
-    let repo_path = state.repos_path.join(&record.owner).join(&record.name);
-    let vv_path = repo_path.join("vv");
+async fn delete_repo(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<RepoDeleteResponse>, AppError> {
+    let record = find_repo_by_id(&state, &id).await?;
+    // Claim the repo like an ingest does, so none can start while its data is being removed.
+    lock_idle_repo(&state, &record)
+        .await?
+        .insert(record.id.clone());
+    let removed = remove_repo_data(&state, &record).await;
+    release_ingest_slot(&state, &record.id).await;
+    let documents_removed = removed?;
 
-    write_status(
+    info!(
+        "deleted repo {} ({}/{}), {} documents removed",
+        record.id, record.owner, record.name, documents_removed
+    );
+    publish_event(
         &state,
-        &vv_path,
         &record.id,
-        "in_progress",
-        Some("Ingestion queued".into()),
+        "deleted",
+        Some(format!(
+            "Repository deleted ({documents_removed} documents removed)"
+        )),
+        None,
     )
-    .await?;
-    let state_clone = state.clone();
-    let record_clone = record.clone();
-    let repo_path_clone = repo_path.clone();
-    let vv_path_clone = vv_path.clone();
-    tokio::spawn(async move {
-        let state_for_ingest = state_clone.clone();
-        let vv_path_for_ingest = vv_path_clone.clone();
-        if let Err(err) =
-            ingest_repo(state_for_ingest, record_clone, repo_path_clone, vv_path_for_ingest).await
-        {
-            error!("ingestion failed for repo {}: {}", record.id, err);
-            let _ = write_status(
-                &state_clone,
-                &vv_path_clone,
-                &record.id,
-                "error",
-                Some(err.to_string()),
-            )
-            .await;
-        }
-    });
-
-    Ok(Json(StatusResponse {
-        status: "in_progress".into(),
-        message: Some("Ingestion started".into()),
+    .await;
+    Ok(Json(RepoDeleteResponse {
+        repo_id: record.id,
+        documents_removed,
     }))
 }
 
-async fn repo_status(
-    State(state): State<AppState>,
-    Path(id): Path<String>,
-) -> Result<Json<StatusResponse>, AppError> {
-    let record = find_repo_by_id(&state, &id).await?;
-    let vv_path = state
-        .repos_path
-        .join(&record.owner)
-        .join(&record.name)
-        .join("vv");
-    let mut status = read_status(&vv_path).await?;
-    if status.status == "unknown" {
-        if repo_indexed_in_vespa(&state, &record.id).await.unwrap_or(false) {
-            status = StatusResponse {
-                status: "complete".into(),
-                message: Some("Ingestion complete (status inferred from Vespa).".into()),
-            };
+/// Removes a repo's documents, registry entry and checkout, returning how many documents the
+/// index reported deleting.
+async fn remove_repo_data(state: &AppState, record: &RepoRecord) -> Result<u64, AppError> {
+    let documents_removed = if state.search_backend == SearchBackend::Local {
+        let mut index = state.local_index.write().await;
+        let doc_ids: Vec<String> = index
+            .documents
+            .iter()
+            .filter(|(_, document)| document.repo_id == record.id)
+            .map(|(doc_id, _)| doc_id.clone())
+            .collect();
+        for doc_id in &doc_ids {
+            index.remove(doc_id);
+        }
+        doc_ids.len() as u64
+    } else if state.vespa_document_endpoint.trim().is_empty() {
+        0
+    } else {
+        let selection = format!(
+            "{}.repo_id=={}",
+            state.vespa_document_type,
+            yql_quote(&record.id)
+        );
+        delete_vespa_documents(state, &selection).await?
+    };
+
+    {
+        let mut registry = state.registry.write().await;
+        registry.retain(|existing| existing.id != record.id);
+        save_registry(&state.registry_path, &registry).await?;
+    }
+    match fs::remove_dir_all(repo_dir(state, record)).await {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err.into()),
+    }
+    Ok(documents_removed)
+}
+
+fn is_busy_status(status: &str) -> bool {
+    matches!(
+        status,
+        "in_progress" | "indexing" | "summarizing" | "mirroring" | "reembedding"
+    )
+}
+
+fn normalize_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+async fn resolve_repo_scope(
+    state: &AppState,
+    repo_filter: Option<&str>,
+    repo_tags: Option<&[String]>,
+) -> Option<Vec<String>> {
+    let repo_filter = repo_filter.map(str::trim).filter(|value| !value.is_empty());
+    let tags: Vec<String> = repo_tags
+        .unwrap_or_default()
+        .iter()
+        .map(|tag| normalize_tag(tag))
+        .filter(|tag| !tag.is_empty())
+        .collect();
+
+    let mut scope: Option<Vec<String>> = repo_filter.map(|repo_id| vec![repo_id.to_string()]);
+    if !tags.is_empty() {
+        let registry = state.registry.read().await;
+        let tagged: Vec<String> = registry
+            .iter()
+            .filter(|record| record.tags.iter().any(|tag| tags.contains(tag)))
+            .map(|record| record.id.clone())
+            .collect();
+        scope = Some(match scope {
+            Some(ids) => ids.into_iter().filter(|id| tagged.contains(id)).collect(),
+            None => tagged,
+        });
+    }
+    scope
+}
+
+async fn run_ingest(state: AppState, record: RepoRecord) {
+    let repo_path = repo_dir(&state, &record);
+    let vv_path = repo_path.join("vv");
+    let ingest_span = info_span!("ingest", repo_id = %record.id);
+    async {
+        let result = if state.shutting_down.load(Ordering::SeqCst) {
+            Err(AppError::ShuttingDown)
+        } else {
+            ingest_repo(state.clone(), record.clone(), repo_path, vv_path.clone()).await
+        };
+        match result {
+            Ok(()) => {}
+            Err(AppError::ShuttingDown) => {
+                warn!("ingestion for repo {} stopped for shutdown", record.id);
+                let _ = write_status(
+                    &state,
+                    &vv_path,
+                    &record.id,
+                    "interrupted",
+                    Some("Interrupted by server shutdown".into()),
+                )
+                .await;
+            }
+            Err(err) => {
+                error!("ingestion failed for repo {}: {}", record.id, err);
+                let _ = write_status(&state, &vv_path, &record.id, "error", Some(err.to_string()))
+                    .await;
+            }
+        }
+    }
+    .instrument(ingest_span)
+    .await;
+    state.active_ingests.lock().await.remove(&record.id);
+}
+
+async fn index_repo(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    payload: Option<Json<IndexRepoRequest>>,
+) -> Result<Json<StatusResponse>, AppError> {
+    let mut record = find_repo_by_id(&state, &id).await?;
+    let git_ref = match payload {
+        Some(Json(payload)) => {
+            let branch = validate_git_ref("branch", payload.branch.as_deref())?;
+            let commit = validate_git_ref("commit", payload.commit.as_deref())?;
+            if is_archive_url(&record.repo_url) && (branch.is_some() || commit.is_some()) {
+                return Err(AppError::InvalidRequest(
+                    "branch and commit are not supported for archive repos".into(),
+                ));
+            }
+            Some((branch, commit))
+        }
+        None => None,
+    };
+    let permit = acquire_ingest_slot(&state, &record).await?;
+    if let Some((branch, commit)) = git_ref {
+        let mut registry = state.registry.write().await;
+        if let Some(entry) = registry.iter_mut().find(|entry| entry.id == record.id) {
+            entry.branch = branch;
+            entry.commit = commit;
+            record = entry.clone();
+        }
+        if let Err(err) = save_registry(&state.registry_path, &registry).await {
+            drop(registry);
+            release_ingest_slot(&state, &record.id).await;
+            return Err(err);
+        }
+    }
+    spawn_ingest(state, record, permit, None).await?;
+
+    Ok(Json(StatusResponse {
+        status: "in_progress".into(),
+        message: Some("Ingestion started".into()),
+        progress: None,
+        summary_error: None,
+    }))
+}
+
+/// Locks `active_ingests` once `record` is known to be idle, so the caller can claim it under the
+/// same guard and two concurrent requests cannot both start an ingest.
+async fn lock_idle_repo<'a>(
+    state: &'a AppState,
+    record: &RepoRecord,
+) -> Result<tokio::sync::MutexGuard<'a, HashSet<String>>, AppError> {
+    if state.shutting_down.load(Ordering::SeqCst) {
+        return Err(AppError::ShuttingDown);
+    }
+    let status = read_status(&repo_dir(state, record).join("vv"))
+        .await
+        .map(|status| status.status)
+        .unwrap_or_default();
+    let active = state.active_ingests.lock().await;
+    if is_busy_status(&status) || active.contains(&record.id) {
+        return Err(AppError::Conflict(format!(
+            "repo {} is already being ingested; retry once it finishes",
+            record.id
+        )));
+    }
+    Ok(active)
+}
+
+async fn acquire_ingest_slot(
+    state: &AppState,
+    record: &RepoRecord,
+) -> Result<OwnedSemaphorePermit, AppError> {
+    let mut active = lock_idle_repo(state, record).await?;
+    let permit = state
+        .ingest_semaphore
+        .clone()
+        .try_acquire_owned()
+        .map_err(|_| AppError::TooManyIngests(state.max_concurrent_ingests))?;
+    active.insert(record.id.clone());
+    Ok(permit)
+}
+
+/// Claims `record` for an ingest that waits for a free slot instead of failing fast.
+async fn claim_queued_ingest(state: &AppState, record: &RepoRecord) -> Result<(), AppError> {
+    lock_idle_repo(state, record)
+        .await?
+        .insert(record.id.clone());
+    Ok(())
+}
+
+/// Gives up a repo claimed through `lock_idle_repo`.
+async fn release_ingest_slot(state: &AppState, repo_id: &str) {
+    state.active_ingests.lock().await.remove(repo_id);
+}
+
+async fn spawn_ingest(
+    state: AppState,
+    record: RepoRecord,
+    permit: OwnedSemaphorePermit,
+    refresh_branch: Option<String>,
+) -> Result<(), AppError> {
+    let repo_path = repo_dir(&state, &record);
+    if let Err(err) = write_status(
+        &state,
+        &repo_path.join("vv"),
+        &record.id,
+        "in_progress",
+        Some("Ingestion queued".into()),
+    )
+    .await
+    {
+        release_ingest_slot(&state, &record.id).await;
+        return Err(err);
+    }
+    tokio::spawn(async move {
+        let _permit = permit;
+        if let Some(branch) = refresh_branch.filter(|_| repo_path.exists()) {
+            if let Err(message) = checkout_repo_ref(&repo_path, &record, &branch).await {
+                warn!(
+                    "could not update repo {} to {}: {}",
+                    record.id, branch, message
+                );
+            }
+        }
+        run_ingest(state, record).await
+    });
+    Ok(())
+}
+
+async fn github_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<(StatusCode, Json<StatusResponse>), AppError> {
+    if state.github_webhook_secrets.is_empty() {
+        return Err(AppError::Config(
+            "GITHUB_WEBHOOK_SECRET must be set to accept GitHub webhooks".into(),
+        ));
+    }
+    let signature = headers
+        .get("x-hub-signature-256")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if !verify_github_signature(&state.github_webhook_secrets, &body, signature) {
+        return Err(AppError::InvalidSignature);
+    }
+
+    let ignored = |message: String| {
+        info!("github webhook ignored: {}", message);
+        Ok((
+            StatusCode::OK,
+            Json(StatusResponse {
+                status: "ignored".into(),
+                message: Some(message),
+                progress: None,
+                summary_error: None,
+            }),
+        ))
+    };
+    let event = headers
+        .get("x-github-event")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if event != "push" {
+        return ignored(format!("{event:?} events are not handled"));
+    }
+    let push: GitHubPushEvent = serde_json::from_slice(&body)
+        .map_err(|err| AppError::InvalidRequest(format!("invalid push payload: {err}")))?;
+    let full_name = push.repository.full_name;
+    let Some(branch) = push.git_ref.strip_prefix("refs/heads/") else {
+        return ignored(format!("push to {} is not a branch", push.git_ref));
+    };
+    if push.deleted {
+        return ignored(format!("branch {branch} of {full_name} was deleted"));
+    }
+    let record = state
+        .registry
+        .read()
+        .await
+        .iter()
+        .find(|record| {
+            record.host == GITHUB_HOST
+                && !is_archive_url(&record.repo_url)
+                && format!("{}/{}", record.owner, record.name).eq_ignore_ascii_case(&full_name)
+        })
+        .cloned();
+    let Some(record) = record else {
+        return ignored(format!("{full_name} is not registered"));
+    };
+    if record.commit.is_some() {
+        return ignored(format!("repo {} is pinned to a commit", record.id));
+    }
+    let tracked = record
+        .branch
+        .clone()
+        .or(push.repository.default_branch)
+        .unwrap_or_default();
+    if branch != tracked {
+        return ignored(format!(
+            "push to {branch} of {full_name}; only {tracked} is indexed"
+        ));
+    }
+
+    let permit = acquire_ingest_slot(&state, &record).await?;
+    info!(
+        "github push to {} of {} triggered ingest of repo {}",
+        branch, full_name, record.id
+    );
+    let refresh_branch = record.branch.is_none().then(|| branch.to_string());
+    spawn_ingest(state, record, permit, refresh_branch).await?;
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(StatusResponse {
+            status: "in_progress".into(),
+            message: Some(format!("Ingestion started for push to {branch}")),
+            progress: None,
+            summary_error: None,
+        }),
+    ))
+}
+
+async fn mirror_repo(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<StatusResponse>, AppError> {
+    let record = find_repo_by_id(&state, &id).await?;
+    if is_archive_url(&record.repo_url) {
+        return Err(AppError::InvalidRequest(
+            "archive repos are not mirrored to GitHub".into(),
+        ));
+    }
+    if record.host != GITHUB_HOST {
+        return Ok(Json(StatusResponse {
+            status: "complete".into(),
+            message: Some(format!("Mirroring skipped for {} repos", record.host)),
+            progress: None,
+            summary_error: None,
+        }));
+    }
+    if state.github_org.is_none() || state.github_token.is_none() {
+        return Err(AppError::Config(
+            "GITHUB_ORG and GITHUB_TOKEN are required for repo mirroring".into(),
+        ));
+    }
+    let repo_path = repo_dir(&state, &record);
+    if !repo_path.join(".git").exists() && !is_bare_repo(&repo_path) {
+        return Err(AppError::FileNotFound(format!(
+            "{}/{} is not cloned on this instance",
+            record.owner, record.name
+        )));
+    }
+    // Claimed like an ingest: the two would run git in the same checkout, and shutdown waits for
+    // claimed repos.
+    lock_idle_repo(&state, &record)
+        .await?
+        .insert(record.id.clone());
+    let vv_path = repo_path.join("vv");
+    if let Err(err) = write_status(
+        &state,
+        &vv_path,
+        &record.id,
+        "mirroring",
+        Some("Mirroring repository to GitHub".into()),
+    )
+    .await
+    {
+        release_ingest_slot(&state, &record.id).await;
+        return Err(err);
+    }
+
+    let mirror_span = info_span!("mirror", repo_id = %record.id);
+    tokio::spawn(
+        async move {
+            let result = async {
+                let vv_state_path = write_vv_state(&repo_path, &record).await?;
+                commit_vv_state(&repo_path, &vv_state_path).await?;
+                mirror_repo_to_github(&state, &record, &repo_path).await
+            }
+            .await;
+            let (status, message) = match result {
+                Ok(()) => ("complete", "Mirror complete".to_string()),
+                Err(err) => {
+                    error!("mirroring failed for repo {}: {}", record.id, err);
+                    ("error", format!("Mirror failed: {err}"))
+                }
+            };
+            let _ = write_status(&state, &vv_path, &record.id, status, Some(message)).await;
+            release_ingest_slot(&state, &record.id).await;
+        }
+        .instrument(mirror_span),
+    );
+
+    Ok(Json(StatusResponse {
+        status: "mirroring".into(),
+        message: Some("Mirror started".into()),
+        progress: None,
+        summary_error: None,
+    }))
+}
+
+async fn repo_status(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<StatusQuery>,
+) -> Result<Json<StatusResponse>, AppError> {
+    let record = find_repo_by_id(&state, &id).await?;
+    let vv_path = repo_dir(&state, &record).join("vv");
+    let mut receiver = state.status_tx.subscribe();
+    let mut status = read_status(&vv_path).await?;
+    if status.status == "unknown"
+        && repo_indexed_in_vespa(&state, &record.id)
+            .await
+            .unwrap_or(false)
+    {
+        status = StatusResponse {
+            status: "complete".into(),
+            message: Some("Ingestion complete (status inferred from Vespa).".into()),
+            progress: None,
+            summary_error: None,
+        };
+    }
+
+    let wait = query.wait.unwrap_or(0).min(MAX_STATUS_WAIT_SECS);
+    let current = query.current.unwrap_or_else(|| status.status.clone());
+    if wait == 0 || status.status != current {
+        return Ok(Json(status));
+    }
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(wait);
+    loop {
+        match tokio::time::timeout_at(deadline, receiver.recv()).await {
+            Ok(Ok(event)) if event.repo_id == record.id && event.status != current => {
+                return Ok(Json(read_status(&vv_path).await?));
+            }
+            Ok(Ok(_)) | Ok(Err(broadcast::error::RecvError::Lagged(_))) => continue,
+            Ok(Err(broadcast::error::RecvError::Closed)) | Err(_) => break,
         }
     }
     Ok(Json(status))
@@ -598,21 +2732,42 @@ async fn repo_status(
 async fn repo_events(
     State(state): State<AppState>,
     Path(id): Path<String>,
+    Query(query): Query<EventsQuery>,
 ) -> Sse<impl futures_util::Stream<Item = Result<Event, Infallible>>> {
+    let (receiver, replay) = {
+        let history = state.event_history.read().await;
+        let receiver = state.status_tx.subscribe();
+        let replay: Vec<IngestEvent> = match query.since {
+            Some(since) => history
+                .get(&id)
+                .map(|events| {
+                    events
+                        .iter()
+                        .filter(|event| event.timestamp > since)
+                        .cloned()
+                        .collect()
+                })
+                .unwrap_or_default(),
+            None => Vec::new(),
+        };
+        (receiver, replay)
+    };
+
     let repo_id = id.clone();
-    let stream = BroadcastStream::new(state.status_tx.subscribe()).filter_map(move |result| {
+    let live = BroadcastStream::new(receiver).filter_map(move |result| {
         let repo_id = repo_id.clone();
         async move {
             match result {
-                Ok(event) if event.repo_id == repo_id => {
-                    let payload = serde_json::to_string(&event).unwrap_or_else(|_| "{}".into());
-                    Some(Ok(Event::default().event("status").data(payload)))
-                }
+                Ok(event) if event.repo_id == repo_id => Some(event),
                 Ok(_) => None,
                 Err(_) => None,
             }
         }
     });
+    let stream = stream::iter(replay).chain(live).map(|event| {
+        let payload = serde_json::to_string(&event).unwrap_or_else(|_| "{}".into());
+        Ok(Event::default().event("status").data(payload))
+    });
 
     Sse::new(stream).keep_alive(
         KeepAlive::new()
@@ -626,22 +2781,26 @@ async fn repo_wiki(
     Path(id): Path<String>,
 ) -> Result<Json<WikiResponse>, AppError> {
     let record = find_repo_by_id(&state, &id).await?;
-    let vv_path = state
-        .repos_path
-        .join(&record.owner)
-        .join(&record.name)
-        .join("vv");
+    let vv_path = repo_dir(&state, &record).join("vv");
 
     let store = read_summary_store(&vv_path).await.unwrap_or_default();
     if let Some(latest) = store.latest() {
         let mut history = store.entries.clone();
         history.reverse();
-            return Ok(Json(WikiResponse {
-                summary: latest.summary.clone(),
-                long_summary: latest.long_summary.clone(),
-                history,
-            }));
+        let mut markdown = None;
+        if state.wiki_markdown_mode == WikiMarkdownMode::Preserve {
+            markdown = fs::read_to_string(vv_path.join("wiki/index.md"))
+                .await
+                .ok()
+                .filter(|content| !is_generated_wiki_markdown(&record, &store, content));
         }
+        return Ok(Json(WikiResponse {
+            summary: latest.summary.clone(),
+            long_summary: latest.long_summary.clone(),
+            history,
+            markdown,
+        }));
+    }
 
     let wiki_path = vv_path.join("wiki/index.md");
     let fallback = fs::read_to_string(wiki_path)
@@ -651,6 +2810,7 @@ async fn repo_wiki(
         summary: fallback.clone(),
         long_summary: fallback,
         history: Vec::new(),
+        markdown: None,
     }))
 }
 
@@ -659,835 +2819,4791 @@ async fn update_repo_summary(
     Path(id): Path<String>,
 ) -> Result<Json<WikiResponse>, AppError> {
     let record = find_repo_by_id(&state, &id).await?;
-    let repo_path = state.repos_path.join(&record.owner).join(&record.name);
+    let repo_path = repo_dir(&state, &record);
     let vv_path = repo_path.join("vv");
     let store = generate_repo_summary(&state, &record, &repo_path, &vv_path).await?;
-    let mut history = store.entries.clone();
-    history.reverse();
-    let summary = store
-        .latest()
-        .map(|entry| entry.summary.clone())
-        .unwrap_or_else(|| "Summary not available.".into());
-    let long_summary = store
-        .latest()
-        .map(|entry| entry.long_summary.clone())
-        .unwrap_or_else(|| "Summary not available.".into());
-    Ok(Json(WikiResponse {
-        summary,
-        long_summary,
-        history,
-    }))
+    Ok(Json(wiki_response_from_store(&store)))
 }
 
-async fn search(
+async fn regenerate_repo_wiki(
     State(state): State<AppState>,
-    Json(payload): Json<SearchRequest>,
-) -> Result<Json<SearchResponse>, AppError> {
-    let query = payload.query.trim();
-    if query.is_empty() {
-        return Ok(Json(SearchResponse { results: vec![] }));
+    Path(id): Path<String>,
+    payload: Option<Json<WikiRegenerateRequest>>,
+) -> Result<Json<WikiResponse>, AppError> {
+    let payload = payload.map(|Json(payload)| payload).unwrap_or_default();
+    let detail = parse_summary_detail(payload.detail.as_deref())?;
+    let record = find_repo_by_id(&state, &id).await?;
+    let repo_path = repo_dir(&state, &record);
+    let vv_path = repo_path.join("vv");
+    let store =
+        generate_repo_summary_with_detail(&state, &record, &repo_path, &vv_path, detail).await?;
+    Ok(Json(wiki_response_from_store(&store)))
+}
+
+async fn repo_wiki_input(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<WikiRegenerateRequest>,
+) -> Result<Json<WikiInputResponse>, AppError> {
+    let detail = parse_summary_detail(query.detail.as_deref())?;
+    let record = find_repo_by_id(&state, &id).await?;
+    let repo_path = repo_dir(&state, &record);
+    let max_chars = detail.input_chars(state.huggingface_summary_max_chars);
+    let sections = build_repo_summary_sections(&state, &record, &repo_path, max_chars).await?;
+    let section_chars: usize = sections.iter().map(|section| section.chars().count()).sum();
+    let section_count = if sections.len() > 1 && section_chars > max_chars {
+        sections.len()
+    } else {
+        1
+    };
+    let input = build_repo_summary_input(&state, &record, &repo_path, max_chars).await?;
+    Ok(Json(WikiInputResponse {
+        repo_id: record.id,
+        detail: detail.name().to_string(),
+        max_chars,
+        char_count: input.chars().count(),
+        section_count,
+        input,
+    }))
+}
+
+fn parse_summary_detail(value: Option<&str>) -> Result<SummaryDetail, AppError> {
+    match value.map(str::trim) {
+        None | Some("") => Ok(SummaryDetail::Standard),
+        Some(value) => SummaryDetail::parse(value).ok_or_else(|| {
+            AppError::InvalidRequest(format!(
+                "detail must be one of short, standard, detailed (got {value:?})"
+            ))
+        }),
     }
+}
 
-    let search_mode = resolve_search_mode(payload.search_mode.as_deref());
-    let yql = build_search_yql(payload.repo_filter.as_deref(), search_mode);
-    let search_url = vespa_search_url(&state)?;
-    let has_repo_filter = payload
-        .repo_filter
-        .as_deref()
-        .map(str::trim)
-        .filter(|value| !value.is_empty())
-        .is_some();
-    let hits = if has_repo_filter { 100 } else { 10 };
-    let mut body = serde_json::json!({
-        "yql": yql,
-        "hits": hits,
-        "query": query,
+async fn regenerate_all_wikis(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let records: Vec<RepoRecord> = state
+        .registry
+        .read()
+        .await
+        .iter()
+        .filter(|record| repo_dir(&state, record).exists())
+        .cloned()
+        .collect();
+    let queued = records.len();
+    info!("queued summary regeneration for {} repos", queued);
+
+    let concurrency = state.summary_concurrency;
+    tokio::spawn(async move {
+        stream::iter(records)
+            .for_each_concurrent(concurrency, |record| {
+                let state = state.clone();
+                async move {
+                    let repo_path = repo_dir(&state, &record);
+                    let vv_path = repo_path.join("vv");
+                    publish_event(
+                        &state,
+                        &record.id,
+                        "summarizing",
+                        Some("Regenerating repository summary".into()),
+                        None,
+                    )
+                    .await;
+                    match generate_repo_summary(&state, &record, &repo_path, &vv_path).await {
+                        Ok(_) => {
+                            publish_event(
+                                &state,
+                                &record.id,
+                                "summary_complete",
+                                Some("Repository summary regenerated".into()),
+                                None,
+                            )
+                            .await;
+                        }
+                        Err(err) => {
+                            warn!(
+                                "summary regeneration failed for repo {}: {}",
+                                record.id, err
+                            );
+                            publish_event(
+                                &state,
+                                &record.id,
+                                "summary_failed",
+                                Some(err.to_string()),
+                                None,
+                            )
+                            .await;
+                        }
+                    }
+                }
+            })
+            .await;
+        info!("summary regeneration batch finished");
     });
 
-    if matches!(search_mode, SearchMode::Hybrid | SearchMode::Bm25) {
-        if let Some(object) = body.as_object_mut() {
-            object.insert("defaultIndex".to_string(), "content".into());
-        }
-    }
+    Ok(Json(serde_json::json!({ "queued": queued })))
+}
 
-    if let Some(profile) = search_mode.profile_name() {
-        let query_embedding = VespaEmbedding {
-            values: embed_text(&state, query).await?,
+async fn failed_repos(state: &AppState) -> Vec<(RepoRecord, Option<String>)> {
+    let records: Vec<RepoRecord> = state.registry.read().await.clone();
+    let mut failed = Vec::new();
+    for record in records {
+        let status_path = repo_dir(state, &record).join("vv").join("status.json");
+        let Ok(data) = fs::read(&status_path).await else {
+            continue;
         };
-        let embedding_value = serde_json::to_value(&query_embedding)?;
-        if let Some(object) = body.as_object_mut() {
-            object.insert("ranking.profile".to_string(), profile.into());
-            object.insert(
-                "input.query(query_embedding)".to_string(),
-                embedding_value,
-            );
+        let Ok(status) = serde_json::from_slice::<StatusResponse>(&data) else {
+            continue;
+        };
+        if matches!(status.status.as_str(), "error" | "interrupted") {
+            failed.push((record, status.message));
         }
     }
+    failed
+}
 
-    let response = state.http_client.post(search_url).json(&body).send().await?;
+async fn export_registry(State(state): State<AppState>) -> Json<Vec<RepoRecord>> {
+    Json(state.registry.read().await.clone())
+}
 
-    if !response.status().is_success() {
-        let body = response.text().await.unwrap_or_default();
-        return Err(AppError::VespaRejected(body));
+async fn import_registry(
+    State(state): State<AppState>,
+    Json(records): Json<Vec<RepoRecord>>,
+) -> Result<Json<RegistryImportResponse>, AppError> {
+    let mut registry = state.registry.write().await;
+    let mut imported = 0usize;
+    let mut skipped = 0usize;
+    let mut rejected = Vec::new();
+    for record in records {
+        let record = match validate_imported_record(record) {
+            Ok(record) => record,
+            Err(message) => {
+                warn!("registry import: rejected record: {}", message);
+                rejected.push(message);
+                skipped += 1;
+                continue;
+            }
+        };
+        let key = (record.owner.to_lowercase(), record.name.to_lowercase());
+        let duplicate = registry.iter().any(|existing| {
+            existing.id == record.id
+                || (existing.owner.to_lowercase(), existing.name.to_lowercase()) == key
+        });
+        if duplicate {
+            skipped += 1;
+            continue;
+        }
+        registry.push(record);
+        imported += 1;
     }
+    if imported > 0 {
+        save_registry(&state.registry_path, &registry).await?;
+    }
+    info!(
+        "registry import: {} imported, {} skipped",
+        imported, skipped
+    );
+    Ok(Json(RegistryImportResponse {
+        imported,
+        skipped,
+        total: registry.len(),
+        rejected,
+    }))
+}
 
-    let body: serde_json::Value = response.json().await?;
-    let mut results = Vec::new();
-    if let Some(children) = body.pointer("/root/children").and_then(|v| v.as_array()) {
-        for child in children {
-            let fields = match child.get("fields") {
-                Some(fields) => fields,
-                None => continue,
-            };
-            let repo_id = fields
-                .get("repo_id")
-                .and_then(|value| value.as_str())
-                .unwrap_or_default()
-                .to_string();
-            let file_path = fields
-                .get("file_path")
-                .and_then(|value| value.as_str())
-                .unwrap_or_default()
-                .to_string();
-            let line_start = fields
-                .get("line_start")
-                .and_then(|value| value.as_i64())
-                .unwrap_or(1)
-                .max(1) as usize;
-            let line_end = fields
-                .get("line_end")
-                .and_then(|value| value.as_i64())
-                .unwrap_or(line_start as i64)
-                .max(1) as usize;
-            let content = fields
-                .get("content")
-                .and_then(|value| value.as_str())
-                .unwrap_or("");
-            let snippet = build_snippet(content);
-
-            results.push(SearchResult {
-                repo_id,
-                file_path,
-                line_start,
-                line_end,
-                snippet,
-            });
+fn validate_imported_record(mut record: RepoRecord) -> Result<RepoRecord, String> {
+    let label = format!("{}/{} ({})", record.owner, record.name, record.repo_url);
+    for (field, value) in [("owner", &record.owner), ("name", &record.name)] {
+        if !is_safe_path_segment(value) {
+            return Err(format!(
+                "{label}: {field} {value:?} is not a valid path segment"
+            ));
         }
     }
-
-    if let Some(repo_id) = payload
-        .repo_filter
-        .as_deref()
-        .map(str::trim)
-        .filter(|value| !value.is_empty())
+    let parsed = if is_archive_url(&record.repo_url) {
+        reqwest::Url::parse(record.repo_url.trim())
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .ok_or(AppError::InvalidRepoUrl)
+            .and_then(|host| {
+                let (owner, name) = parse_archive_url(&record.repo_url)?;
+                Ok((host, owner, name))
+            })
+    } else {
+        parse_repo_url(&record.repo_url)
+    };
+    let (host, owner, name) = parsed.map_err(|err| format!("{label}: {err}"))?;
+    if !host.eq_ignore_ascii_case(&record.host)
+        || !owner.eq_ignore_ascii_case(&record.owner)
+        || !name.eq_ignore_ascii_case(&record.name)
     {
-        results.retain(|result| result.repo_id == repo_id);
+        return Err(format!(
+            "{label}: host/owner/name do not match repo_url (expected {host} {owner}/{name})"
+        ));
+    }
+    for (field, value) in [("branch", &record.branch), ("commit", &record.commit)] {
+        validate_git_ref(field, value.as_deref()).map_err(|err| format!("{label}: {err}"))?;
     }
+    record.id = Uuid::parse_str(record.id.trim())
+        .map(|id| id.to_string())
+        .unwrap_or_else(|_| Uuid::new_v4().to_string());
+    Ok(record)
+}
 
-    Ok(Json(SearchResponse { results }))
+async fn list_failed_repos(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<FailedRepo>>, AppError> {
+    let failed = failed_repos(&state)
+        .await
+        .into_iter()
+        .map(|(record, message)| FailedRepo {
+            repo_id: record.id,
+            owner: record.owner,
+            name: record.name,
+            message,
+        })
+        .collect();
+    Ok(Json(failed))
 }
 
-async fn load_registry(path: &StdPath) -> Result<Vec<RepoRecord>, AppError> {
-    if !path.exists() {
-        return Ok(vec![]);
+async fn retry_failed_repos(
+    State(state): State<AppState>,
+    payload: Option<Json<RetryFailedRequest>>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if state.shutting_down.load(Ordering::SeqCst) {
+        return Err(AppError::ShuttingDown);
     }
-    let contents = fs::read(path).await?;
-    let registry = serde_json::from_slice(&contents)?;
-    Ok(registry)
-}
+    let subset = payload.and_then(|Json(payload)| payload.repo_ids);
+    let failed: Vec<RepoRecord> = failed_repos(&state)
+        .await
+        .into_iter()
+        .map(|(record, _)| record)
+        .filter(|record| {
+            subset
+                .as_ref()
+                .is_none_or(|ids| ids.iter().any(|id| id == &record.id))
+        })
+        .collect();
+    let mut records = Vec::new();
+    let mut skipped = Vec::new();
+    for record in failed {
+        if let Err(err) = claim_queued_ingest(&state, &record).await {
+            skipped.push(serde_json::json!({ "repo_id": record.id, "error": err.to_string() }));
+            continue;
+        }
+        let vv_path = repo_dir(&state, &record).join("vv");
+        if let Err(err) = write_status(
+            &state,
+            &vv_path,
+            &record.id,
+            "in_progress",
+            Some("Ingestion queued (retry)".into()),
+        )
+        .await
+        {
+            release_ingest_slot(&state, &record.id).await;
+            skipped.push(serde_json::json!({ "repo_id": record.id, "error": err.to_string() }));
+            continue;
+        }
+        records.push(record);
+    }
+    let repo_ids: Vec<String> = records.iter().map(|record| record.id.clone()).collect();
+    info!("retrying ingestion for {} failed repos", repo_ids.len());
 
-async fn save_registry(path: &StdPath, registry: &[RepoRecord]) -> Result<(), AppError> {
-    let contents = serde_json::to_vec_pretty(registry)?;
-    fs::write(path, contents).await?;
-    Ok(())
+    tokio::spawn(async move {
+        stream::iter(records)
+            .for_each_concurrent(None, |record| {
+                let state = state.clone();
+                async move {
+                    let _permit = state.ingest_semaphore.clone().acquire_owned().await;
+                    run_ingest(state, record).await
+                }
+            })
+            .await;
+        info!("failed repo retry batch finished");
+    });
+
+    Ok(Json(serde_json::json!({
+        "queued": repo_ids.len(),
+        "repo_ids": repo_ids,
+        "skipped": skipped,
+    })))
 }
 
-async fn list_github_org_repos(state: &AppState, org: &str) -> Result<Vec<GitHubRepo>, AppError> {
-    let mut page = 1usize;
-    let mut repos = Vec::new();
+async fn reconcile_index(
+    State(state): State<AppState>,
+    payload: Option<Json<ReconcileRequest>>,
+) -> Result<Json<ReconcileReport>, AppError> {
+    let options = payload.map(|Json(payload)| payload).unwrap_or_default();
+    let mut counts = if state.search_backend == SearchBackend::Local {
+        let index = state.local_index.read().await;
+        let mut counts: HashMap<String, u64> = HashMap::new();
+        for document in index.documents.values() {
+            *counts.entry(document.repo_id.clone()).or_default() += 1;
+        }
+        counts
+    } else {
+        fetch_vespa_repo_document_counts(&state).await?
+    };
+    let records: Vec<RepoRecord> = state.registry.read().await.clone();
+    let mut report = ReconcileReport::default();
 
-    loop {
-        let url = format!("https://api.github.com/orgs/{org}/repos?per_page=100&page={page}");
-        let mut request = state
-            .http_client
-            .get(&url)
-            .header("Accept", "application/vnd.github+json")
-            .header("User-Agent", "vespa-code-search");
-        if let Some(token) = state.github_token.as_deref() {
-            request = request.header("Authorization", format!("token {token}"));
+    for record in &records {
+        let document_count = counts.remove(&record.id).unwrap_or(0);
+        if document_count == 0 {
+            report.empty_repos.push(record.id.clone());
         }
-        let response = request.send().await?;
-        if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await.unwrap_or_default();
-            return Err(AppError::GitHub(format!(
-                "failed to list GitHub repos for {org}: {status} {body}"
-            )));
+        report.repos.push(ReconciledRepo {
+            repo_id: record.id.clone(),
+            owner: record.owner.clone(),
+            name: record.name.clone(),
+            document_count,
+        });
+    }
+    report.orphans = counts
+        .into_iter()
+        .map(|(repo_id, document_count)| OrphanedRepo {
+            repo_id,
+            document_count,
+        })
+        .collect();
+    report
+        .orphans
+        .sort_by(|left, right| left.repo_id.cmp(&right.repo_id));
+
+    if options.purge_orphans {
+        for orphan in &report.orphans {
+            let purged = if state.search_backend == SearchBackend::Local {
+                let mut index = state.local_index.write().await;
+                let doc_ids: Vec<String> = index
+                    .documents
+                    .iter()
+                    .filter(|(_, document)| document.repo_id == orphan.repo_id)
+                    .map(|(doc_id, _)| doc_id.clone())
+                    .collect();
+                for doc_id in &doc_ids {
+                    index.remove(doc_id);
+                }
+                doc_ids.len() as u64
+            } else {
+                let selection = format!(
+                    "{}.repo_id=={}",
+                    state.vespa_document_type,
+                    yql_quote(&orphan.repo_id)
+                );
+                delete_vespa_documents(&state, &selection).await?
+            };
+            info!(
+                "purged {} orphaned documents for {}",
+                purged, orphan.repo_id
+            );
+            report.purged_documents += purged;
         }
+    }
 
-        let page_repos: Vec<GitHubRepo> = response.json().await?;
-        let page_count = page_repos.len();
-        repos.extend(page_repos);
-        if page_count < 100 {
-            break;
+    if options.flag_empty {
+        for record in records
+            .iter()
+            .filter(|record| report.empty_repos.contains(&record.id))
+        {
+            let repo_path = repo_dir(&state, record);
+            let vv_path = repo_path.join("vv");
+            let Ok(status) = read_status(&vv_path).await else {
+                continue;
+            };
+            if status.status != "complete" || repo_is_empty(&repo_path).await {
+                continue;
+            }
+            write_status(
+                &state,
+                &vv_path,
+                &record.id,
+                "error",
+                Some("No documents indexed; reindex required".into()),
+            )
+            .await?;
+            report.flagged_repos.push(record.id.clone());
         }
-        page += 1;
     }
 
-    Ok(repos)
+    info!(
+        "reconcile: {} repos, {} empty, {} orphaned repo ids",
+        report.repos.len(),
+        report.empty_repos.len(),
+        report.orphans.len()
+    );
+    Ok(Json(report))
 }
 
-async fn fetch_github_repo_state(
-    state: &AppState,
-    org: &str,
-    repo: &GitHubRepo,
-) -> Result<Option<RepoRecord>, AppError> {
-    let branch = if repo.default_branch.is_empty() {
-        "main"
-    } else {
-        repo.default_branch.as_str()
-    };
-    let url = format!(
-        "https://raw.githubusercontent.com/{org}/{}/{}/.vv/state.json",
-        repo.name, branch
-    );
-    let mut request = state
-        .http_client
-        .get(&url)
-        .header("User-Agent", "vespa-code-search");
-    if let Some(token) = state.github_token.as_deref() {
-        request = request.header("Authorization", format!("token {token}"));
-    }
-    let response = request
-        .send()
-        .await
-        .map_err(|err| AppError::HuggingFace(err.to_string()))?;
-    if response.status() == StatusCode::NOT_FOUND {
-        return Ok(None);
+fn wiki_response_from_store(store: &SummaryStore) -> WikiResponse {
+    let mut history = store.entries.clone();
+    history.reverse();
+    let summary = store
+        .latest()
+        .map(|entry| entry.summary.clone())
+        .unwrap_or_else(|| "Summary not available.".into());
+    let long_summary = store
+        .latest()
+        .map(|entry| entry.long_summary.clone())
+        .unwrap_or_else(|| "Summary not available.".into());
+    WikiResponse {
+        summary,
+        long_summary,
+        history,
+        markdown: None,
     }
-    if !response.status().is_success() {
-        let status = response.status();
-        let body = response.text().await.unwrap_or_default();
-        return Err(AppError::GitHub(format!(
-            "failed to fetch .vv state from {url}: {status} {body}"
+}
+
+async fn repo_context(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(payload): Json<ContextRequest>,
+) -> Result<Json<ContextResponse>, AppError> {
+    let record = find_repo_by_id(&state, &id).await?;
+    let file_path = payload
+        .file_path
+        .trim()
+        .trim_start_matches("./")
+        .to_string();
+    let relative = StdPath::new(&file_path);
+    if file_path.is_empty()
+        || relative.is_absolute()
+        || relative
+            .components()
+            .any(|component| !matches!(component, std::path::Component::Normal(_)))
+    {
+        return Err(AppError::InvalidRequest(format!(
+            "file_path must be a relative path inside the repo (got {:?})",
+            payload.file_path
         )));
     }
-
-    let payload = match response.json::<GitHubRepoState>().await {
-        Ok(payload) => payload,
-        Err(err) => {
-            warn!("failed to parse .vv state from {url}: {err}");
-            return Ok(None);
+    let hit_start = payload.line_start.max(1);
+    let hit_end = payload.line_end.unwrap_or(hit_start).max(hit_start);
+    let before = payload
+        .before
+        .unwrap_or(DEFAULT_CONTEXT_LINES)
+        .min(MAX_CONTEXT_LINES);
+    let after = payload
+        .after
+        .unwrap_or(DEFAULT_CONTEXT_LINES)
+        .min(MAX_CONTEXT_LINES);
+    let window_start = hit_start.saturating_sub(before).max(1);
+    let window_end = hit_end.saturating_add(after);
+
+    let repo_path = repo_dir(&state, &record);
+    let (source, file_lines) = match read_repo_file(&repo_path, relative).await {
+        Ok(bytes) => {
+            let (decoded, _) = decode_source_bytes(&bytes, state.detect_encoding);
+            let text = sanitize_vespa_content(&decoded);
+            let lines = text
+                .lines()
+                .enumerate()
+                .map(|(index, line)| (index + 1, line.to_string()))
+                .collect::<HashMap<_, _>>();
+            ("working_tree", lines)
         }
+        Err(_) => (
+            "index",
+            fetch_indexed_file_lines(&state, &record.id, &file_path).await?,
+        ),
     };
-    if payload.repo_id.is_empty() {
-        return Ok(None);
+    if file_lines.is_empty() {
+        return Err(AppError::FileNotFound(file_path));
     }
 
-    Ok(Some(RepoRecord {
-        id: payload.repo_id,
-        repo_url: payload.repo_url,
-        owner: payload.owner,
-        name: payload.name,
+    let lines: Vec<ContextLine> = (window_start..=window_end)
+        .filter_map(|number| {
+            file_lines.get(&number).map(|text| ContextLine {
+                number,
+                text: text.clone(),
+            })
+        })
+        .collect();
+    let line_start = lines
+        .first()
+        .map(|line| line.number)
+        .unwrap_or(window_start);
+    let line_end = lines.last().map(|line| line.number).unwrap_or(line_start);
+
+    Ok(Json(ContextResponse {
+        repo_id: record.id,
+        file_path,
+        line_start,
+        line_end,
+        source: source.to_string(),
+        lines,
     }))
 }
 
-async fn sync_registry_from_github(state: &AppState) -> Result<usize, AppError> {
-    let org = match state.github_org.as_deref() {
-        Some(org) => org,
-        None => return Ok(0),
-    };
-
-    let repos = list_github_org_repos(state, org).await?;
-    let mut records = Vec::new();
-    for repo in repos {
-        if !repo.name.ends_with("-vv-search") {
-            continue;
-        }
-        match fetch_github_repo_state(state, org, &repo).await {
-            Ok(Some(record)) => records.push(record),
-            Ok(None) => {}
-            Err(err) => warn!("failed to read vv state for {}: {}", repo.name, err),
+async fn repo_files(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<RepoFilesResponse>, AppError> {
+    let record = find_repo_by_id(&state, &id).await?;
+    let repo_path = repo_dir(&state, &record);
+    let (source, files) = match state.github_token.as_deref() {
+        Some(token) if !repo_path.exists() && record.host == GITHUB_HOST => (
+            "github",
+            list_github_tree_files(&state, &record, token).await?,
+        ),
+        _ if !repo_path.exists() => {
+            return Err(AppError::FileNotFound(format!(
+                "{}/{} is not cloned on this instance",
+                record.owner, record.name
+            )))
         }
-    }
-
-    if records.is_empty() {
-        return Ok(0);
-    }
+        _ => (
+            "local",
+            list_repo_files(&repo_path, &state.index_ignore_patterns).await?,
+        ),
+    };
+    let mut files: Vec<String> = files
+        .iter()
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+    files.sort();
+    Ok(Json(RepoFilesResponse {
+        repo_id: record.id,
+        source: source.to_string(),
+        files,
+    }))
+}
 
-    let mut registry = state.registry.write().await;
-    let mut index = HashMap::new();
-    for (idx, record) in registry.iter().enumerate() {
-        index.insert(record.id.clone(), idx);
+async fn repo_diff_index(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<DiffIndexResponse>, AppError> {
+    let record = find_repo_by_id(&state, &id).await?;
+    let repo_path = repo_dir(&state, &record);
+    if !repo_path.exists() {
+        return Err(AppError::FileNotFound(format!(
+            "{}/{} is not cloned on this instance",
+            record.owner, record.name
+        )));
     }
+    let vv_path = repo_path.join("vv");
 
-    let mut changes = 0usize;
-    for record in records {
-        if let Some(&idx) = index.get(&record.id) {
-            let existing = &mut registry[idx];
-            if existing.repo_url != record.repo_url
-                || existing.owner != record.owner
-                || existing.name != record.name
-            {
-                *existing = record;
-                changes += 1;
+    let mut indexed: HashMap<String, String> = HashMap::new();
+    let chunks = fs::read_to_string(vv_path.join("chunks.jsonl"))
+        .await
+        .unwrap_or_default();
+    for line in chunks.lines() {
+        let Ok(entry) = serde_json::from_str::<serde_json::Value>(line) else {
+            continue;
+        };
+        let field = |name: &str| entry.get(name).and_then(|value| value.as_str());
+        let file_sha = field("file_sha").or_else(|| field("content_sha"));
+        if let (Some(file_path), Some(content_sha)) = (field("file_path"), file_sha) {
+            if !file_path.starts_with("commit:") {
+                indexed.insert(file_path.to_string(), content_sha.to_string());
             }
-        } else {
-            index.insert(record.id.clone(), registry.len());
-            registry.push(record);
-            changes += 1;
         }
     }
 
-    if changes > 0 {
-        save_registry(&state.registry_path, &registry).await?;
+    let skip_generated = !record.include_generated.unwrap_or(false);
+    let mut diff = DiffIndexResponse {
+        repo_id: record.id.clone(),
+        ..Default::default()
+    };
+    for file_path in list_repo_files(&repo_path, &state.index_ignore_patterns).await? {
+        let Ok(Some(bytes)) =
+            read_repo_file_within(&repo_path, &file_path, state.max_content_bytes).await
+        else {
+            continue;
+        };
+        let Some((content, _)) =
+            feedable_content(&bytes, state.max_content_bytes, state.detect_encoding)
+        else {
+            continue;
+        };
+        if skip_generated && is_likely_generated(&file_path, &content) {
+            continue;
+        }
+        let path = file_path.to_string_lossy().to_string();
+        match indexed.remove(&path) {
+            Some(content_sha) if content_sha == sha256_hex(content.as_bytes()) => {
+                diff.unchanged_count += 1
+            }
+            Some(_) => diff.modified.push(path),
+            None => diff.added.push(path),
+        }
     }
-
-    Ok(changes)
+    diff.deleted = indexed.into_keys().collect();
+    diff.added.sort();
+    diff.modified.sort();
+    diff.deleted.sort();
+    Ok(Json(diff))
 }
 
-async fn find_repo_by_id(state: &AppState, id: &str) -> Result<RepoRecord, AppError> {
+async fn gc_repo_embedding_cache(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<VectorsGcReport>, AppError> {
+    let record = find_repo_by_id(&state, &id).await?;
+    let vv_path = repo_dir(&state, &record).join("vv");
+    Ok(Json(gc_vectors_cache(&state, &record, &vv_path).await?))
+}
+
+async fn reembed_repo(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<ReembedQuery>,
+) -> Result<Json<StatusResponse>, AppError> {
+    let record = find_repo_by_id(&state, &id).await?;
+    let vv_path = repo_dir(&state, &record).join("vv");
+    let model = repo_embedding_model(&state, &record).to_string();
+    let from_model = query
+        .from_model
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty());
+    if from_model.as_deref() == Some(model.as_str()) {
+        return Ok(Json(StatusResponse {
+            status: "complete".into(),
+            message: Some(format!("Documents are already embedded with {model}")),
+            progress: None,
+            summary_error: None,
+        }));
+    }
+    // Claimed like an ingest: an ingest running alongside would race the embedding updates, and
+    // shutdown waits for claimed repos.
+    lock_idle_repo(&state, &record)
+        .await?
+        .insert(record.id.clone());
+    if let Err(err) = write_status(
+        &state,
+        &vv_path,
+        &record.id,
+        "reembedding",
+        Some(format!("Re-embedding documents with {model}")),
+    )
+    .await
     {
-        let registry = state.registry.read().await;
-        if let Some(record) = registry.iter().find(|repo| repo.id == id) {
-            return Ok(record.clone());
+        release_ingest_slot(&state, &record.id).await;
+        return Err(err);
+    }
+
+    let reembed_span = info_span!("reembed", repo_id = %record.id);
+    tokio::spawn(
+        async move {
+            let result =
+                reembed_documents(&state, &record, &vv_path, &model, from_model.as_deref()).await;
+            let (status, message) = match result {
+                Ok(report) => {
+                    info!(
+                        "re-embedded repo {} with {}: {} updated, {} skipped, {} failed",
+                        record.id, model, report.updated, report.skipped, report.failed
+                    );
+                    (
+                        "complete",
+                        format!(
+                            "Re-embedding complete: {} updated, {} skipped, {} failed",
+                            report.updated, report.skipped, report.failed
+                        ),
+                    )
+                }
+                Err(err) => {
+                    error!("re-embedding failed for repo {}: {}", record.id, err);
+                    ("error", format!("Re-embedding failed: {err}"))
+                }
+            };
+            let _ = write_status(&state, &vv_path, &record.id, status, Some(message)).await;
+            release_ingest_slot(&state, &record.id).await;
+        }
+        .instrument(reembed_span),
+    );
+
+    Ok(Json(StatusResponse {
+        status: "reembedding".into(),
+        message: Some("Re-embedding started".into()),
+        progress: None,
+        summary_error: None,
+    }))
+}
+
+async fn reembed_documents(
+    state: &AppState,
+    record: &RepoRecord,
+    vv_path: &StdPath,
+    model: &str,
+    from_model: Option<&str>,
+) -> Result<ReembedReport, AppError> {
+    let mut report = ReembedReport::default();
+    let is_stale = |document_model: &str| match from_model {
+        Some(from_model) => document_model == from_model,
+        None => document_model != model,
+    };
+
+    if state.search_backend == SearchBackend::Local {
+        let candidates: Vec<(String, String)> = {
+            let index = state.local_index.read().await;
+            index
+                .documents
+                .iter()
+                .filter(|(_, document)| document.repo_id == record.id)
+                .filter_map(|(doc_id, document)| {
+                    if is_stale(&document.embedding_model) {
+                        Some((doc_id.clone(), document.content.clone()))
+                    } else {
+                        None
+                    }
+                })
+                .collect()
+        };
+        for (doc_id, content) in candidates {
+            let content_sha = sha256_hex(content.as_bytes());
+            match embed_content_with_cache(state, vv_path, model, &content, &content_sha).await {
+                Ok(values) => {
+                    let mut index = state.local_index.write().await;
+                    if let Some(document) = index.documents.get_mut(&doc_id) {
+                        document.embedding = Some(values);
+                        document.embedding_model = model.to_string();
+                        report.updated += 1;
+                    }
+                }
+                Err(err) => {
+                    warn!("re-embedding {} failed: {}", doc_id, err);
+                    report.failed += 1;
+                }
+            }
         }
+        return Ok(report);
     }
 
-    if state.github_org.is_some() {
-        if let Err(err) = sync_registry_from_github(state).await {
-            warn!("failed to refresh registry from GitHub: {err}");
+    let document_type = &state.vespa_document_type;
+    let mut selection = format!("{document_type}.repo_id=={}", yql_quote(&record.id));
+    if let Some(from_model) = from_model {
+        selection.push_str(&format!(
+            " and {document_type}.embedding_model=={}",
+            yql_quote(from_model)
+        ));
+    }
+    let field_set = format!("{document_type}:content,content_sha,embedding_model");
+    let mut continuation: Option<String> = None;
+    loop {
+        let (documents, next) =
+            visit_vespa_documents(state, &selection, &field_set, continuation.as_deref()).await?;
+        for document in documents {
+            let Some(doc_id) = document
+                .get("id")
+                .and_then(|value| value.as_str())
+                .and_then(|value| value.split("::").nth(1))
+            else {
+                continue;
+            };
+            let fields = document.get("fields");
+            let field = |name: &str| {
+                fields
+                    .and_then(|fields| fields.get(name))
+                    .and_then(|value| value.as_str())
+                    .unwrap_or_default()
+            };
+            if !is_stale(field("embedding_model")) {
+                report.skipped += 1;
+                continue;
+            }
+            let content = field("content");
+            let content_sha = match field("content_sha") {
+                "" => sha256_hex(content.as_bytes()),
+                sha => sha.to_string(),
+            };
+            let result = async {
+                let values =
+                    embed_content_with_cache(state, vv_path, model, content, &content_sha).await?;
+                update_vespa_embedding(state, doc_id, values, model).await
+            }
+            .await;
+            match result {
+                Ok(()) => report.updated += 1,
+                Err(err) => {
+                    warn!("re-embedding {} failed: {}", doc_id, err);
+                    report.failed += 1;
+                }
+            }
         }
-        let registry = state.registry.read().await;
-        if let Some(record) = registry.iter().find(|repo| repo.id == id) {
-            return Ok(record.clone());
+        match next {
+            Some(next) => continuation = Some(next),
+            None => break,
         }
+        write_status(
+            state,
+            vv_path,
+            &record.id,
+            "reembedding",
+            Some(format!(
+                "Re-embedding documents with {model}: {} updated, {} skipped, {} failed so far",
+                report.updated, report.skipped, report.failed
+            )),
+        )
+        .await?;
     }
+    Ok(report)
+}
 
-    Err(AppError::RepoNotFound)
+async fn visit_vespa_documents(
+    state: &AppState,
+    selection: &str,
+    field_set: &str,
+    continuation: Option<&str>,
+) -> Result<(Vec<serde_json::Value>, Option<String>), AppError> {
+    if state.vespa_document_endpoint.trim().is_empty() {
+        return Err(AppError::Config(
+            "VESPA_DOCUMENT_ENDPOINT or VESPA_ENDPOINT must be set".into(),
+        ));
+    }
+    let url = format!(
+        "{}/document/v1/{}/{}/docid",
+        state.vespa_document_endpoint.trim_end_matches('/'),
+        state.vespa_namespace,
+        state.vespa_document_type
+    );
+    let mut params = vec![
+        ("selection", selection.to_string()),
+        ("cluster", state.vespa_cluster.clone()),
+        ("fieldSet", field_set.to_string()),
+        ("wantedDocumentCount", "100".to_string()),
+    ];
+    if let Some(continuation) = continuation {
+        params.push(("continuation", continuation.to_string()));
+    }
+    let response = state.http_client.get(url).query(&params).send().await?;
+    let status = response.status();
+    let text = response.text().await?;
+    if !status.is_success() {
+        return Err(AppError::VespaRejected(text));
+    }
+    let body = parse_vespa_json(status, &text)?;
+    let documents = body
+        .get("documents")
+        .and_then(|value| value.as_array())
+        .cloned()
+        .unwrap_or_default();
+    let continuation = body
+        .get("continuation")
+        .and_then(|value| value.as_str())
+        .map(|value| value.to_string());
+    Ok((documents, continuation))
 }
 
-async fn write_status(
+/// Deletes the repo's code documents that are not in `keep`, for feeds that have no previous
+/// chunks.jsonl to find stale chunks in. Returns how many were deleted.
+async fn delete_unlisted_code_documents(
     state: &AppState,
-    vv_path: &StdPath,
     repo_id: &str,
-    status: &str,
-    message: Option<String>,
+    keep: &HashSet<String>,
+) -> Result<usize, AppError> {
+    let document_type = &state.vespa_document_type;
+    let selection = format!(
+        "{document_type}.repo_id=={} and {document_type}.doc_type=={}",
+        yql_quote(repo_id),
+        yql_quote(DOC_TYPE_CODE)
+    );
+    let mut stale = Vec::new();
+    let mut continuation: Option<String> = None;
+    loop {
+        let (documents, next) =
+            visit_vespa_documents(state, &selection, "[id]", continuation.as_deref()).await?;
+        stale.extend(
+            documents
+                .iter()
+                .filter_map(|document| document.get("id")?.as_str()?.split("::").nth(1))
+                .filter(|doc_id| !keep.contains(*doc_id))
+                .map(str::to_string),
+        );
+        match next {
+            Some(next) => continuation = Some(next),
+            None => break,
+        }
+    }
+    for doc_id in &stale {
+        delete_vespa_document(state, doc_id).await?;
+    }
+    Ok(stale.len())
+}
+
+async fn delete_vespa_documents(state: &AppState, selection: &str) -> Result<u64, AppError> {
+    if state.vespa_document_endpoint.trim().is_empty() {
+        return Err(AppError::Config(
+            "VESPA_DOCUMENT_ENDPOINT or VESPA_ENDPOINT must be set".into(),
+        ));
+    }
+    let url = format!(
+        "{}/document/v1/{}/{}/docid",
+        state.vespa_document_endpoint.trim_end_matches('/'),
+        state.vespa_namespace,
+        state.vespa_document_type
+    );
+    let mut deleted = 0u64;
+    let mut continuation: Option<String> = None;
+    loop {
+        let mut params = vec![
+            ("selection", selection.to_string()),
+            ("cluster", state.vespa_cluster.clone()),
+        ];
+        if let Some(continuation) = &continuation {
+            params.push(("continuation", continuation.clone()));
+        }
+        let response = state.http_client.delete(&url).query(&params).send().await?;
+        let status = response.status();
+        let text = response.text().await?;
+        if !status.is_success() {
+            return Err(AppError::VespaRejected(text));
+        }
+        let body = parse_vespa_json(status, &text)?;
+        deleted += body
+            .get("documentCount")
+            .and_then(|value| value.as_u64())
+            .unwrap_or(0);
+        continuation = body
+            .get("continuation")
+            .and_then(|value| value.as_str())
+            .map(|value| value.to_string());
+        if continuation.is_none() {
+            return Ok(deleted);
+        }
+    }
+}
+
+async fn update_vespa_embedding(
+    state: &AppState,
+    doc_id: &str,
+    values: Vec<f32>,
+    model: &str,
 ) -> Result<(), AppError> {
-    fs::create_dir_all(vv_path).await?;
-    let payload = StatusResponse {
-        status: status.into(),
-        message: message.clone(),
-    };
-    fs::write(
-        vv_path.join("status.json"),
-        serde_json::to_vec_pretty(&payload)?,
-    )
-    .await?;
-    let _ = state.status_tx.send(IngestEvent {
-        repo_id: repo_id.to_string(),
-        status: status.to_string(),
-        message,
-        timestamp: Utc::now().timestamp_millis(),
+    let embedding = VespaEmbedding::new(values, state.vespa_tensor_encoding);
+    let update = serde_json::json!({
+        "fields": {
+            "embedding": { "assign": embedding },
+            "embedding_model": { "assign": model },
+        }
     });
+    let response = state
+        .http_client
+        .put(vespa_document_url(state, doc_id)?)
+        .json(&update)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(AppError::VespaRejected(body));
+    }
     Ok(())
 }
 
-async fn read_status(vv_path: &StdPath) -> Result<StatusResponse, AppError> {
-    let path = vv_path.join("status.json");
-    if fs::metadata(&path).await.is_err() {
-        let chunks_path = vv_path.join("chunks.jsonl");
-        if let Ok(metadata) = fs::metadata(&chunks_path).await {
-            if metadata.len() > 0 {
-                return Ok(StatusResponse {
-                    status: "complete".into(),
-                    message: Some("Ingestion complete (status recovered).".into()),
-                });
+async fn fetch_indexed_file_lines(
+    state: &AppState,
+    repo_id: &str,
+    file_path: &str,
+) -> Result<HashMap<usize, String>, AppError> {
+    let mut chunks: Vec<(usize, String)> = Vec::new();
+    if state.search_backend == SearchBackend::Local {
+        let index = state.local_index.read().await;
+        for document in index.documents.values() {
+            if document.repo_id == repo_id && document.file_path == file_path {
+                chunks.push((document.line_start, document.content.clone()));
             }
         }
-
-        let wiki_path = vv_path.join("wiki/index.md");
-        if fs::metadata(&wiki_path).await.is_ok() {
-            return Ok(StatusResponse {
-                status: "unknown".into(),
-                message: Some(
-                    "Ingestion artifacts found, but status is unavailable. Re-run ingestion to refresh."
-                        .into(),
-                ),
-            });
+    } else {
+        let yql = format!(
+            "select line_start, content from sources * where repo_id contains {} and file_path contains {};",
+            yql_quote(repo_id),
+            yql_quote(file_path)
+        );
+        let body = with_query_profile(
+            state,
+            serde_json::json!({
+                "yql": yql,
+                "hits": 400,
+            }),
+        );
+        let response = state
+            .http_client
+            .post(vespa_search_url(state)?)
+            .json(&body)
+            .send()
+            .await?;
+        let status = response.status();
+        let text = response.text().await?;
+        if !status.is_success() {
+            return Err(AppError::VespaRejected(text));
+        }
+        let body = parse_vespa_json(status, &text)?;
+        if let Some(children) = body.pointer("/root/children").and_then(|v| v.as_array()) {
+            for child in children {
+                let Some(fields) = child.get("fields") else {
+                    continue;
+                };
+                let line_start = fields
+                    .get("line_start")
+                    .and_then(|value| value.as_i64())
+                    .unwrap_or(1)
+                    .max(1) as usize;
+                let content = fields
+                    .get("content")
+                    .and_then(|value| value.as_str())
+                    .unwrap_or_default();
+                chunks.push((line_start, content.to_string()));
+            }
         }
-
-        return Ok(StatusResponse {
-            status: "unknown".into(),
-            message: Some(
-                "Status not available on this instance. Re-run ingestion if needed.".into(),
-            ),
-        });
     }
 
-    let data = fs::read(path).await?;
-    let mut status: StatusResponse = serde_json::from_slice(&data)?;
-    if status.message.is_none() {
-        status.message = Some(match status.status.as_str() {
-            "complete" => "Ingestion complete.".into(),
-            "in_progress" => "Ingestion in progress.".into(),
-            "error" => "Ingestion failed. Check backend logs.".into(),
-            _ => "Status unavailable. Re-run ingestion if needed.".into(),
-        });
+    let mut lines = HashMap::new();
+    for (line_start, content) in chunks {
+        for (offset, line) in content.lines().enumerate() {
+            lines
+                .entry(line_start + offset)
+                .or_insert_with(|| line.to_string());
+        }
     }
-    Ok(status)
+    Ok(lines)
 }
 
-async fn read_summary_store(vv_path: &StdPath) -> Result<SummaryStore, AppError> {
-    let summary_path = vv_path.join("wiki/summary.json");
-    if fs::metadata(&summary_path).await.is_err() {
-        return Ok(SummaryStore::default());
-    }
-    let data = fs::read(&summary_path).await?;
-    let store = serde_json::from_slice::<SummaryStore>(&data)?;
-    Ok(store)
+async fn metrics_endpoint(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(
+            reqwest::header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        state.metrics.render(),
+    )
 }
 
-async fn write_summary_store(vv_path: &StdPath, store: &SummaryStore) -> Result<(), AppError> {
-    let summary_path = vv_path.join("wiki/summary.json");
-    fs::create_dir_all(summary_path.parent().unwrap()).await?;
-    let data = serde_json::to_vec_pretty(store)?;
-    fs::write(summary_path, data).await?;
-    Ok(())
+async fn search(
+    State(state): State<AppState>,
+    Json(payload): Json<SearchRequest>,
+) -> Result<Json<SearchResponse>, AppError> {
+    Ok(Json(run_search(&state, payload).await?))
 }
 
-async fn run_git_command(
-    cwd: Option<&StdPath>,
-    args: &[&str],
-) -> Result<std::process::Output, AppError> {
-    let mut command = Command::new("git");
-    command.env("GIT_TERMINAL_PROMPT", "0");
-    if let Some(path) = cwd {
-        command.arg("-C").arg(path);
+async fn get_document(
+    State(state): State<AppState>,
+    Path(doc_id): Path<String>,
+) -> Result<Json<DocumentResponse>, AppError> {
+    if state.search_backend == SearchBackend::Local {
+        let index = state.local_index.read().await;
+        let document = index
+            .documents
+            .get(&doc_id)
+            .ok_or_else(|| AppError::DocumentNotFound(doc_id.clone()))?;
+        return Ok(Json(DocumentResponse {
+            doc_id: doc_id.clone(),
+            repo_id: document.repo_id.clone(),
+            file_path: document.file_path.clone(),
+            line_start: document.line_start,
+            line_end: document.line_end,
+            content: document.content.clone(),
+            doc_type: Some(document.doc_type.clone()),
+            language: Some(guess_language(StdPath::new(&document.file_path))),
+            license_spdx: None,
+            commit_sha: document.commit_sha.clone(),
+            blob_sha: document.blob_sha.clone(),
+            committed_at: document.committed_at,
+            embedding_model: Some(document.embedding_model.clone()),
+            symbol_names: Vec::new(),
+            also_in: document.also_in.clone(),
+        }));
     }
-    command.args(args);
-    command.output().await.map_err(AppError::Io)
-}
 
-async fn ensure_github_repo(
-    state: &AppState,
-    org: &str,
-    token: &str,
-    repo_name: &str,
-) -> Result<(), AppError> {
+    let field_set = format!("{}:{}", state.vespa_document_type, DOCUMENT_RESPONSE_FIELDS);
     let response = state
         .http_client
-        .post(format!("https://api.github.com/orgs/{org}/repos"))
-        .header("Authorization", format!("token {token}"))
-        .header("Accept", "application/vnd.github+json")
-        .header("User-Agent", "vespa-code-search")
-        .json(&serde_json::json!({
-            "name": repo_name,
-            "private": false,
-        }))
+        .get(vespa_document_url(&state, &doc_id)?)
+        .query(&[("fieldSet", field_set)])
         .send()
         .await?;
-
-    if response.status().is_success() {
-        return Ok(());
+    let status = response.status();
+    if status == StatusCode::NOT_FOUND {
+        return Err(AppError::DocumentNotFound(doc_id));
+    }
+    let text = response.text().await?;
+    if !status.is_success() {
+        return Err(AppError::VespaRejected(text));
     }
+    let body = parse_vespa_json(status, &text)?;
+    let fields = body.get("fields").cloned().unwrap_or_default();
+    let text = |name: &str| {
+        fields
+            .get(name)
+            .and_then(|value| value.as_str())
+            .filter(|value| !value.is_empty() && *value != "unknown")
+            .map(str::to_string)
+    };
+    let strings = |name: &str| {
+        fields
+            .get(name)
+            .and_then(|value| value.as_array())
+            .map(|values| {
+                values
+                    .iter()
+                    .filter_map(|value| value.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let line = |name: &str| {
+        fields
+            .get(name)
+            .and_then(|value| value.as_u64())
+            .unwrap_or(1) as usize
+    };
+    Ok(Json(DocumentResponse {
+        repo_id: text("repo_id").unwrap_or_default(),
+        file_path: text("file_path").unwrap_or_default(),
+        line_start: line("line_start"),
+        line_end: line("line_end"),
+        content: text("content").unwrap_or_default(),
+        doc_type: text("doc_type"),
+        language: text("language"),
+        license_spdx: text("license_spdx"),
+        commit_sha: text("commit_sha"),
+        blob_sha: text("blob_sha"),
+        committed_at: fields.get("committed_at").and_then(|value| value.as_i64()),
+        embedding_model: text("embedding_model"),
+        symbol_names: strings("symbol_names"),
+        also_in: strings("also_in"),
+        doc_id,
+    }))
+}
 
-    let status = response.status();
-    let body = response.text().await.unwrap_or_default();
-    if status == StatusCode::UNPROCESSABLE_ENTITY && body.contains("name already exists") {
-        return Ok(());
+async fn search_batch(
+    State(state): State<AppState>,
+    Json(payload): Json<BatchSearchRequest>,
+) -> Result<Json<Vec<BatchSearchResult>>, AppError> {
+    if payload.queries.len() > MAX_BATCH_QUERIES {
+        return Err(AppError::InvalidRequest(format!(
+            "at most {MAX_BATCH_QUERIES} queries per batch (got {})",
+            payload.queries.len()
+        )));
     }
+    let results: Vec<BatchSearchResult> = stream::iter(payload.queries)
+        .map(|request| async { run_search(&state, request).await.into() })
+        .buffered(BATCH_SEARCH_CONCURRENCY)
+        .collect()
+        .await;
+    Ok(Json(results))
+}
 
-    Err(AppError::GitHub(format!(
-        "failed to create GitHub repo {org}/{repo_name}: {status} {body}"
-    )))
+async fn run_search(state: &AppState, payload: SearchRequest) -> Result<SearchResponse, AppError> {
+    let started = std::time::Instant::now();
+    let result = execute_search(state, payload).await;
+    let outcome = if result.is_ok() { "ok" } else { "error" };
+    counter!("searches_total", "outcome" => outcome).increment(1);
+    histogram!("search_duration_seconds").record(started.elapsed().as_secs_f64());
+    result
 }
 
-async fn mirror_repo_to_github(
+async fn execute_search(
+    state: &AppState,
+    payload: SearchRequest,
+) -> Result<SearchResponse, AppError> {
+    let query = payload.query.trim();
+    if query.is_empty() {
+        return Ok(SearchResponse {
+            results: vec![],
+            total_count: 0,
+            total_count_exact: true,
+        });
+    }
+    let query_chars = query.chars().count();
+    if query_chars > state.max_query_chars {
+        return Err(AppError::InvalidRequest(format!(
+            "query is too long ({query_chars} characters, at most {} allowed)",
+            state.max_query_chars
+        )));
+    }
+    let query_terms = query.split_whitespace().count();
+    if query_terms > state.max_query_terms {
+        return Err(AppError::InvalidRequest(format!(
+            "query has too many terms ({query_terms}, at most {} allowed)",
+            state.max_query_terms
+        )));
+    }
+
+    let search_mode =
+        resolve_search_mode(payload.search_mode.as_deref(), state.default_search_mode);
+    let include_embedding_model = payload.include_embedding_model.unwrap_or(false);
+    let snippet_mode = match payload.snippet_mode.as_deref().map(str::trim) {
+        None | Some("") => SnippetMode::Clean,
+        Some(value) => SnippetMode::parse(value).ok_or_else(|| {
+            AppError::InvalidRequest(format!(
+                "snippet_mode must be one of clean, raw (got {value:?})"
+            ))
+        })?,
+    };
+    let result_options = ResultOptions {
+        include_embedding_model,
+        snippet_mode,
+        snippet_lines: payload
+            .snippet_lines
+            .unwrap_or(DEFAULT_SNIPPET_LINES)
+            .clamp(1, MAX_SNIPPET_LINES),
+        explain: payload.explain.unwrap_or(false),
+        rich: payload.rich.unwrap_or(false),
+        rank_features: payload.return_rank_features.unwrap_or(false),
+    };
+    let extra_fields: &[&str] = if include_embedding_model {
+        &["embedding_model"]
+    } else {
+        &[]
+    };
+    let has_repo_filter = payload
+        .repo_filter
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .is_some();
+    let page = SearchPage {
+        hits: payload
+            .hits
+            .map(|hits| hits.clamp(1, MAX_SEARCH_HITS))
+            .unwrap_or(if has_repo_filter {
+                MAX_SEARCH_HITS
+            } else {
+                DEFAULT_SEARCH_HITS
+            }),
+        offset: payload.offset.unwrap_or(0),
+    };
+    if page.offset > MAX_SEARCH_OFFSET {
+        return Err(AppError::InvalidRequest(format!(
+            "offset must be at most {MAX_SEARCH_OFFSET} (got {})",
+            page.offset
+        )));
+    }
+    let repo_scope = resolve_repo_scope(
+        state,
+        payload.repo_filter.as_deref(),
+        payload.repo_tags.as_deref(),
+    )
+    .await;
+    if repo_scope.as_ref().is_some_and(|ids| ids.is_empty()) {
+        return Ok(SearchResponse {
+            results: vec![],
+            total_count: 0,
+            total_count_exact: true,
+        });
+    }
+    let doc_types: Vec<String> = payload
+        .doc_types
+        .as_deref()
+        .unwrap_or_default()
+        .iter()
+        .map(|doc_type| doc_type.trim().to_lowercase())
+        .filter(|doc_type| !doc_type.is_empty())
+        .collect();
+    let commit_sha = payload
+        .commit_sha
+        .as_deref()
+        .map(str::trim)
+        .filter(|sha| !sha.is_empty())
+        .map(str::to_lowercase);
+    let mut languages: Vec<String> = Vec::new();
+    for language in payload
+        .language
+        .iter()
+        .chain(payload.languages.iter().flatten())
+        .map(|language| language.trim().to_lowercase())
+    {
+        if KNOWN_LANGUAGES.contains(&language.as_str()) && !languages.contains(&language) {
+            languages.push(language);
+        }
+    }
+    let min_score = payload.min_score;
+    if min_score.is_some_and(|score| !score.is_finite()) {
+        return Err(AppError::InvalidRequest(
+            "min_score must be a finite number".into(),
+        ));
+    }
+    let filters = SearchFilters {
+        repo_ids: repo_scope,
+        doc_types,
+        commit_sha,
+        languages,
+        min_score,
+    };
+    if let Some(values) = payload.query_embedding.as_ref() {
+        let dimension = state.embedding_provider.dimension();
+        if values.len() != dimension {
+            return Err(AppError::InvalidRequest(format!(
+                "query_embedding must have {dimension} values (got {})",
+                values.len()
+            )));
+        }
+    }
+    let pinned_embedding = payload
+        .query_embedding
+        .map(|values| pinned_query_embedding(values, state.normalize_embeddings));
+    let recency_half_life_days = payload.recency_half_life_days;
+    if recency_half_life_days.is_some_and(|days| !(days.is_finite() && days > 0.0)) {
+        return Err(AppError::InvalidRequest(
+            "recency_half_life_days must be a positive number".into(),
+        ));
+    }
+    let summary_class = payload
+        .summary_class
+        .as_deref()
+        .map(str::trim)
+        .filter(|class| !class.is_empty());
+    if let Some(class) = summary_class {
+        if !state
+            .vespa_summary_classes
+            .iter()
+            .any(|allowed| allowed == class)
+        {
+            return Err(AppError::InvalidRequest(format!(
+                "summary_class must be one of {} (got {class:?})",
+                state.vespa_summary_classes.join(", ")
+            )));
+        }
+    }
+
+    if state.search_backend == SearchBackend::Local {
+        let (mut results, total_count) = search_local_index(
+            state,
+            query,
+            search_mode,
+            &filters,
+            pinned_embedding,
+            page,
+            result_options,
+        )
+        .await?;
+        postprocess_results(&mut results, min_score, recency_half_life_days);
+        return Ok(SearchResponse {
+            results,
+            total_count,
+            total_count_exact: true,
+        });
+    }
+
+    let yql = build_search_yql(
+        search_mode,
+        extra_fields,
+        &filters.yql_clauses(),
+        summary_class.is_some(),
+    );
+    let search_url = vespa_search_url(state)?;
+    let query_text = match search_mode {
+        SearchMode::Path => path_search_tokens(query).join(" "),
+        _ => query.to_string(),
+    };
+    let mut body = serde_json::json!({
+        "yql": yql,
+        "hits": page.hits,
+        "offset": page.offset,
+        "query": query_text,
+    });
+    if let (Some(class), Some(object)) = (summary_class, body.as_object_mut()) {
+        object.insert("presentation.summary".to_string(), class.into());
+    }
+
+    if matches!(search_mode, SearchMode::Hybrid | SearchMode::Bm25) {
+        if let Some(object) = body.as_object_mut() {
+            object.insert("defaultIndex".to_string(), "content".into());
+        }
+    }
+
+    if let Some(profile) = search_mode.profile_name() {
+        if let Some(object) = body.as_object_mut() {
+            object.insert("ranking.profile".to_string(), profile.into());
+        }
+    }
+    if result_options.rank_features {
+        if let Some(object) = body.as_object_mut() {
+            object.insert("ranking.listFeatures".to_string(), true.into());
+        }
+    }
+    if matches!(search_mode, SearchMode::Hybrid | SearchMode::Semantic) {
+        let values = match pinned_embedding {
+            Some(values) => values,
+            None => {
+                let model = resolve_query_embedding_model(state, filters.repo_ids.as_deref()).await;
+                embed_query(state, &model, query).await?
+            }
+        };
+        let query_embedding = VespaEmbedding::new(values, VespaTensorEncoding::Json);
+        let embedding_value = serde_json::to_value(&query_embedding)?;
+        if let Some(object) = body.as_object_mut() {
+            object.insert("input.query(query_embedding)".to_string(), embedding_value);
+        }
+    }
+
+    let body = with_query_profile(state, body);
+    let response = state
+        .http_client
+        .post(search_url)
+        .json(&body)
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(AppError::VespaRejected(body));
+    }
+
+    let status = response.status();
+    let text = response.text().await?;
+    let body = parse_vespa_json(status, &text)?;
+    let total_count = body
+        .pointer("/root/fields/totalCount")
+        .and_then(|value| value.as_u64())
+        .unwrap_or(0);
+    let mut results = Vec::new();
+    if let Some(children) = body.pointer("/root/children").and_then(|v| v.as_array()) {
+        for child in children {
+            let fields = match child.get("fields") {
+                Some(fields) => fields,
+                None => continue,
+            };
+            let doc_id = child
+                .get("id")
+                .and_then(|value| value.as_str())
+                .and_then(|value| value.split_once("::"))
+                .map(|(_, doc_id)| doc_id.to_string());
+            let repo_id = fields
+                .get("repo_id")
+                .and_then(|value| value.as_str())
+                .unwrap_or_default()
+                .to_string();
+            let file_path = fields
+                .get("file_path")
+                .and_then(|value| value.as_str())
+                .unwrap_or_default()
+                .to_string();
+            let line_start = fields
+                .get("line_start")
+                .and_then(|value| value.as_i64())
+                .unwrap_or(1)
+                .max(1) as usize;
+            let line_end = fields
+                .get("line_end")
+                .and_then(|value| value.as_i64())
+                .unwrap_or(line_start as i64)
+                .max(1) as usize;
+            let content = fields
+                .get("content")
+                .and_then(|value| value.as_str())
+                .unwrap_or("");
+            let highlights = fields
+                .get("content_highlight")
+                .and_then(|value| value.as_str())
+                .map(parse_highlights)
+                .unwrap_or_default();
+            let snippet_query = if highlights.is_empty() {
+                query.to_string()
+            } else {
+                highlights.join(" ")
+            };
+            let snippet = build_snippet(
+                content,
+                StdPath::new(&file_path),
+                line_start,
+                &snippet_query,
+                result_options,
+            );
+            let (language, lines) = if result_options.rich {
+                let path = StdPath::new(&file_path);
+                (
+                    Some(guess_language(path)),
+                    Some(snippet_lines(
+                        content,
+                        path,
+                        line_start,
+                        &snippet_query,
+                        result_options,
+                    )),
+                )
+            } else {
+                (None, None)
+            };
+            let doc_type = fields
+                .get("doc_type")
+                .and_then(|value| value.as_str())
+                .map(|value| value.to_string());
+            let commit_sha = fields
+                .get("commit_sha")
+                .and_then(|value| value.as_str())
+                .filter(|value| !value.is_empty() && *value != "unknown")
+                .map(|value| value.to_string());
+            let blob_sha = fields
+                .get("blob_sha")
+                .and_then(|value| value.as_str())
+                .map(|value| value.to_string());
+            let committed_at = fields.get("committed_at").and_then(|value| value.as_i64());
+            let relevance = child
+                .get("relevance")
+                .and_then(|value| value.as_f64())
+                .unwrap_or(0.0);
+            let embedding_model = if include_embedding_model {
+                fields
+                    .get("embedding_model")
+                    .and_then(|value| value.as_str())
+                    .map(|value| value.to_string())
+            } else {
+                None
+            };
+            let explanation = result_options.explain.then(|| {
+                let features = fields
+                    .get("matchfeatures")
+                    .and_then(|value| value.as_object())
+                    .map(|features| {
+                        features
+                            .iter()
+                            .filter_map(|(name, value)| Some((name.clone(), value.as_f64()?)))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                ResultExplanation::new(relevance, features)
+            });
+            let rank_features = result_options
+                .rank_features
+                .then(|| selected_rank_features(fields));
+            let also_in = fields
+                .get("also_in")
+                .and_then(|value| value.as_array())
+                .map(|paths| {
+                    paths
+                        .iter()
+                        .filter_map(|path| path.as_str().map(str::to_string))
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            results.push(SearchResult {
+                doc_id,
+                repo_id,
+                file_path,
+                line_start,
+                line_end,
+                snippet,
+                doc_type,
+                commit_sha,
+                blob_sha,
+                committed_at,
+                embedding_model,
+                explanation,
+                score: relevance,
+                language,
+                lines,
+                highlights,
+                relevance: result_options.rank_features.then_some(relevance),
+                rank_features,
+                also_in,
+            });
+        }
+    }
+
+    if let Some(repo_ids) = filters.repo_ids.as_deref() {
+        results.retain(|result| repo_ids.contains(&result.repo_id));
+    }
+    let fetched = results.len();
+    postprocess_results(&mut results, min_score, recency_half_life_days);
+    let (total_count, total_count_exact) =
+        min_score_total_count(total_count, page.offset, fetched, results.len(), min_score);
+
+    Ok(SearchResponse {
+        results,
+        total_count,
+        total_count_exact,
+    })
+}
+
+fn selected_rank_features(fields: &serde_json::Value) -> BTreeMap<String, f64> {
+    ["matchfeatures", "rankfeatures"]
+        .iter()
+        .filter_map(|key| fields.get(*key).and_then(|value| value.as_object()))
+        .flatten()
+        .filter(|(name, _)| {
+            RANK_FEATURE_PREFIXES
+                .iter()
+                .any(|prefix| name.starts_with(prefix))
+        })
+        .filter_map(|(name, value)| Some((name.clone(), value.as_f64()?)))
+        .collect()
+}
+
+/// Vespa's `totalCount` ignores `min_score`, which only filters the returned page. Hits arrive in
+/// relevance order, so once the threshold drops a hit from this page no later hit passes either and
+/// the count is `offset + kept`; a kept hit also means every earlier page passed. If the page kept
+/// nothing, earlier pages may have dropped hits too and `offset` is only an upper bound, and if the
+/// whole page passed, later pages may still drop hits and Vespa's count is only an upper bound.
+fn min_score_total_count(
+    total_count: u64,
+    offset: usize,
+    fetched: usize,
+    kept: usize,
+    min_score: Option<f64>,
+) -> (u64, bool) {
+    if kept < fetched {
+        return ((offset + kept) as u64, kept > 0 || offset == 0);
+    }
+    (total_count, min_score.is_none())
+}
+
+fn postprocess_results(
+    results: &mut Vec<SearchResult>,
+    min_score: Option<f64>,
+    recency_half_life_days: Option<f64>,
+) {
+    if let Some(min_score) = min_score {
+        results.retain(|result| result.score >= min_score);
+    }
+    if let Some(half_life_days) = recency_half_life_days {
+        apply_recency_decay(results, half_life_days);
+    }
+}
+
+fn apply_recency_decay(results: &mut [SearchResult], half_life_days: f64) {
+    const MILLIS_PER_DAY: f64 = 86_400_000.0;
+    let now = Utc::now().timestamp_millis();
+    let decay = |committed_at: i64| {
+        let age_days = (now - committed_at).max(0) as f64 / MILLIS_PER_DAY;
+        0.5f64.powf(age_days / half_life_days)
+    };
+    let oldest = results
+        .iter()
+        .filter_map(|result| result.committed_at)
+        .min()
+        .map(decay)
+        .unwrap_or(1.0);
+    for result in results.iter_mut() {
+        result.score *= result.committed_at.map(decay).unwrap_or(oldest);
+    }
+    results.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+}
+
+async fn list_languages(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<LanguageCount>>, AppError> {
+    if let Some(cache) = state.language_cache.read().await.as_ref() {
+        if cache.fetched_at.elapsed() < LANGUAGE_CACHE_TTL {
+            return Ok(Json(cache.languages.clone()));
+        }
+    }
+
+    let mut languages = if state.search_backend == SearchBackend::Local {
+        let index = state.local_index.read().await;
+        let mut counts: HashMap<String, (usize, Vec<&str>)> = HashMap::new();
+        for document in index.documents.values() {
+            if document.doc_type != DOC_TYPE_CODE {
+                continue;
+            }
+            let entry = counts
+                .entry(guess_language(StdPath::new(&document.file_path)))
+                .or_default();
+            entry.0 += 1;
+            if !entry.1.contains(&document.repo_id.as_str()) {
+                entry.1.push(&document.repo_id);
+            }
+        }
+        counts
+            .into_iter()
+            .map(|(language, (document_count, repos))| LanguageCount {
+                language,
+                document_count,
+                repo_count: repos.len(),
+            })
+            .collect()
+    } else {
+        fetch_vespa_language_counts(&state).await?
+    };
+    languages.sort_by(|a, b| {
+        b.document_count
+            .cmp(&a.document_count)
+            .then_with(|| a.language.cmp(&b.language))
+    });
+
+    *state.language_cache.write().await = Some(LanguageCache {
+        fetched_at: std::time::Instant::now(),
+        languages: languages.clone(),
+    });
+    Ok(Json(languages))
+}
+
+async fn fetch_vespa_repo_document_counts(
+    state: &AppState,
+) -> Result<HashMap<String, u64>, AppError> {
+    let yql = "select * from sources * where true limit 0 \
+        | all(group(repo_id) max(100000) each(output(count())))";
+    let body = with_query_profile(state, serde_json::json!({ "yql": yql, "hits": 0 }));
+    let response = state
+        .http_client
+        .post(vespa_search_url(state)?)
+        .json(&body)
+        .send()
+        .await?;
+    let status = response.status();
+    let text = response.text().await?;
+    if !status.is_success() {
+        return Err(AppError::VespaRejected(text));
+    }
+    let body = parse_vespa_json(status, &text)?;
+    let groups = body
+        .pointer("/root/children/0/children/0/children")
+        .and_then(|value| value.as_array());
+    let mut counts = HashMap::new();
+    for group in groups.into_iter().flatten() {
+        let Some(repo_id) = group.get("value").and_then(|value| value.as_str()) else {
+            continue;
+        };
+        let count = group
+            .pointer("/fields/count()")
+            .and_then(|value| value.as_u64())
+            .unwrap_or(0);
+        counts.insert(repo_id.to_string(), count);
+    }
+    Ok(counts)
+}
+
+async fn fetch_vespa_language_counts(state: &AppState) -> Result<Vec<LanguageCount>, AppError> {
+    let yql = format!(
+        "select * from sources * where true and !(doc_type contains {}) and !(doc_type contains {}) limit 0 | all(group(language) max(200) each(output(count()) all(group(repo_id) max(10000) each(output(count())))))",
+        yql_quote(DOC_TYPE_COMMIT),
+        yql_quote(DOC_TYPE_RELEASE)
+    );
+    let body = with_query_profile(state, serde_json::json!({ "yql": yql, "hits": 0 }));
+    let response = state
+        .http_client
+        .post(vespa_search_url(state)?)
+        .json(&body)
+        .send()
+        .await?;
+    let status = response.status();
+    let text = response.text().await?;
+    if !status.is_success() {
+        return Err(AppError::VespaRejected(text));
+    }
+    let body = parse_vespa_json(status, &text)?;
+
+    let mut languages = Vec::new();
+    let language_groups = body
+        .pointer("/root/children/0/children/0/children")
+        .and_then(|value| value.as_array());
+    for group in language_groups.into_iter().flatten() {
+        let Some(language) = group.get("value").and_then(|value| value.as_str()) else {
+            continue;
+        };
+        let document_count = group
+            .pointer("/fields/count()")
+            .and_then(|value| value.as_u64())
+            .unwrap_or(0) as usize;
+        let repo_count = group
+            .pointer("/children/0/children")
+            .and_then(|value| value.as_array())
+            .map(|repos| repos.len())
+            .unwrap_or(0);
+        languages.push(LanguageCount {
+            language: language.to_string(),
+            document_count,
+            repo_count,
+        });
+    }
+    Ok(languages)
+}
+
+async fn prepare_data_root(
+    data_root: &StdPath,
+    registry_path: &StdPath,
+    repos_path: &StdPath,
+) -> Result<(), AppError> {
+    let not_writable = |path: &StdPath, err: std::io::Error| {
+        AppError::Config(format!(
+            "data root {} is not writable ({}: {err}); set DATA_DIR to a writable directory",
+            data_root.display(),
+            path.display()
+        ))
+    };
+    for dir in [registry_path.parent().unwrap_or(data_root), repos_path] {
+        fs::create_dir_all(dir)
+            .await
+            .map_err(|err| not_writable(dir, err))?;
+    }
+    let probe = registry_path.with_extension("write-check");
+    fs::write(&probe, b"")
+        .await
+        .map_err(|err| not_writable(&probe, err))?;
+    let _ = fs::remove_file(&probe).await;
+    Ok(())
+}
+
+async fn load_registry(
+    path: &StdPath,
+    repos_path: &StdPath,
+    layout: RepoPathLayout,
+) -> Result<Vec<RepoRecord>, AppError> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let contents = fs::read(path).await?;
+    let registry: Vec<RepoRecord> = serde_json::from_slice(&contents)?;
+    let total = registry.len();
+    let registry = dedupe_registry(registry, repos_path, layout).await;
+    if registry.len() < total {
+        warn!(
+            "collapsed {} duplicate registry entries in {}",
+            total - registry.len(),
+            path.display()
+        );
+        save_registry(path, &registry).await?;
+    }
+    Ok(registry)
+}
+
+async fn dedupe_registry(
+    registry: Vec<RepoRecord>,
+    repos_path: &StdPath,
+    layout: RepoPathLayout,
+) -> Vec<RepoRecord> {
+    let mut groups: Vec<(String, String)> = Vec::new();
+    let mut grouped: HashMap<(String, String), Vec<RepoRecord>> = HashMap::new();
+    for record in registry {
+        let key = (record.owner.to_lowercase(), record.name.to_lowercase());
+        if !grouped.contains_key(&key) {
+            groups.push(key.clone());
+        }
+        grouped.entry(key).or_default().push(record);
+    }
+
+    let mut deduped = Vec::with_capacity(groups.len());
+    for key in groups {
+        let mut records = grouped.remove(&key).unwrap_or_default();
+        if records.len() == 1 {
+            deduped.extend(records);
+            continue;
+        }
+        let mut keep = records.len() - 1;
+        let mut latest = None;
+        let mut seen_paths = HashSet::new();
+        for record in &records {
+            let repo_path = layout.repo_path(repos_path, record);
+            if !seen_paths.insert(repo_path.clone()) {
+                continue;
+            }
+            let Some((id, indexed_at)) = last_indexed_repo(&repo_path).await else {
+                continue;
+            };
+            let Some(position) = records.iter().position(|record| record.id == id) else {
+                continue;
+            };
+            if latest.is_none() || indexed_at > latest {
+                latest = indexed_at;
+                keep = position;
+            }
+        }
+        deduped.push(records.swap_remove(keep));
+    }
+    deduped
+}
+
+async fn last_indexed_repo(
+    repo_path: &StdPath,
+) -> Option<(String, Option<chrono::DateTime<chrono::FixedOffset>>)> {
+    for (state_path, timestamp_key) in [
+        (repo_path.join("vv").join("manifest.json"), "indexed_at"),
+        (repo_path.join(".vv").join("state.json"), "updated_at"),
+    ] {
+        let Ok(data) = fs::read(&state_path).await else {
+            continue;
+        };
+        let Ok(value) = serde_json::from_slice::<serde_json::Value>(&data) else {
+            continue;
+        };
+        let Some(id) = value.get("repo_id").and_then(|id| id.as_str()) else {
+            continue;
+        };
+        let indexed_at = value
+            .get(timestamp_key)
+            .and_then(|timestamp| timestamp.as_str())
+            .and_then(|timestamp| chrono::DateTime::parse_from_rfc3339(timestamp).ok());
+        return Some((id.to_string(), indexed_at));
+    }
+    None
+}
+
+async fn save_registry(path: &StdPath, registry: &[RepoRecord]) -> Result<(), AppError> {
+    let contents = serde_json::to_vec_pretty(registry)?;
+    fs::write(path, contents).await?;
+    Ok(())
+}
+
+async fn list_github_org_repos(state: &AppState, org: &str) -> Result<Vec<GitHubRepo>, AppError> {
+    let mut page = 1usize;
+    let mut repos = Vec::new();
+
+    loop {
+        let url = format!("https://api.github.com/orgs/{org}/repos?per_page=100&page={page}");
+        let mut request = state
+            .http_client
+            .get(&url)
+            .header("Accept", "application/vnd.github+json")
+            .header("User-Agent", "vespa-code-search");
+        if let Some(token) = state.github_token.as_deref() {
+            request = request.header("Authorization", format!("token {token}"));
+        }
+        let response = request.send().await?;
+        if let Some(err) = github_rate_limit(&response) {
+            return Err(err);
+        }
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(AppError::GitHub(format!(
+                "failed to list GitHub repos for {org}: {status} {body}"
+            )));
+        }
+
+        let page_repos: Vec<GitHubRepo> = response.json().await?;
+        let page_count = page_repos.len();
+        repos.extend(page_repos);
+        if page_count < 100 {
+            break;
+        }
+        page += 1;
+    }
+
+    Ok(repos)
+}
+
+async fn list_github_tree_files(
+    state: &AppState,
+    record: &RepoRecord,
+    token: &str,
+) -> Result<Vec<PathBuf>, AppError> {
+    let github_get = |url: String| {
+        state
+            .http_client
+            .get(url)
+            .header("Authorization", format!("token {token}"))
+            .header("Accept", "application/vnd.github+json")
+            .header("User-Agent", "vespa-code-search")
+    };
+    let repo_url = format!(
+        "https://api.github.com/repos/{}/{}",
+        record.owner, record.name
+    );
+    let response = github_get(repo_url).send().await?;
+    if let Some(err) = github_rate_limit(&response) {
+        return Err(err);
+    }
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(AppError::GitHub(format!(
+            "repo lookup for {}/{} failed ({status}): {body}",
+            record.owner, record.name
+        )));
+    }
+    let details: GitHubRepoDetails = response
+        .json()
+        .await
+        .map_err(|err| AppError::GitHub(err.to_string()))?;
+    let branch = details.default_branch.unwrap_or_else(|| "HEAD".to_string());
+
+    let tree_url = format!(
+        "https://api.github.com/repos/{}/{}/git/trees/{}?recursive=1",
+        record.owner,
+        record.name,
+        urlencoding::encode(&branch)
+    );
+    let response = github_get(tree_url).send().await?;
+    if let Some(err) = github_rate_limit(&response) {
+        return Err(err);
+    }
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(AppError::GitHub(format!(
+            "tree listing for {}/{}@{branch} failed ({status}): {body}",
+            record.owner, record.name
+        )));
+    }
+    let tree: GitHubTree = response
+        .json()
+        .await
+        .map_err(|err| AppError::GitHub(err.to_string()))?;
+    if tree.truncated {
+        warn!(
+            "GitHub tree for {}/{}@{} is truncated; file list is incomplete",
+            record.owner, record.name, branch
+        );
+    }
+    Ok(tree
+        .tree
+        .into_iter()
+        .filter(|entry| entry.kind == "blob")
+        .filter(|entry| entry.path != ".vv" && !entry.path.starts_with(".vv/"))
+        .map(|entry| PathBuf::from(entry.path))
+        .collect())
+}
+
+async fn healthz(State(state): State<AppState>) -> (StatusCode, Json<HealthResponse>) {
+    let (vespa, github) = tokio::join!(check_vespa_health(&state), check_github_health(&state));
+    let healthy = [&vespa, &github]
+        .iter()
+        .all(|component| matches!(component.as_str(), "ok" | "skipped"));
+    let status = if healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(HealthResponse { vespa, github }))
+}
+
+async fn check_vespa_health(state: &AppState) -> String {
+    if state.search_backend == SearchBackend::Local {
+        return "skipped".into();
+    }
+    if state.vespa_endpoint.trim().is_empty() {
+        return "error: VESPA_ENDPOINT is not set".into();
+    }
+    let url = format!(
+        "{}/state/v1/health",
+        state.vespa_endpoint.trim_end_matches('/')
+    );
+    let response = state
+        .http_client
+        .get(url)
+        .timeout(HEALTH_CHECK_TIMEOUT)
+        .send()
+        .await;
+    match response {
+        Ok(response) if response.status().is_success() => {
+            let body: serde_json::Value = response.json().await.unwrap_or_default();
+            match body
+                .pointer("/status/code")
+                .and_then(|value| value.as_str())
+            {
+                None | Some("up") => "ok".into(),
+                Some(code) => format!("error: status {code}"),
+            }
+        }
+        Ok(response) => format!("error: HTTP {}", response.status()),
+        Err(err) => format!("error: {}", describe_vespa_error(&err)),
+    }
+}
+
+async fn check_github_health(state: &AppState) -> String {
+    let Some(token) = state.github_token.as_deref() else {
+        return "skipped".into();
+    };
+    let response = state
+        .http_client
+        .get("https://api.github.com/rate_limit")
+        .header("Authorization", format!("token {token}"))
+        .header("Accept", "application/vnd.github+json")
+        .header("User-Agent", "vespa-code-search")
+        .timeout(HEALTH_CHECK_TIMEOUT)
+        .send()
+        .await;
+    match response {
+        Ok(response) if response.status().is_success() => "ok".into(),
+        Ok(response) => format!("error: HTTP {}", response.status()),
+        Err(err) => format!("error: {err}"),
+    }
+}
+
+async fn resolve_canonical_repo_name(
+    state: &AppState,
+    owner: String,
+    name: String,
+) -> (String, String) {
+    let Some(token) = state.github_token.as_deref() else {
+        return (owner, name);
+    };
+    let url = format!("https://api.github.com/repos/{owner}/{name}");
+    let response = state
+        .http_client
+        .get(&url)
+        .header("Authorization", format!("token {token}"))
+        .header("Accept", "application/vnd.github+json")
+        .header("User-Agent", "vespa-code-search")
+        .send()
+        .await;
+    let details = match response {
+        Ok(response) if response.status().is_success() => {
+            response.json::<GitHubRepoDetails>().await.ok()
+        }
+        Ok(response) => {
+            warn!(
+                "GitHub lookup for {owner}/{name} returned {}; using parsed owner/name",
+                response.status()
+            );
+            None
+        }
+        Err(err) => {
+            warn!("GitHub lookup for {owner}/{name} failed ({err}); using parsed owner/name");
+            None
+        }
+    };
+    match details {
+        Some(details) if !details.owner.login.is_empty() && !details.name.is_empty() => {
+            if details.owner.login != owner || details.name != name {
+                info!(
+                    "resolved {owner}/{name} to canonical {}/{}",
+                    details.owner.login, details.name
+                );
+            }
+            (details.owner.login, details.name)
+        }
+        _ => (owner, name),
+    }
+}
+
+async fn fetch_github_repo_state(
+    state: &AppState,
+    org: &str,
+    repo: &GitHubRepo,
+) -> Result<Option<RepoRecord>, AppError> {
+    let Some(branch) = repo
+        .default_branch
+        .as_deref()
+        .filter(|branch| !branch.is_empty())
+    else {
+        return Ok(None);
+    };
+    let url = format!(
+        "https://raw.githubusercontent.com/{org}/{}/{}/.vv/state.json",
+        repo.name, branch
+    );
+    let mut request = state
+        .http_client
+        .get(&url)
+        .header("User-Agent", "vespa-code-search");
+    if let Some(token) = state.github_token.as_deref() {
+        request = request.header("Authorization", format!("token {token}"));
+    }
+    let response = request
+        .send()
+        .await
+        .map_err(|err| AppError::HuggingFace(err.to_string()))?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if let Some(err) = github_rate_limit(&response) {
+        return Err(err);
+    }
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(AppError::GitHub(format!(
+            "failed to fetch .vv state from {url}: {status} {body}"
+        )));
+    }
+
+    let payload = match response.json::<GitHubRepoState>().await {
+        Ok(payload) => payload,
+        Err(err) => {
+            warn!("failed to parse .vv state from {url}: {err}");
+            return Ok(None);
+        }
+    };
+    if payload.repo_id.is_empty() {
+        return Ok(None);
+    }
+
+    let host = parse_repo_url(&payload.repo_url)
+        .map(|(host, _, _)| host)
+        .unwrap_or_else(|_| default_repo_host());
+    Ok(Some(RepoRecord {
+        id: payload.repo_id,
+        repo_url: payload.repo_url,
+        host,
+        owner: payload.owner,
+        name: payload.name,
+        tags: Vec::new(),
+        include_generated: None,
+        embedding_model: None,
+        branch: None,
+        commit: None,
+    }))
+}
+
+async fn sync_registry_from_github(state: &AppState) -> Result<usize, AppError> {
+    let org = match state.github_org.as_deref() {
+        Some(org) => org,
+        None => return Ok(0),
+    };
+
+    let repos =
+        retry_github_rate_limited("listing org repos", || list_github_org_repos(state, org))
+            .await?;
+    let mut records = Vec::new();
+    let mut plain_repos = Vec::new();
+    for repo in repos {
+        if !repo.name.ends_with("-vv-search") {
+            if state.sync_all_org_repos {
+                plain_repos.push(repo);
+            }
+            continue;
+        }
+        let fetched =
+            retry_github_rate_limited(&repo.name, || fetch_github_repo_state(state, org, &repo))
+                .await;
+        match fetched {
+            Ok(Some(record)) => records.push(record),
+            Ok(None) => {}
+            Err(err @ AppError::GitHubRateLimited(_)) => return Err(err),
+            Err(err) => warn!("failed to read vv state for {}: {}", repo.name, err),
+        }
+    }
+    if !plain_repos.is_empty() {
+        let registry = state.registry.read().await;
+        let known: HashSet<(String, String)> = registry
+            .iter()
+            .chain(records.iter())
+            .map(|record| (record.owner.to_lowercase(), record.name.to_lowercase()))
+            .collect();
+        for repo in plain_repos {
+            if known.contains(&(org.to_lowercase(), repo.name.to_lowercase())) {
+                continue;
+            }
+            records.push(org_repo_record(org, &repo.name));
+        }
+    }
+
+    if records.is_empty() {
+        return Ok(0);
+    }
+
+    let mut registry = state.registry.write().await;
+    let mut index = HashMap::new();
+    for (idx, record) in registry.iter().enumerate() {
+        index.insert(record.id.clone(), idx);
+    }
+
+    let mut changes = 0usize;
+    for record in records {
+        if let Some(&idx) = index.get(&record.id) {
+            let existing = &mut registry[idx];
+            if existing.host != GITHUB_HOST {
+                continue;
+            }
+            if existing.repo_url != record.repo_url
+                || existing.owner != record.owner
+                || existing.name != record.name
+            {
+                existing.repo_url = record.repo_url;
+                existing.owner = record.owner;
+                existing.name = record.name;
+                changes += 1;
+            }
+        } else {
+            index.insert(record.id.clone(), registry.len());
+            registry.push(record);
+            changes += 1;
+        }
+    }
+
+    if changes > 0 {
+        save_registry(&state.registry_path, &registry).await?;
+    }
+
+    Ok(changes)
+}
+
+fn org_repo_record(org: &str, name: &str) -> RepoRecord {
+    let digest = Sha256::digest(format!("{}/{}", org, name).to_lowercase().as_bytes());
+    let id = Uuid::from_slice(&digest[..16])
+        .map(|id| id.to_string())
+        .unwrap_or_else(|_| hex::encode(&digest[..16]));
+    RepoRecord {
+        id,
+        repo_url: format!("https://github.com/{org}/{name}"),
+        host: default_repo_host(),
+        owner: org.to_string(),
+        name: name.to_string(),
+        tags: Vec::new(),
+        include_generated: None,
+        embedding_model: None,
+        branch: None,
+        commit: None,
+    }
+}
+
+async fn find_repo_by_id(state: &AppState, id: &str) -> Result<RepoRecord, AppError> {
+    {
+        let registry = state.registry.read().await;
+        if let Some(record) = registry.iter().find(|repo| repo.id == id) {
+            return Ok(record.clone());
+        }
+    }
+
+    if state.github_org.is_some() {
+        if let Err(err) = sync_registry_from_github(state).await {
+            warn!("failed to refresh registry from GitHub: {err}");
+        }
+        let registry = state.registry.read().await;
+        if let Some(record) = registry.iter().find(|repo| repo.id == id) {
+            return Ok(record.clone());
+        }
+    }
+
+    Err(AppError::RepoNotFound)
+}
+
+async fn write_status(
+    state: &AppState,
+    vv_path: &StdPath,
+    repo_id: &str,
+    status: &str,
+    message: Option<String>,
+) -> Result<(), AppError> {
+    write_status_with_progress(state, vv_path, repo_id, status, message, None).await
+}
+
+async fn write_status_with_progress(
+    state: &AppState,
+    vv_path: &StdPath,
+    repo_id: &str,
+    status: &str,
+    message: Option<String>,
+    progress: Option<IngestProgress>,
+) -> Result<(), AppError> {
+    let payload = StatusResponse {
+        status: status.into(),
+        message,
+        progress,
+        summary_error: None,
+    };
+    write_status_payload(state, vv_path, repo_id, payload).await
+}
+
+async fn write_status_payload(
+    state: &AppState,
+    vv_path: &StdPath,
+    repo_id: &str,
+    payload: StatusResponse,
+) -> Result<(), AppError> {
+    fs::create_dir_all(vv_path).await?;
+    fs::write(
+        vv_path.join("status.json"),
+        serde_json::to_vec_pretty(&payload)?,
+    )
+    .await?;
+    publish_event(
+        state,
+        repo_id,
+        &payload.status,
+        payload.message,
+        payload.progress,
+    )
+    .await;
+    Ok(())
+}
+
+async fn publish_event(
+    state: &AppState,
+    repo_id: &str,
+    status: &str,
+    message: Option<String>,
+    progress: Option<IngestProgress>,
+) {
+    let event = IngestEvent {
+        repo_id: repo_id.to_string(),
+        status: status.to_string(),
+        message,
+        progress,
+        timestamp: Utc::now().timestamp_millis(),
+    };
+    let mut history = state.event_history.write().await;
+    let events = history.entry(repo_id.to_string()).or_default();
+    if events.len() >= EVENT_HISTORY_LIMIT {
+        events.pop_front();
+    }
+    events.push_back(event.clone());
+    let _ = state.status_tx.send(event);
+}
+
+async fn read_status(vv_path: &StdPath) -> Result<StatusResponse, AppError> {
+    let path = vv_path.join("status.json");
+    if fs::metadata(&path).await.is_err() {
+        let chunks_path = vv_path.join("chunks.jsonl");
+        if let Ok(metadata) = fs::metadata(&chunks_path).await {
+            if metadata.len() > 0 {
+                return Ok(StatusResponse {
+                    status: "complete".into(),
+                    message: Some("Ingestion complete (status recovered).".into()),
+                    progress: None,
+                    summary_error: None,
+                });
+            }
+        }
+
+        let wiki_path = vv_path.join("wiki/index.md");
+        if fs::metadata(&wiki_path).await.is_ok() {
+            return Ok(StatusResponse {
+                status: "unknown".into(),
+                message: Some(
+                    "Ingestion artifacts found, but status is unavailable. Re-run ingestion to refresh."
+                        .into(),
+                ),
+                progress: None,
+                summary_error: None,
+            });
+        }
+
+        return Ok(StatusResponse {
+            status: "unknown".into(),
+            message: Some(
+                "Status not available on this instance. Re-run ingestion if needed.".into(),
+            ),
+            progress: None,
+            summary_error: None,
+        });
+    }
+
+    let data = fs::read(path).await?;
+    let mut status: StatusResponse = serde_json::from_slice(&data)?;
+    if status.message.is_none() {
+        status.message = Some(match status.status.as_str() {
+            "complete" => "Ingestion complete.".into(),
+            "in_progress" => "Ingestion in progress.".into(),
+            "error" => "Ingestion failed. Check backend logs.".into(),
+            _ => "Status unavailable. Re-run ingestion if needed.".into(),
+        });
+    }
+    Ok(status)
+}
+
+async fn read_summary_store(vv_path: &StdPath) -> Result<SummaryStore, AppError> {
+    let summary_path = vv_path.join("wiki/summary.json");
+    if fs::metadata(&summary_path).await.is_err() {
+        return Ok(SummaryStore::default());
+    }
+    let data = fs::read(&summary_path).await?;
+    let store = serde_json::from_slice::<SummaryStore>(&data)?;
+    Ok(store)
+}
+
+async fn write_summary_store(vv_path: &StdPath, store: &SummaryStore) -> Result<(), AppError> {
+    let summary_path = vv_path.join("wiki/summary.json");
+    fs::create_dir_all(summary_path.parent().unwrap()).await?;
+    let data = serde_json::to_vec_pretty(store)?;
+    fs::write(summary_path, data).await?;
+    Ok(())
+}
+
+async fn run_git_command(
+    cwd: Option<&StdPath>,
+    args: &[&str],
+) -> Result<std::process::Output, AppError> {
+    run_git_command_with_env(cwd, args, &[]).await
+}
+
+async fn run_git_command_with_env(
+    cwd: Option<&StdPath>,
+    args: &[&str],
+    envs: &[(&str, &str)],
+) -> Result<std::process::Output, AppError> {
+    let mut command = Command::new("git");
+    command.env("GIT_TERMINAL_PROMPT", "0");
+    command.envs(envs.iter().copied());
+    if let Some(path) = cwd {
+        command.arg("-C").arg(path);
+    }
+    command.args(args);
+    command.output().await.map_err(AppError::Io)
+}
+
+async fn ensure_github_repo(
+    state: &AppState,
+    org: &str,
+    token: &str,
+    repo_name: &str,
+) -> Result<(), AppError> {
+    let response = state
+        .http_client
+        .post(format!("https://api.github.com/orgs/{org}/repos"))
+        .header("Authorization", format!("token {token}"))
+        .header("Accept", "application/vnd.github+json")
+        .header("User-Agent", "vespa-code-search")
+        .json(&serde_json::json!({
+            "name": repo_name,
+            "private": false,
+        }))
+        .send()
+        .await?;
+
+    if response.status().is_success() {
+        return Ok(());
+    }
+
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    if status == StatusCode::UNPROCESSABLE_ENTITY && body.contains("name already exists") {
+        return Ok(());
+    }
+
+    Err(AppError::GitHub(format!(
+        "failed to create GitHub repo {org}/{repo_name}: {status} {body}"
+    )))
+}
+
+async fn mirror_repo_to_github(
+    state: &AppState,
+    record: &RepoRecord,
+    repo_path: &StdPath,
+) -> Result<(), AppError> {
+    let org = state
+        .github_org
+        .as_deref()
+        .ok_or_else(|| AppError::Config("GITHUB_ORG is required for repo mirroring".into()))?;
+    let token = state
+        .github_token
+        .as_deref()
+        .ok_or_else(|| AppError::Config("GITHUB_TOKEN is required for repo mirroring".into()))?;
+    let mirror_name = format!("{}-vv-search", record.name);
+
+    ensure_github_repo(state, org, token, &mirror_name).await?;
+
+    let remote_url = format!(
+        "https://x-access-token:{}@github.com/{}/{}.git",
+        token, org, mirror_name
+    );
+
+    let _ = run_git_command(Some(repo_path), &["remote", "remove", "mirror"]).await;
+    let output =
+        run_git_command(Some(repo_path), &["remote", "add", "mirror", &remote_url]).await?;
+    if !output.status.success() {
+        return Err(AppError::GitHub(
+            "failed to add mirror remote for GitHub".into(),
+        ));
+    }
+
+    let output = run_git_command(Some(repo_path), &["push", "--mirror", "mirror"]).await?;
+    if !output.status.success() {
+        return Err(AppError::GitHub("failed to push mirror to GitHub".into()));
+    }
+
+    Ok(())
+}
+
+async fn write_vv_state(repo_path: &StdPath, record: &RepoRecord) -> Result<PathBuf, AppError> {
+    let vv_path = repo_path.join(".vv");
+    fs::create_dir_all(&vv_path).await?;
+    let payload = serde_json::json!({
+        "repo_id": record.id,
+        "repo_url": record.repo_url,
+        "owner": record.owner,
+        "name": record.name,
+        "mirror_repo": format!("{}-vv-search", record.name),
+        "updated_at": Utc::now().to_rfc3339(),
+    });
+    let state_path = vv_path.join("state.json");
+    fs::write(&state_path, serde_json::to_vec_pretty(&payload)?).await?;
+    Ok(state_path)
+}
+
+async fn commit_vv_state(repo_path: &StdPath, state_path: &StdPath) -> Result<(), AppError> {
+    if is_bare_repo(repo_path) {
+        return commit_vv_state_bare(repo_path, state_path).await;
+    }
+    let _ = run_git_command(
+        Some(repo_path),
+        &["config", "user.email", "vv-search@users.noreply.github.com"],
+    )
+    .await?;
+    let _ = run_git_command(Some(repo_path), &["config", "user.name", "vv-search"]).await?;
+
+    let state_path_str = state_path.to_string_lossy();
+    let output = run_git_command(Some(repo_path), &["add", "-f", state_path_str.as_ref()]).await?;
+    if !output.status.success() {
+        return Err(AppError::GitHub("failed to stage .vv state file".into()));
+    }
+
+    let diff_output = run_git_command(Some(repo_path), &["diff", "--cached", "--quiet"]).await?;
+    if diff_output.status.code() == Some(0) {
+        return Ok(());
+    }
+    if diff_output.status.code() != Some(1) {
+        return Err(AppError::GitHub(
+            "failed to inspect staged changes for .vv state".into(),
+        ));
+    }
+
+    let output = run_git_command(
+        Some(repo_path),
+        &[
+            "commit",
+            "-m",
+            "chore: update vv state",
+            "--",
+            state_path_str.as_ref(),
+        ],
+    )
+    .await?;
+    if !output.status.success() {
+        return Err(AppError::GitHub("failed to commit .vv state file".into()));
+    }
+
+    Ok(())
+}
+
+async fn commit_vv_state_bare(repo_path: &StdPath, state_path: &StdPath) -> Result<(), AppError> {
+    let git_output = |output: std::process::Output, step: &str| {
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        } else {
+            Err(AppError::GitHub(format!(
+                "failed to {step} for .vv state: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )))
+        }
+    };
+
+    let state_path_str = state_path.to_string_lossy();
+    let blob = git_output(
+        run_git_command(
+            Some(repo_path),
+            &["hash-object", "-w", state_path_str.as_ref()],
+        )
+        .await?,
+        "hash state file",
+    )?;
+    let index_path = repo_path.join("vv-state.index");
+    let index_path_str = index_path.to_string_lossy().to_string();
+    let envs = [
+        ("GIT_INDEX_FILE", index_path_str.as_str()),
+        ("GIT_AUTHOR_NAME", "vv-search"),
+        ("GIT_AUTHOR_EMAIL", "vv-search@users.noreply.github.com"),
+        ("GIT_COMMITTER_NAME", "vv-search"),
+        ("GIT_COMMITTER_EMAIL", "vv-search@users.noreply.github.com"),
+    ];
+    let result = async {
+        git_output(
+            run_git_command_with_env(Some(repo_path), &["read-tree", "HEAD"], &envs).await?,
+            "read HEAD tree",
+        )?;
+        let cacheinfo = format!("100644,{blob},.vv/state.json");
+        git_output(
+            run_git_command_with_env(
+                Some(repo_path),
+                &["update-index", "--add", "--cacheinfo", &cacheinfo],
+                &envs,
+            )
+            .await?,
+            "stage state file",
+        )?;
+        let tree = git_output(
+            run_git_command_with_env(Some(repo_path), &["write-tree"], &envs).await?,
+            "write tree",
+        )?;
+        let head_tree = git_output(
+            run_git_command(Some(repo_path), &["rev-parse", "HEAD^{tree}"]).await?,
+            "resolve HEAD tree",
+        )?;
+        if tree == head_tree {
+            return Ok(());
+        }
+        let commit = git_output(
+            run_git_command_with_env(
+                Some(repo_path),
+                &[
+                    "commit-tree",
+                    &tree,
+                    "-p",
+                    "HEAD",
+                    "-m",
+                    "chore: update vv state",
+                ],
+                &envs,
+            )
+            .await?,
+            "commit state file",
+        )?;
+        git_output(
+            run_git_command(Some(repo_path), &["update-ref", "HEAD", &commit]).await?,
+            "update HEAD",
+        )?;
+        Ok(())
+    }
+    .await;
+    fs::remove_file(&index_path).await.ok();
+    result
+}
+
+async fn ingest_repo(
+    state: AppState,
+    record: RepoRecord,
+    repo_path: PathBuf,
+    vv_path: PathBuf,
+) -> Result<(), AppError> {
+    write_status(
+        &state,
+        &vv_path,
+        &record.id,
+        "in_progress",
+        Some("Cloning repository".into()),
+    )
+    .await?;
+
+    let archive = is_archive_url(&record.repo_url);
+    if archive {
+        if !state.allow_archive_ingest {
+            return Err(AppError::Config(
+                "archive ingest is disabled (set ALLOW_ARCHIVE_INGEST=true)".into(),
+            ));
+        }
+        fetch_archive(&state, &record, &repo_path, &vv_path).await?;
+    } else {
+        if repo_path.exists() && !repo_path.join(".git").exists() && !is_bare_repo(&repo_path) {
+            if is_dir_empty(&repo_path).await? {
+                fs::remove_dir(&repo_path).await?;
+            } else if dir_contains_only_vv(&repo_path).await? {
+                warn!(
+                    "repo path {} contains only vv artifacts, removing for re-clone",
+                    repo_path.display()
+                );
+                fs::remove_dir_all(&vv_path).await.ok();
+                if is_dir_empty(&repo_path).await? {
+                    fs::remove_dir(&repo_path).await?;
+                }
+            }
+
+            if repo_path.exists() {
+                write_status(
+                    &state,
+                    &vv_path,
+                    &record.id,
+                    "error",
+                    Some("Repo path exists but is not a git repository".into()),
+                )
+                .await?;
+                return Err(AppError::Io(std::io::Error::other(
+                    "repo path exists but is not a git repository",
+                )));
+            }
+        }
+
+        if !repo_path.exists() {
+            ensure_free_disk_space(&state, &record, &vv_path).await?;
+            fs::create_dir_all(repo_path.parent().unwrap()).await?;
+            let repo_path_str = repo_path.to_string_lossy();
+            let mut clone_args = vec!["clone"];
+            if state.git_bare_clone {
+                clone_args.push("--bare");
+            }
+            clone_args.extend([record.repo_url.as_str(), repo_path_str.as_ref()]);
+            let output = run_git_command(None, &clone_args).await?;
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                let message = format!("Git clone failed: {}", stderr.trim());
+                write_status(&state, &vv_path, &record.id, "error", Some(message)).await?;
+                return Err(AppError::Io(std::io::Error::other("git clone failed")));
+            }
+        }
+
+        if let Some(git_ref) = record.commit.as_deref().or(record.branch.as_deref()) {
+            if let Err(message) = checkout_repo_ref(&repo_path, &record, git_ref).await {
+                write_status(&state, &vv_path, &record.id, "error", Some(message)).await?;
+                return Err(AppError::Io(std::io::Error::other("git checkout failed")));
+            }
+        }
+    }
+
+    if !archive && repo_is_empty(&repo_path).await {
+        info!("repo {} has no commits; skipping indexing", record.id);
+        write_status(
+            &state,
+            &vv_path,
+            &record.id,
+            "complete",
+            Some("Ingestion complete (empty repo)".into()),
+        )
+        .await?;
+        return Ok(());
+    }
+    let branch = record_branch(&record, &repo_path).await;
+    info!("ingesting repo {} on branch {}", record.id, branch);
+
+    if archive {
+        info!(
+            "skipping vv state commit and GitHub mirror for archive repo {}",
+            record.id
+        );
+    } else {
+        let vv_state_path = write_vv_state(&repo_path, &record).await?;
+        commit_vv_state(&repo_path, &vv_state_path).await?;
+
+        if record.host != GITHUB_HOST {
+            info!(
+                "skipping GitHub mirror for repo {} hosted on {}",
+                record.id, record.host
+            );
+        } else if state.search_backend == SearchBackend::Local
+            && (state.github_org.is_none() || state.github_token.is_none())
+        {
+            info!(
+                "skipping GitHub mirror for repo {} (local backend without GitHub credentials)",
+                record.id
+            );
+        } else {
+            write_status(
+                &state,
+                &vv_path,
+                &record.id,
+                "mirroring",
+                Some("Mirroring repository to GitHub".into()),
+            )
+            .await?;
+            mirror_repo_to_github(&state, &record, &repo_path).await?;
+        }
+    }
+
+    fs::create_dir_all(&vv_path).await?;
+    fs::create_dir_all(vv_path.join("vectors")).await?;
+    fs::create_dir_all(vv_path.join("wiki")).await?;
+
+    let license = detect_license(&repo_path).await;
+    let manifest = serde_json::json!({
+        "repo_id": record.id,
+        "repo_url": record.repo_url,
+        "owner": record.owner,
+        "name": record.name,
+        "branch": branch,
+        "license_spdx": license,
+        "indexed_at": Utc::now().to_rfc3339(),
+    });
+    fs::write(
+        vv_path.join("manifest.json"),
+        serde_json::to_vec_pretty(&manifest)?,
+    )
+    .await?;
+
+    let license_spdx = license.as_deref().unwrap_or("unknown");
+
+    let wiki_path = vv_path.join("wiki/index.md");
+    if state.wiki_markdown_mode == WikiMarkdownMode::Overwrite || !wiki_path.exists() {
+        fs::write(wiki_path, placeholder_wiki_markdown(&record)).await?;
+    }
+
+    write_status(
+        &state,
+        &vv_path,
+        &record.id,
+        "indexing",
+        Some("Feeding documents to Vespa".into()),
+    )
+    .await?;
+    let stats = feed_repo_to_vespa(&state, &record, &repo_path, &vv_path, license_spdx).await?;
+    info!(
+        "vespa feed completed for repo {} ({} documents: {} added, {} updated, {} unchanged, {} deleted, {} failed; {} generated files skipped, {} duplicate chunks, {} files over the chunk limit)",
+        record.id,
+        stats.indexed,
+        stats.added,
+        stats.updated,
+        stats.skipped,
+        stats.deleted,
+        stats.failed,
+        stats.skipped_generated,
+        stats.deduplicated,
+        stats.overflowed
+    );
+    record_feed_stats(&vv_path, &stats).await?;
+
+    if state.verify_feed && state.search_backend == SearchBackend::Vespa {
+        write_status(
+            &state,
+            &vv_path,
+            &record.id,
+            "indexing",
+            Some("Verifying fed documents".into()),
+        )
+        .await?;
+        verify_vespa_feed(&state, &record, &vv_path, stats.indexed).await?;
+    }
+
+    if state.index_commits && !archive {
+        write_status(
+            &state,
+            &vv_path,
+            &record.id,
+            "indexing",
+            Some("Indexing commit messages".into()),
+        )
+        .await?;
+        let commits =
+            feed_commits_to_index(&state, &record, &repo_path, &vv_path, license_spdx).await?;
+        info!("indexed {} commit messages for repo {}", commits, record.id);
+    }
+
+    if state.index_releases && record.host == GITHUB_HOST {
+        if let Some(token) = state.github_token.as_deref() {
+            write_status(
+                &state,
+                &vv_path,
+                &record.id,
+                "indexing",
+                Some("Indexing release notes".into()),
+            )
+            .await?;
+            match feed_releases_to_index(&state, &record, &vv_path, token, license_spdx).await {
+                Ok(releases) => {
+                    info!("indexed {} release notes for repo {}", releases, record.id)
+                }
+                Err(err) => warn!("release indexing failed for repo {}: {}", record.id, err),
+            }
+        } else {
+            info!(
+                "skipping release indexing for repo {}: GITHUB_TOKEN is not set",
+                record.id
+            );
+        }
+    }
+
+    write_status(
+        &state,
+        &vv_path,
+        &record.id,
+        "summarizing",
+        Some("Generating repository summary".into()),
+    )
+    .await?;
+    if let Err(err) = gc_vectors_cache(&state, &record, &vv_path).await {
+        warn!("vectors cache gc failed for repo {}: {}", record.id, err);
+    }
+
+    let mut summary_error = None;
+    if let Err(err) = generate_repo_summary(&state, &record, &repo_path, &vv_path).await {
+        warn!("failed to generate summary for repo {}: {}", record.id, err);
+        match state.summary_failure_mode {
+            SummaryFailureMode::Ignore => {}
+            SummaryFailureMode::WarnStatus => {
+                write_manifest_field(
+                    &vv_path,
+                    "summary_error",
+                    serde_json::Value::String(err.to_string()),
+                )
+                .await?;
+                summary_error = Some(err.to_string());
+            }
+            SummaryFailureMode::Fail => return Err(err),
+        }
+    }
+
+    write_status_payload(
+        &state,
+        &vv_path,
+        &record.id,
+        StatusResponse {
+            status: "complete".into(),
+            message: Some(format!(
+                "Ingestion complete: indexed {} changed chunks, skipped {} unchanged, deleted {}, {} failed",
+                stats.added + stats.updated,
+                stats.skipped,
+                stats.deleted,
+                stats.failed
+            )),
+            progress: None,
+            summary_error,
+        },
+    )
+    .await?;
+
+    Ok(())
+}
+
+async fn feed_repo_to_vespa(
+    state: &AppState,
+    record: &RepoRecord,
+    repo_path: &StdPath,
+    vv_path: &StdPath,
+    license_spdx: &str,
+) -> Result<FeedStats, AppError> {
+    let files = list_repo_files(repo_path, &state.index_ignore_patterns).await?;
+    let total = files.len();
+    let skip_generated = !record.include_generated.unwrap_or(false);
+    let branch = record_branch(record, repo_path).await;
+    let commit_sha = repo_head_sha(repo_path).await;
+    let blob_shas = repo_blob_shas(repo_path).await;
+    let commit_times = repo_file_commit_times(repo_path).await;
+    let embedding_model = repo_embedding_model(state, record);
+    let mut stats = FeedStats::default();
+
+    let chunks_path = vv_path.join("chunks.jsonl");
+    let indexed = repo_indexed_in_vespa(state, &record.id)
+        .await
+        .unwrap_or(false);
+    let mut previous = if indexed {
+        read_previous_chunks(&chunks_path).await
+    } else {
+        HashMap::new()
+    };
+    // Without a previous manifest the stale chunks are found by listing the repo's documents.
+    let sweep_stale = indexed
+        && previous.is_empty()
+        && state.search_backend == SearchBackend::Vespa
+        && !state.index_commit_history;
+    let mut fed_doc_ids = HashSet::new();
+    let previous_duplicates = chunk_duplicates(previous.values());
+    let mut canonical_chunks: HashMap<String, (String, String)> = HashMap::new();
+    let mut duplicates: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut refed_chunks = HashSet::new();
+    // The new manifest only replaces chunks.jsonl once the feed and stale-chunk cleanup succeed, so
+    // a failed or interrupted run still knows which chunks the index holds.
+    let pending_chunks_path = vv_path.join("chunks.jsonl.tmp");
+    let mut chunks_file = fs::File::create(&pending_chunks_path).await?;
+    let mut embedding_dump = if state.debug_dump_embeddings {
+        Some(EmbeddingDump::create(vv_path).await?)
+    } else {
+        None
+    };
+    let ctx = FeedContext {
+        state,
+        record,
+        vv_path,
+        embedding_model,
+        commit_sha: commit_sha.clone(),
+        branch,
+        embed_permits: Semaphore::new(state.embed_concurrency),
+        feed_permits: Semaphore::new(state.vespa_feed_concurrency),
+        keep_embeddings: embedding_dump.is_some(),
+        license_spdx,
+    };
+    let pipeline_depth = state.embed_concurrency + state.vespa_feed_concurrency;
+    let mut pending = stream::FuturesOrdered::new();
+
+    let mut interrupted = false;
+    for (processed, file_path) in files.into_iter().enumerate() {
+        if state.shutting_down.load(Ordering::SeqCst) {
+            interrupted = true;
+            break;
+        }
+        if processed > 0 && processed % PROGRESS_PERSIST_INTERVAL == 0 {
+            chunks_file.flush().await?;
+            write_feed_progress(state, vv_path, &record.id, processed, total).await?;
+        }
+        let read = read_repo_file_within(repo_path, &file_path, state.max_content_bytes).await;
+        let content_bytes = match read {
+            Ok(Some(bytes)) => bytes,
+            Ok(None) => continue,
+            Err(err) => {
+                error!(
+                    "skipping file {} due to read error: {}",
+                    file_path.display(),
+                    err
+                );
+                continue;
+            }
+        };
+
+        let Some((content, converted)) = feedable_content(
+            &content_bytes,
+            state.max_content_bytes,
+            state.detect_encoding,
+        ) else {
+            continue;
+        };
+        if skip_generated && is_likely_generated(&file_path, &content) {
+            stats.skipped_generated += 1;
+            continue;
+        }
+        if converted {
+            stats.encoding_converted += 1;
+        }
+        let file_sha = sha256_hex(content.as_bytes());
+        let language = guess_language(&file_path);
+        let blob_sha = blob_shas.get(file_path.to_string_lossy().as_ref()).cloned();
+        let committed_at = commit_times
+            .get(file_path.to_string_lossy().as_ref())
+            .copied();
+        let symbols = extract_symbols(&file_path, &content);
+        let chunk_prefix = match commit_sha.as_deref().filter(|_| state.index_commit_history) {
+            Some(sha) => format!("{}:{}:{}", record.id, sha, file_path.display()),
+            None => format!("{}:{}", record.id, file_path.display()),
+        };
+
+        let chunk_size = state
+            .chunk_lines_by_language
+            .get(&language)
+            .copied()
+            .unwrap_or(ChunkSize::Lines(state.chunk_max_lines));
+        let chunks = chunk_content(&content, chunk_size, state.chunk_overlap_lines);
+        let chunk_count = chunks.len();
+        let (chunks, overflowed) =
+            apply_chunk_limit(chunks, state.max_chunks_per_file, state.max_chunks_overflow);
+        if overflowed {
+            let policy = state.max_chunks_overflow.as_str();
+            warn!(
+                "{} has {} chunks, over MAX_CHUNKS_PER_FILE={} ({})",
+                file_path.display(),
+                chunk_count,
+                state.max_chunks_per_file,
+                policy
+            );
+            counter!("chunk_overflow_files_total", "policy" => policy).increment(1);
+            stats.overflowed += 1;
+            if stats.overflow_files.len() < MAX_RECORDED_FEED_FAILURES {
+                stats
+                    .overflow_files
+                    .push(file_path.to_string_lossy().into_owned());
+            }
+        }
+        let chunk_symbols = chunk_symbol_names(&symbols, &chunks);
+        for (chunk, symbol_names) in chunks.into_iter().zip(chunk_symbols) {
+            let FileChunk {
+                line_start,
+                line_end,
+                content,
+            } = chunk;
+            let content_sha = sha256_hex(content.as_bytes());
+            let chunk_id = sha256_hex(format!("{chunk_prefix}:{line_start}-{line_end}").as_bytes());
+            let embedding_sha = embedding_content_sha(state, &content, &content_sha);
+            let doc_id = format!("{}-{}", record.id, chunk_id);
+
+            if state.dedup_chunks {
+                let path = file_path.to_string_lossy();
+                match canonical_chunks.get(&content_sha) {
+                    Some((canonical_id, canonical_path)) if *canonical_path != path => {
+                        let also_in = duplicates.entry(canonical_id.clone()).or_default();
+                        if !also_in.iter().any(|other| *other == path) {
+                            also_in.push(path.to_string());
+                        }
+                        let entry = serde_json::json!({
+                            "repo_id": record.id.clone(),
+                            "file_path": path,
+                            "chunk_id": chunk_id,
+                            "line_start": line_start,
+                            "line_end": line_end,
+                            "content_sha": content_sha,
+                            "file_sha": file_sha,
+                            "embedding_model": embedding_model,
+                            "commit_sha": commit_sha,
+                            "blob_sha": blob_sha,
+                            "committed_at": committed_at,
+                            "duplicate_of": canonical_id,
+                        });
+                        chunks_file.write_all(entry.to_string().as_bytes()).await?;
+                        chunks_file.write_all(b"\n").await?;
+                        stats.deduplicated += 1;
+                        continue;
+                    }
+                    Some(_) => {}
+                    None => {
+                        canonical_chunks
+                            .insert(content_sha.clone(), (chunk_id.clone(), path.to_string()));
+                    }
+                }
+            }
+
+            if sweep_stale {
+                fed_doc_ids.insert(doc_id.clone());
+            }
+            let previous_entry = previous
+                .remove(&chunk_id)
+                .filter(|entry| entry.get("duplicate_of").is_none());
+            let unchanged = previous_entry.as_ref().filter(|entry| {
+                entry.get("content_sha").and_then(|value| value.as_str())
+                    == Some(content_sha.as_str())
+                    && entry
+                        .get("embedding_model")
+                        .and_then(|value| value.as_str())
+                        == Some(embedding_model)
+                    && entry.get("license_spdx").and_then(|value| value.as_str())
+                        == Some(license_spdx)
+            });
+            let entry = match unchanged {
+                Some(entry) => serde_json::to_string(entry)?,
+                None => serde_json::to_string(&serde_json::json!({
+                    "repo_id": record.id.clone(),
+                    "file_path": file_path.to_string_lossy(),
+                    "chunk_id": chunk_id,
+                    "line_start": line_start,
+                    "line_end": line_end,
+                    "content_sha": content_sha,
+                    "file_sha": file_sha,
+                    "embedding_sha": embedding_sha,
+                    "embedding_model": embedding_model,
+                    "license_spdx": license_spdx,
+                    "commit_sha": commit_sha,
+                    "blob_sha": blob_sha,
+                    "committed_at": committed_at,
+                }))?,
+            };
+            let fed = FedChunk {
+                file_path: file_path.to_string_lossy().to_string(),
+                line_start,
+                line_end,
+                content_sha,
+                entry,
+                updated: previous_entry.is_some(),
+                unchanged: unchanged.is_some(),
+            };
+            if state.dedup_chunks && !fed.unchanged {
+                refed_chunks.insert(chunk_id.clone());
+            }
+            let job = (!fed.unchanged).then(|| ChunkJob {
+                doc_id,
+                chunk_id,
+                content,
+                language: language.clone(),
+                symbol_names,
+                blob_sha: blob_sha.clone(),
+                committed_at,
+            });
+            pending.push_back(index_chunk(&ctx, fed, job));
+            while pending.len() >= pipeline_depth {
+                if let Some((fed, result)) = pending.next().await {
+                    record_fed_chunk(
+                        &mut chunks_file,
+                        &mut stats,
+                        &mut embedding_dump,
+                        fed,
+                        result,
+                    )
+                    .await?;
+                }
+            }
+        }
+    }
+    while let Some((fed, result)) = pending.next().await {
+        record_fed_chunk(
+            &mut chunks_file,
+            &mut stats,
+            &mut embedding_dump,
+            fed,
+            result,
+        )
+        .await?;
+    }
+    chunks_file.flush().await?;
+    chunks_file.sync_all().await?;
+    if interrupted {
+        return Err(AppError::ShuttingDown);
+    }
+    if stats.indexed == 0 {
+        if let Some(failure) = stats.failures.first() {
+            return Err(AppError::VespaRejected(format!(
+                "all {} documents failed to feed; first error ({}): {}",
+                stats.failed, failure.file_path, failure.error
+            )));
+        }
+    }
+
+    if !state.index_commit_history {
+        let leftovers = previous
+            .into_iter()
+            .filter(|(_, entry)| entry.get("duplicate_of").is_none());
+        for (chunk_id, _) in leftovers {
+            let doc_id = format!("{}-{}", record.id, chunk_id);
+            if state.search_backend == SearchBackend::Local {
+                state.local_index.write().await.remove(&doc_id);
+            } else {
+                delete_vespa_document(state, &doc_id).await?;
+            }
+            stats.deleted += 1;
+        }
+    }
+    if sweep_stale {
+        stats.deleted += delete_unlisted_code_documents(state, &record.id, &fed_doc_ids).await?;
+    }
+    for (chunk_id, also_in) in &duplicates {
+        if previous_duplicates.get(chunk_id) != Some(also_in) || refed_chunks.contains(chunk_id) {
+            set_chunk_also_in(state, &format!("{}-{}", record.id, chunk_id), also_in).await?;
+        }
+    }
+    for chunk_id in previous_duplicates.keys() {
+        if !duplicates.contains_key(chunk_id) && !refed_chunks.contains(chunk_id) {
+            set_chunk_also_in(state, &format!("{}-{}", record.id, chunk_id), &[]).await?;
+        }
+    }
+    fs::rename(&pending_chunks_path, &chunks_path).await?;
+    write_feed_progress(state, vv_path, &record.id, total, total).await?;
+
+    Ok(stats)
+}
+
+async fn index_chunk(
+    ctx: &FeedContext<'_>,
+    fed: FedChunk,
+    job: Option<ChunkJob>,
+) -> (FedChunk, Result<Option<Vec<f32>>, AppError>) {
+    let result = match job {
+        Some(job) => index_chunk_document(ctx, &fed, job).await,
+        None => Ok(None),
+    };
+    (fed, result)
+}
+
+async fn index_chunk_document(
+    ctx: &FeedContext<'_>,
+    fed: &FedChunk,
+    job: ChunkJob,
+) -> Result<Option<Vec<f32>>, AppError> {
+    let state = ctx.state;
+    let record = ctx.record;
+    let embedding = {
+        let _permit = ctx.embed_permits.acquire().await.ok();
+        embed_content_with_cache(
+            state,
+            ctx.vv_path,
+            ctx.embedding_model,
+            &job.content,
+            &fed.content_sha,
+        )
+        .await
+    };
+
+    if state.search_backend == SearchBackend::Local {
+        let embedding = match embedding {
+            Ok(values) => Some(values),
+            Err(err) => {
+                warn!(
+                    "local index: no embedding for {}:{}-{} ({}), keyword search only",
+                    fed.file_path, fed.line_start, fed.line_end, err
+                );
+                None
+            }
+        };
+        let kept = embedding.clone().filter(|_| ctx.keep_embeddings);
+        state.local_index.write().await.upsert(
+            job.doc_id,
+            LocalDocument {
+                doc_type: DOC_TYPE_CODE.to_string(),
+                repo_id: record.id.clone(),
+                file_path: fed.file_path.clone(),
+                line_start: fed.line_start,
+                line_end: fed.line_end,
+                content: job.content,
+                commit_sha: ctx.commit_sha.clone(),
+                blob_sha: job.blob_sha,
+                committed_at: job.committed_at,
+                embedding,
+                embedding_model: ctx.embedding_model.to_string(),
+                term_count: 0,
+                also_in: Vec::new(),
+            },
+        );
+        return Ok(kept);
+    }
+
+    let embedding_values = embedding?;
+    let kept = ctx.keep_embeddings.then(|| embedding_values.clone());
+    let put = VespaPut {
+        fields: VespaFields {
+            doc_type: DOC_TYPE_CODE.to_string(),
+            repo_id: record.id.clone(),
+            repo_url: record.repo_url.clone(),
+            repo_name: record.name.clone(),
+            repo_owner: record.owner.clone(),
+            commit_sha: ctx
+                .commit_sha
+                .clone()
+                .unwrap_or_else(|| "unknown".to_string()),
+            blob_sha: job.blob_sha,
+            branch: ctx.branch.clone(),
+            file_path: fed.file_path.clone(),
+            path_tokens: path_search_tokens(&fed.file_path).join(" "),
+            language: job.language,
+            license_spdx: ctx.license_spdx.to_string(),
+            chunk_id: job.chunk_id,
+            chunk_hash: fed.content_sha.clone(),
+            line_start: fed.line_start as i32,
+            line_end: fed.line_end as i32,
+            symbol_names: job.symbol_names,
+            content: job.content,
+            content_sha: fed.content_sha.clone(),
+            embedding: VespaEmbedding::new(embedding_values, state.vespa_tensor_encoding),
+            embedding_model: ctx.embedding_model.to_string(),
+            last_indexed_at: Utc::now().timestamp_millis(),
+            committed_at: job.committed_at,
+        },
+    };
+    let _permit = ctx.feed_permits.acquire().await.ok();
+    put_vespa_document(state, &job.doc_id, &put).await?;
+    Ok(kept)
+}
+
+async fn record_fed_chunk(
+    chunks_file: &mut fs::File,
+    stats: &mut FeedStats,
+    embedding_dump: &mut Option<EmbeddingDump>,
+    fed: FedChunk,
+    result: Result<Option<Vec<f32>>, AppError>,
+) -> Result<(), AppError> {
+    match result {
+        Ok(embedding) => {
+            if let (Some(dump), Some(values)) = (embedding_dump.as_mut(), embedding.as_deref()) {
+                dump.record(StdPath::new(&fed.file_path), &fed.content_sha, values)
+                    .await;
+            }
+            chunks_file.write_all(fed.entry.as_bytes()).await?;
+            chunks_file.write_all(b"\n").await?;
+            if fed.unchanged {
+                stats.skipped += 1;
+            } else if fed.updated {
+                stats.updated += 1;
+            } else {
+                stats.added += 1;
+            }
+            stats.indexed += 1;
+        }
+        Err(err) => {
+            error!(
+                "failed to feed {}:{}-{}: {}",
+                fed.file_path, fed.line_start, fed.line_end, err
+            );
+            stats.failed += 1;
+            if stats.failures.len() < MAX_RECORDED_FEED_FAILURES {
+                stats.failures.push(FeedFailure {
+                    file_path: fed.file_path,
+                    line_start: fed.line_start,
+                    line_end: fed.line_end,
+                    error: err.to_string(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+struct EmbeddingDump {
+    file: fs::File,
+    skip: usize,
+    written_bytes: u64,
+}
+
+impl EmbeddingDump {
+    async fn create(vv_path: &StdPath) -> Result<Self, AppError> {
+        let debug_path = vv_path.join("debug");
+        fs::create_dir_all(&debug_path).await?;
+        let file = fs::File::create(debug_path.join("embeddings.ndjson")).await?;
+        Ok(Self {
+            file,
+            skip: 0,
+            written_bytes: 0,
+        })
+    }
+
+    async fn record(&mut self, file_path: &StdPath, content_sha: &str, values: &[f32]) {
+        if self.skip > 0 {
+            self.skip -= 1;
+            return;
+        }
+        self.skip = DEBUG_EMBEDDINGS_SAMPLE_EVERY - 1;
+        if self.written_bytes >= DEBUG_EMBEDDINGS_MAX_BYTES {
+            return;
+        }
+        let norm = values.iter().map(|value| value * value).sum::<f32>().sqrt();
+        let entry = serde_json::json!({
+            "file_path": file_path.to_string_lossy(),
+            "content_sha": content_sha,
+            "norm": norm,
+            "embedding": values,
+        });
+        let mut line = entry.to_string();
+        line.push('\n');
+        if let Err(err) = self.file.write_all(line.as_bytes()).await {
+            warn!("failed to write embedding debug dump: {}", err);
+            self.written_bytes = DEBUG_EMBEDDINGS_MAX_BYTES;
+            return;
+        }
+        self.written_bytes += line.len() as u64;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct FileChunk {
+    line_start: usize,
+    line_end: usize,
+    content: String,
+}
+
+fn chunk_file(content: &str, max_lines: usize, overlap: usize) -> Vec<FileChunk> {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    if lines.is_empty() {
+        return Vec::new();
+    }
+    let max_lines = max_lines.max(1);
+    let step = max_lines.saturating_sub(overlap).max(1);
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    loop {
+        let end = (start + max_lines).min(lines.len());
+        chunks.push(FileChunk {
+            line_start: start + 1,
+            line_end: end,
+            content: lines[start..end].concat(),
+        });
+        if end == lines.len() {
+            return chunks;
+        }
+        start += step;
+    }
+}
+
+/// How a language's files are split into chunks (`CHUNK_LINES_BY_LANGUAGE`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChunkSize {
+    Lines(usize),
+    WholeFile,
+    Headings,
+}
+
+/// Parses `language=size` pairs, where size is a line count, `file` (one chunk per file) or, for
+/// markdown only, `headings`. Returns the offending entry on error.
+fn parse_chunk_lines_by_language(value: &str) -> Result<HashMap<String, ChunkSize>, String> {
+    let mut sizes = HashMap::new();
+    for entry in value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        let Some((language, size)) = entry.split_once('=') else {
+            return Err(entry.to_string());
+        };
+        let language = language.trim().to_lowercase();
+        if !KNOWN_LANGUAGES.contains(&language.as_str()) {
+            return Err(entry.to_string());
+        }
+        let size = match size.trim().to_lowercase().as_str() {
+            "file" => ChunkSize::WholeFile,
+            "headings" if language == "markdown" => ChunkSize::Headings,
+            lines => match lines.parse::<usize>() {
+                Ok(lines) if lines > 0 => ChunkSize::Lines(lines),
+                _ => return Err(entry.to_string()),
+            },
+        };
+        sizes.insert(language, size);
+    }
+    Ok(sizes)
+}
+
+/// What to do with a file that splits into more than `MAX_CHUNKS_PER_FILE` chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChunkOverflowPolicy {
+    Truncate,
+    Skip,
+}
+
+impl ChunkOverflowPolicy {
+    fn as_str(self) -> &'static str {
+        match self {
+            ChunkOverflowPolicy::Truncate => "truncate",
+            ChunkOverflowPolicy::Skip => "skip",
+        }
+    }
+}
+
+fn parse_chunk_overflow_policy(value: &str) -> Option<ChunkOverflowPolicy> {
+    match value.trim().to_lowercase().as_str() {
+        "truncate" => Some(ChunkOverflowPolicy::Truncate),
+        "skip" => Some(ChunkOverflowPolicy::Skip),
+        _ => None,
+    }
+}
+
+/// Caps a file's chunks at `limit`, keeping the first ones or dropping the file entirely.
+/// The flag reports whether the file was over the limit.
+fn apply_chunk_limit(
+    mut chunks: Vec<FileChunk>,
+    limit: usize,
+    policy: ChunkOverflowPolicy,
+) -> (Vec<FileChunk>, bool) {
+    if chunks.len() <= limit {
+        return (chunks, false);
+    }
+    match policy {
+        ChunkOverflowPolicy::Truncate => chunks.truncate(limit),
+        ChunkOverflowPolicy::Skip => chunks.clear(),
+    }
+    (chunks, true)
+}
+
+fn chunk_content(content: &str, size: ChunkSize, overlap: usize) -> Vec<FileChunk> {
+    match size {
+        ChunkSize::Lines(max_lines) => {
+            chunk_file(content, max_lines, overlap.min(max_lines.saturating_sub(1)))
+        }
+        ChunkSize::WholeFile => chunk_file(content, content.split_inclusive('\n').count(), 0),
+        ChunkSize::Headings => chunk_markdown_sections(content),
+    }
+}
+
+/// One chunk per markdown section: a new chunk starts at every ATX heading outside a code fence.
+fn chunk_markdown_sections(content: &str) -> Vec<FileChunk> {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let mut starts = vec![0];
+    let mut in_fence = false;
+    for (index, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        } else if !in_fence && index > 0 && is_markdown_heading(line) {
+            starts.push(index);
+        }
+    }
+    starts.push(lines.len());
+    starts
+        .windows(2)
+        .filter(|bounds| bounds[0] < bounds[1])
+        .map(|bounds| FileChunk {
+            line_start: bounds[0] + 1,
+            line_end: bounds[1],
+            content: lines[bounds[0]..bounds[1]].concat(),
+        })
+        .collect()
+}
+
+fn is_markdown_heading(line: &str) -> bool {
+    let level = line.bytes().take_while(|byte| *byte == b'#').count();
+    (1..=6).contains(&level) && line[level..].starts_with([' ', '\t', '\r', '\n'])
+}
+
+fn feedable_content(bytes: &[u8], max_bytes: u64, detect_encoding: bool) -> Option<(String, bool)> {
+    if bytes.is_empty() || bytes.len() as u64 > max_bytes {
+        return None;
+    }
+    let (text, converted) = decode_source_bytes(bytes, detect_encoding);
+    if text.contains('\0') {
+        return None;
+    }
+    let content = sanitize_vespa_content(&text);
+    if content.trim().is_empty() {
+        return None;
+    }
+    Some((content, converted))
+}
+
+fn decode_source_bytes(bytes: &[u8], detect_encoding: bool) -> (Cow<'_, str>, bool) {
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return (Cow::Borrowed(text), false);
+    }
+    if detect_encoding {
+        if let Some((encoding, _)) = encoding_rs::Encoding::for_bom(bytes) {
+            let (text, _, _) = encoding.decode(bytes);
+            return (text, true);
+        }
+        let mut detector = chardetng::EncodingDetector::new();
+        detector.feed(bytes, true);
+        let (encoding, confident) = detector.guess_assess(None, false);
+        if confident {
+            let (text, _, had_errors) = encoding.decode(bytes);
+            if !had_errors {
+                return (text, true);
+            }
+        }
+    }
+    (String::from_utf8_lossy(bytes), false)
+}
+
+fn is_likely_generated(file_path: &StdPath, content: &str) -> bool {
+    let name = file_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if [
+        ".min.js",
+        ".min.css",
+        "_pb2.py",
+        "_pb2_grpc.py",
+        ".pb.go",
+        ".pb.cc",
+        ".pb.h",
+        ".g.dart",
+    ]
+    .iter()
+    .any(|suffix| name.ends_with(suffix))
+    {
+        return true;
+    }
+
+    let line_count = content.lines().count().max(1);
+    if content.len() / line_count > GENERATED_AVG_LINE_LENGTH {
+        return true;
+    }
+
+    content.lines().take(GENERATED_HEADER_LINES).any(|line| {
+        let line = line.to_lowercase();
+        line.contains("generated by")
+            || line.contains("@generated")
+            || line.contains("auto-generated")
+            || line.contains("autogenerated")
+            || line.contains("do not edit")
+    })
+}
+
+async fn detect_license(repo_path: &StdPath) -> Option<String> {
+    let mut licenses: Vec<String> = Vec::new();
+    for name in LICENSE_FILE_NAMES {
+        let Ok(bytes) = read_repo_file(repo_path, StdPath::new(name)).await else {
+            continue;
+        };
+        if let Some(license) = license_from_text(&String::from_utf8_lossy(&bytes)) {
+            if !licenses.contains(&license) {
+                licenses.push(license);
+            }
+        }
+    }
+    (!licenses.is_empty()).then(|| licenses.join(" OR "))
+}
+
+fn license_from_text(text: &str) -> Option<String> {
+    for line in text.lines().take(GENERATED_HEADER_LINES) {
+        if let Some((_, identifier)) = line.split_once("SPDX-License-Identifier:") {
+            let identifier = identifier.trim().trim_end_matches("*/").trim();
+            if !identifier.is_empty() {
+                return Some(identifier.to_string());
+            }
+        }
+    }
+    let text = text
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+    LICENSE_PATTERNS
+        .iter()
+        .find(|(_, phrases)| phrases.iter().all(|phrase| text.contains(phrase)))
+        .map(|(identifier, _)| identifier.to_string())
+}
+
+async fn record_feed_stats(vv_path: &StdPath, stats: &FeedStats) -> Result<(), AppError> {
+    write_manifest_field(vv_path, "feed_stats", serde_json::to_value(stats)?).await
+}
+
+async fn write_manifest_field(
+    vv_path: &StdPath,
+    key: &str,
+    value: serde_json::Value,
+) -> Result<(), AppError> {
+    let manifest_path = vv_path.join("manifest.json");
+    let mut manifest = match fs::read(&manifest_path).await {
+        Ok(data) => serde_json::from_slice::<serde_json::Value>(&data)
+            .unwrap_or_else(|_| serde_json::json!({})),
+        Err(_) => serde_json::json!({}),
+    };
+    if let Some(object) = manifest.as_object_mut() {
+        object.insert(key.to_string(), value);
+    }
+    fs::write(&manifest_path, serde_json::to_vec_pretty(&manifest)?).await?;
+    Ok(())
+}
+
+async fn write_feed_progress(
+    state: &AppState,
+    vv_path: &StdPath,
+    repo_id: &str,
+    processed: usize,
+    total: usize,
+) -> Result<(), AppError> {
+    write_status_with_progress(
+        state,
+        vv_path,
+        repo_id,
+        "indexing",
+        Some(format!(
+            "Feeding documents to Vespa ({processed}/{total} files)"
+        )),
+        Some(IngestProgress {
+            phase: "indexing".into(),
+            processed,
+            total,
+        }),
+    )
+    .await
+}
+
+async fn put_vespa_document(
+    state: &AppState,
+    doc_id: &str,
+    put: &VespaPut,
+) -> Result<(), AppError> {
+    let body_bytes = serde_json::to_vec(put)?;
+    let document_url = vespa_document_url(state, doc_id)?;
+    let response = state
+        .http_client
+        .post(document_url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(reqwest::header::ACCEPT, "application/json")
+        .body(body_bytes.clone())
+        .send()
+        .await
+        .inspect_err(|_| {
+            counter!("vespa_feed_documents_total", "result" => "failure").increment(1)
+        })?;
+
+    if !response.status().is_success() {
+        counter!("vespa_feed_documents_total", "result" => "failure").increment(1);
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        let preview_len = body_bytes.len().min(1024);
+        let preview = String::from_utf8_lossy(&body_bytes[..preview_len]);
+        let response_preview: String = body.chars().take(1024).collect();
+        error!(
+            "vespa feed rejected (status {}), request preview: {}, response: {}",
+            status, preview, response_preview
+        );
+        return Err(AppError::VespaRejected(body));
+    }
+    counter!("vespa_feed_documents_total", "result" => "success").increment(1);
+    Ok(())
+}
+
+async fn delete_vespa_document(state: &AppState, doc_id: &str) -> Result<(), AppError> {
+    let response = state
+        .http_client
+        .delete(vespa_document_url(state, doc_id)?)
+        .send()
+        .await?;
+    if !response.status().is_success() && response.status() != StatusCode::NOT_FOUND {
+        let body = response.text().await.unwrap_or_default();
+        return Err(AppError::VespaRejected(body));
+    }
+    Ok(())
+}
+
+fn chunk_duplicates<'a>(
+    entries: impl Iterator<Item = &'a serde_json::Value>,
+) -> BTreeMap<String, Vec<String>> {
+    let mut duplicates: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for entry in entries {
+        let field = |name: &str| entry.get(name).and_then(|value| value.as_str());
+        if let (Some(canonical_id), Some(file_path)) = (field("duplicate_of"), field("file_path")) {
+            let also_in = duplicates.entry(canonical_id.to_string()).or_default();
+            if !also_in.iter().any(|other| other == file_path) {
+                also_in.push(file_path.to_string());
+            }
+        }
+    }
+    duplicates
+}
+
+async fn set_chunk_also_in(
+    state: &AppState,
+    doc_id: &str,
+    also_in: &[String],
+) -> Result<(), AppError> {
+    if state.search_backend == SearchBackend::Local {
+        if let Some(document) = state.local_index.write().await.documents.get_mut(doc_id) {
+            document.also_in = also_in.to_vec();
+        }
+        return Ok(());
+    }
+    let update = serde_json::json!({ "fields": { "also_in": { "assign": also_in } } });
+    let response = state
+        .http_client
+        .put(vespa_document_url(state, doc_id)?)
+        .json(&update)
+        .send()
+        .await?;
+    if !response.status().is_success() && response.status() != StatusCode::NOT_FOUND {
+        let body = response.text().await.unwrap_or_default();
+        return Err(AppError::VespaRejected(body));
+    }
+    Ok(())
+}
+
+async fn read_previous_chunks(chunks_path: &StdPath) -> HashMap<String, serde_json::Value> {
+    let chunks = fs::read_to_string(chunks_path).await.unwrap_or_default();
+    chunks
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .filter_map(|entry| {
+            let chunk_id = entry.get("chunk_id")?.as_str()?.to_string();
+            Some((chunk_id, entry))
+        })
+        .collect()
+}
+
+async fn feed_commits_to_index(
+    state: &AppState,
+    record: &RepoRecord,
+    repo_path: &StdPath,
+    vv_path: &StdPath,
+    license_spdx: &str,
+) -> Result<usize, AppError> {
+    let limit = state.commit_index_limit.to_string();
+    let output = run_git_command(
+        Some(repo_path),
+        &["log", "-n", &limit, "--format=%H%x1f%an%x1f%aI%x1f%B%x1e"],
+    )
+    .await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        warn!(
+            "git log failed for {}: {}",
+            repo_path.display(),
+            stderr.trim()
+        );
+        return Ok(0);
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let embedding_model = repo_embedding_model(state, record);
+    let branch = record_branch(record, repo_path).await;
+    let mut indexed = 0usize;
+    for entry in stdout.split('\u{1e}') {
+        let mut parts = entry.trim_start_matches('\n').splitn(4, '\u{1f}');
+        let (Some(sha), Some(author), Some(date), Some(message)) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            continue;
+        };
+        let message = sanitize_vespa_content(message.trim());
+        if sha.is_empty() || message.is_empty() {
+            continue;
+        }
+        let content = format!("commit {sha}\nAuthor: {author}\nDate: {date}\n\n{message}");
+        let line_end = content.lines().count().max(1) as i32;
+        let content_sha = sha256_hex(content.as_bytes());
+        let chunk_id = sha256_hex(format!("{}:commit:{}", record.id, sha).as_bytes());
+        let doc_id = format!("{}-{}", record.id, chunk_id);
+        let file_path = format!("commit:{sha}");
+        let committed_at = chrono::DateTime::parse_from_rfc3339(date)
+            .ok()
+            .map(|date| date.timestamp_millis());
+
+        if state.search_backend == SearchBackend::Local {
+            let embedding =
+                embed_content_with_cache(state, vv_path, embedding_model, &content, &content_sha)
+                    .await
+                    .ok();
+            state.local_index.write().await.upsert(
+                doc_id,
+                LocalDocument {
+                    doc_type: DOC_TYPE_COMMIT.to_string(),
+                    repo_id: record.id.clone(),
+                    file_path,
+                    line_start: 1,
+                    line_end: line_end as usize,
+                    content,
+                    commit_sha: Some(sha.to_string()),
+                    blob_sha: None,
+                    committed_at,
+                    embedding,
+                    embedding_model: embedding_model.to_string(),
+                    term_count: 0,
+                    also_in: Vec::new(),
+                },
+            );
+        } else {
+            let embedding_values =
+                embed_content_with_cache(state, vv_path, embedding_model, &content, &content_sha)
+                    .await?;
+            let put = VespaPut {
+                fields: VespaFields {
+                    doc_type: DOC_TYPE_COMMIT.to_string(),
+                    repo_id: record.id.clone(),
+                    repo_url: record.repo_url.clone(),
+                    repo_name: record.name.clone(),
+                    repo_owner: record.owner.clone(),
+                    commit_sha: sha.to_string(),
+                    blob_sha: None,
+                    branch: branch.clone(),
+                    file_path,
+                    path_tokens: String::new(),
+                    language: "unknown".to_string(),
+                    license_spdx: license_spdx.to_string(),
+                    chunk_id,
+                    chunk_hash: content_sha.clone(),
+                    line_start: 1,
+                    line_end,
+                    symbol_names: Vec::new(),
+                    content,
+                    content_sha,
+                    embedding: VespaEmbedding::new(embedding_values, state.vespa_tensor_encoding),
+                    embedding_model: embedding_model.to_string(),
+                    last_indexed_at: Utc::now().timestamp_millis(),
+                    committed_at,
+                },
+            };
+            put_vespa_document(state, &doc_id, &put).await?;
+        }
+        indexed += 1;
+    }
+    Ok(indexed)
+}
+
+async fn fetch_github_releases(
+    state: &AppState,
+    record: &RepoRecord,
+    token: &str,
+) -> Result<Vec<GitHubRelease>, AppError> {
+    let url = format!(
+        "https://api.github.com/repos/{}/{}/releases?per_page={}",
+        record.owner, record.name, state.release_index_limit
+    );
+    let response = state
+        .http_client
+        .get(url)
+        .header("Authorization", format!("token {token}"))
+        .header("Accept", "application/vnd.github+json")
+        .header("User-Agent", "vespa-code-search")
+        .send()
+        .await?;
+    if let Some(err) = github_rate_limit(&response) {
+        return Err(err);
+    }
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(AppError::GitHub(format!(
+            "release listing for {}/{} failed ({status}): {body}",
+            record.owner, record.name
+        )));
+    }
+    let releases: Vec<GitHubRelease> = response
+        .json()
+        .await
+        .map_err(|err| AppError::GitHub(err.to_string()))?;
+    Ok(releases
+        .into_iter()
+        .filter(|release| !release.draft)
+        .take(state.release_index_limit)
+        .collect())
+}
+
+fn release_document_content(release: &GitHubRelease) -> String {
+    let mut content = format!("release {}\n", release.tag_name);
+    if let Some(name) = release
+        .name
+        .as_deref()
+        .filter(|name| !name.trim().is_empty())
+    {
+        content.push_str(&format!("Name: {}\n", name.trim()));
+    }
+    if let Some(published_at) = release.published_at.as_deref() {
+        content.push_str(&format!("Published: {published_at}\n"));
+    }
+    let notes = sanitize_vespa_content(release.body.as_deref().unwrap_or("").trim());
+    if !notes.is_empty() {
+        content.push('\n');
+        content.push_str(&notes);
+    }
+    content
+}
+
+async fn feed_releases_to_index(
+    state: &AppState,
+    record: &RepoRecord,
+    vv_path: &StdPath,
+    token: &str,
+    license_spdx: &str,
+) -> Result<usize, AppError> {
+    let releases = retry_github_rate_limited("release listing", || {
+        fetch_github_releases(state, record, token)
+    })
+    .await?;
+    fs::write(
+        vv_path.join("releases.json"),
+        serde_json::to_vec_pretty(&releases)?,
+    )
+    .await?;
+
+    let embedding_model = repo_embedding_model(state, record);
+    let mut indexed = 0usize;
+    for release in &releases {
+        let content = release_document_content(release);
+        let line_end = content.lines().count().max(1) as i32;
+        let content_sha = sha256_hex(content.as_bytes());
+        let chunk_id = sha256_hex(format!("{}:release:{}", record.id, release.tag_name).as_bytes());
+        let doc_id = format!("{}-{}", record.id, chunk_id);
+        let file_path = format!("release:{}", release.tag_name);
+        let committed_at = release
+            .published_at
+            .as_deref()
+            .and_then(|date| chrono::DateTime::parse_from_rfc3339(date).ok())
+            .map(|date| date.timestamp_millis());
+
+        if state.search_backend == SearchBackend::Local {
+            let embedding =
+                embed_content_with_cache(state, vv_path, embedding_model, &content, &content_sha)
+                    .await
+                    .ok();
+            state.local_index.write().await.upsert(
+                doc_id,
+                LocalDocument {
+                    doc_type: DOC_TYPE_RELEASE.to_string(),
+                    repo_id: record.id.clone(),
+                    file_path,
+                    line_start: 1,
+                    line_end: line_end as usize,
+                    content,
+                    commit_sha: None,
+                    blob_sha: None,
+                    committed_at,
+                    embedding,
+                    embedding_model: embedding_model.to_string(),
+                    term_count: 0,
+                    also_in: Vec::new(),
+                },
+            );
+        } else {
+            let embedding_values =
+                embed_content_with_cache(state, vv_path, embedding_model, &content, &content_sha)
+                    .await?;
+            let put = VespaPut {
+                fields: VespaFields {
+                    doc_type: DOC_TYPE_RELEASE.to_string(),
+                    repo_id: record.id.clone(),
+                    repo_url: record.repo_url.clone(),
+                    repo_name: record.name.clone(),
+                    repo_owner: record.owner.clone(),
+                    commit_sha: "unknown".to_string(),
+                    blob_sha: None,
+                    branch: release.tag_name.clone(),
+                    file_path,
+                    path_tokens: String::new(),
+                    language: "unknown".to_string(),
+                    license_spdx: license_spdx.to_string(),
+                    chunk_id,
+                    chunk_hash: content_sha.clone(),
+                    line_start: 1,
+                    line_end,
+                    symbol_names: Vec::new(),
+                    content,
+                    content_sha,
+                    embedding: VespaEmbedding::new(embedding_values, state.vespa_tensor_encoding),
+                    embedding_model: embedding_model.to_string(),
+                    last_indexed_at: Utc::now().timestamp_millis(),
+                    committed_at,
+                },
+            };
+            put_vespa_document(state, &doc_id, &put).await?;
+        }
+        indexed += 1;
+    }
+    Ok(indexed)
+}
+
+async fn read_latest_release_notes(repo_path: &StdPath) -> Option<String> {
+    let data = fs::read(repo_path.join("vv").join("releases.json"))
+        .await
+        .ok()?;
+    let releases: Vec<GitHubRelease> = serde_json::from_slice(&data).ok()?;
+    releases.first().map(release_document_content)
+}
+
+async fn is_dir_empty(path: &StdPath) -> Result<bool, AppError> {
+    let mut entries = fs::read_dir(path).await?;
+    Ok(entries.next_entry().await?.is_none())
+}
+
+async fn dir_contains_only_vv(path: &StdPath) -> Result<bool, AppError> {
+    let mut entries = fs::read_dir(path).await?;
+    let mut saw_entry = false;
+    while let Some(entry) = entries.next_entry().await? {
+        saw_entry = true;
+        let name = entry.file_name();
+        if name != "vv" && name != ".vv" {
+            return Ok(false);
+        }
+    }
+    Ok(saw_entry)
+}
+
+async fn ensure_free_disk_space(
+    state: &AppState,
+    record: &RepoRecord,
+    vv_path: &StdPath,
+) -> Result<(), AppError> {
+    if let Some(available) = available_disk_bytes(&state.repos_path) {
+        if available < state.min_free_disk_bytes {
+            let message = format!(
+                "Insufficient disk space: {} bytes free under {}, {} required (MIN_FREE_DISK_BYTES)",
+                available,
+                state.repos_path.display(),
+                state.min_free_disk_bytes
+            );
+            write_status(state, vv_path, &record.id, "error", Some(message.clone())).await?;
+            return Err(AppError::Io(std::io::Error::other(message)));
+        }
+    }
+    Ok(())
+}
+
+fn is_archive_url(url: &str) -> bool {
+    let trimmed = url.trim();
+    if !(trimmed.starts_with("https://") || trimmed.starts_with("http://")) {
+        return false;
+    }
+    let path = trimmed
+        .split(['?', '#'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    path.ends_with(".tar.gz") || path.ends_with(".tgz")
+}
+
+fn parse_archive_url(url: &str) -> Result<(String, String), AppError> {
+    let trimmed = url.trim();
+    let rest = trimmed
+        .strip_prefix("https://")
+        .or_else(|| trimmed.strip_prefix("http://"))
+        .ok_or(AppError::InvalidRepoUrl)?;
+    let rest = rest.split(['?', '#']).next().unwrap_or_default();
+    let segments: Vec<&str> = rest
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    let (host, path) = segments.split_first().ok_or(AppError::InvalidRepoUrl)?;
+    let (owner, name) = match path.iter().position(|segment| *segment == "archive") {
+        Some(index) if index >= 2 => (path[index - 2].to_string(), path[index - 1].to_string()),
+        _ => {
+            let file = path.last().ok_or(AppError::InvalidRepoUrl)?;
+            let lower = file.to_lowercase();
+            let stem_len = if lower.ends_with(".tar.gz") {
+                file.len() - ".tar.gz".len()
+            } else {
+                file.len() - ".tgz".len()
+            };
+            let host = host.split(':').next().unwrap_or(host);
+            (host.to_string(), file[..stem_len].to_string())
+        }
+    };
+    let sanitize = |value: &str| -> Result<String, AppError> {
+        let cleaned: String = value
+            .chars()
+            .filter(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '-' | '_' | '.'))
+            .collect();
+        if cleaned.is_empty() || cleaned.chars().all(|ch| ch == '.') {
+            return Err(AppError::InvalidRepoUrl);
+        }
+        Ok(cleaned)
+    };
+    Ok((sanitize(&owner)?, sanitize(&name)?))
+}
+
+async fn fetch_archive(
     state: &AppState,
     record: &RepoRecord,
     repo_path: &StdPath,
+    vv_path: &StdPath,
 ) -> Result<(), AppError> {
-    let org = state.github_org.as_deref().ok_or_else(|| {
-        AppError::Config("GITHUB_ORG is required for repo mirroring".into())
-    })?;
-    let token = state.github_token.as_deref().ok_or_else(|| {
-        AppError::Config("GITHUB_TOKEN is required for repo mirroring".into())
-    })?;
-    let mirror_name = format!("{}-vv-search", record.name);
+    if repo_path.exists()
+        && !is_dir_empty(repo_path).await?
+        && !dir_contains_only_vv(repo_path).await?
+    {
+        info!("archive for repo {} already extracted", record.id);
+        return Ok(());
+    }
+    ensure_free_disk_space(state, record, vv_path).await?;
+    let parent = repo_path.parent().unwrap();
+    fs::create_dir_all(parent).await?;
+    let dir_name = repo_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| record.id.clone());
+    let download_path = parent.join(format!(".{dir_name}.download.tar.gz"));
+    let staging_path = parent.join(format!(".{dir_name}.extracting"));
+
+    let download_error = |err: reqwest::Error| {
+        AppError::Io(std::io::Error::other(format!(
+            "archive download failed: {err}"
+        )))
+    };
+    let mut response = state
+        .http_client
+        .get(&record.repo_url)
+        .timeout(Duration::from_secs(ARCHIVE_DOWNLOAD_TIMEOUT_SECS))
+        .send()
+        .await
+        .map_err(download_error)?;
+    if !response.status().is_success() {
+        return Err(AppError::Io(std::io::Error::other(format!(
+            "archive download failed with status {}",
+            response.status()
+        ))));
+    }
+    let mut file = fs::File::create(&download_path).await?;
+    let mut downloaded: u64 = 0;
+    while let Some(chunk) = response.chunk().await.map_err(download_error)? {
+        downloaded += chunk.len() as u64;
+        if downloaded > state.archive_max_bytes {
+            drop(file);
+            fs::remove_file(&download_path).await.ok();
+            return Err(AppError::Io(std::io::Error::other(format!(
+                "archive exceeds ARCHIVE_MAX_BYTES ({} bytes)",
+                state.archive_max_bytes
+            ))));
+        }
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    drop(file);
+    info!(
+        "downloaded {} bytes of archive for repo {}",
+        downloaded, record.id
+    );
 
-    ensure_github_repo(state, org, token, &mirror_name).await?;
+    fs::remove_dir_all(&staging_path).await.ok();
+    let max_bytes = state.archive_max_bytes;
+    let archive_path = download_path.clone();
+    let extract_path = staging_path.clone();
+    let extracted = tokio::task::spawn_blocking(move || {
+        extract_archive(&archive_path, &extract_path, max_bytes)
+    })
+    .await
+    .map_err(|err| AppError::Io(std::io::Error::other(err.to_string())))?;
+    fs::remove_file(&download_path).await.ok();
+    let files = match extracted {
+        Ok(files) => files,
+        Err(err) => {
+            fs::remove_dir_all(&staging_path).await.ok();
+            return Err(AppError::Io(err));
+        }
+    };
 
-    let remote_url = format!(
-        "https://x-access-token:{}@github.com/{}/{}.git",
-        token, org, mirror_name
+    fs::create_dir_all(repo_path).await?;
+    let mut entries = fs::read_dir(&staging_path).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name();
+        if name == "vv" || name == ".vv" {
+            continue;
+        }
+        fs::rename(entry.path(), repo_path.join(&name)).await?;
+    }
+    fs::remove_dir_all(&staging_path).await.ok();
+    info!(
+        "extracted {} files from archive for repo {}",
+        files, record.id
     );
+    Ok(())
+}
 
-    let _ = run_git_command(Some(repo_path), &["remote", "remove", "mirror"]).await;
-    let output = run_git_command(
-        Some(repo_path),
-        &["remote", "add", "mirror", &remote_url],
-    )
-    .await?;
-    if !output.status.success() {
-        return Err(AppError::GitHub(
-            "failed to add mirror remote for GitHub".into(),
-        ));
-    }
+fn extract_archive(
+    archive_path: &StdPath,
+    dest: &StdPath,
+    max_bytes: u64,
+) -> std::io::Result<usize> {
+    let open = || -> std::io::Result<tar::Archive<flate2::read::GzDecoder<std::fs::File>>> {
+        let file = std::fs::File::open(archive_path)?;
+        Ok(tar::Archive::new(flate2::read::GzDecoder::new(file)))
+    };
 
-    let output = run_git_command(Some(repo_path), &["push", "--mirror", "mirror"]).await?;
-    if !output.status.success() {
-        return Err(AppError::GitHub(
-            "failed to push mirror to GitHub".into(),
-        ));
+    let mut roots = HashSet::new();
+    let mut nested = false;
+    for entry in open()?.entries()? {
+        let entry = entry?;
+        let path = entry.path()?;
+        let mut components = path.components();
+        if let Some(first) = components.next() {
+            roots.insert(first.as_os_str().to_os_string());
+            nested |= components.next().is_some();
+        }
     }
+    let strip = if roots.len() == 1 && nested {
+        roots.into_iter().next()
+    } else {
+        None
+    };
 
-    Ok(())
+    std::fs::create_dir_all(dest)?;
+    let mut total: u64 = 0;
+    let mut files = 0;
+    for entry in open()?.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let relative = match &strip {
+            Some(root) => match path.strip_prefix(root) {
+                Ok(relative) => relative.to_path_buf(),
+                Err(_) => continue,
+            },
+            None => path.clone(),
+        };
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
+        if !relative
+            .components()
+            .all(|component| matches!(component, std::path::Component::Normal(_)))
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "archive entry escapes the repo directory: {}",
+                    path.display()
+                ),
+            ));
+        }
+        let target = dest.join(&relative);
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_dir() {
+            std::fs::create_dir_all(&target)?;
+        } else if entry_type.is_file() {
+            total += entry.header().size()?;
+            if total > max_bytes {
+                return Err(std::io::Error::other(format!(
+                    "extracted archive exceeds ARCHIVE_MAX_BYTES ({max_bytes} bytes)"
+                )));
+            }
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            entry.unpack(&target)?;
+            files += 1;
+        }
+    }
+    Ok(files)
 }
 
-async fn write_vv_state(repo_path: &StdPath, record: &RepoRecord) -> Result<PathBuf, AppError> {
-    let vv_path = repo_path.join(".vv");
-    fs::create_dir_all(&vv_path).await?;
-    let payload = serde_json::json!({
-        "repo_id": record.id,
-        "repo_url": record.repo_url,
-        "owner": record.owner,
-        "name": record.name,
-        "mirror_repo": format!("{}-vv-search", record.name),
-        "updated_at": Utc::now().to_rfc3339(),
-    });
-    let state_path = vv_path.join("state.json");
-    fs::write(&state_path, serde_json::to_vec_pretty(&payload)?).await?;
-    Ok(state_path)
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)]
+fn available_disk_bytes(path: &StdPath) -> Option<u64> {
+    match nix::sys::statvfs::statvfs(path) {
+        Ok(stats) => Some(stats.blocks_available() as u64 * stats.fragment_size() as u64),
+        Err(err) => {
+            warn!(
+                "failed to read free disk space for {}: {}",
+                path.display(),
+                err
+            );
+            None
+        }
+    }
 }
 
-async fn commit_vv_state(repo_path: &StdPath, state_path: &StdPath) -> Result<(), AppError> {
-    let _ = run_git_command(Some(repo_path), &["config", "user.email", "vv-search@users.noreply.github.com"]).await?;
-    let _ = run_git_command(Some(repo_path), &["config", "user.name", "vv-search"]).await?;
+#[cfg(not(unix))]
+fn available_disk_bytes(_path: &StdPath) -> Option<u64> {
+    None
+}
 
-    let state_path_str = state_path.to_string_lossy();
-    let output = run_git_command(
+async fn repo_is_empty(repo_path: &StdPath) -> bool {
+    match run_git_command(
         Some(repo_path),
-        &["add", "-f", state_path_str.as_ref()],
+        &["rev-parse", "--verify", "--quiet", "HEAD"],
     )
-    .await?;
-    if !output.status.success() {
-        return Err(AppError::GitHub(
-            "failed to stage .vv state file".into(),
-        ));
+    .await
+    {
+        Ok(output) => !output.status.success(),
+        Err(_) => false,
     }
+}
 
-    let diff_output = run_git_command(Some(repo_path), &["diff", "--cached", "--quiet"]).await?;
-    if diff_output.status.code() == Some(0) {
-        return Ok(());
+async fn repo_branch(repo_path: &StdPath) -> String {
+    if is_plain_checkout(repo_path) {
+        return ARCHIVE_BRANCH.to_string();
     }
-    if diff_output.status.code() != Some(1) {
-        return Err(AppError::GitHub(
-            "failed to inspect staged changes for .vv state".into(),
-        ));
+    match run_git_command(Some(repo_path), &["symbolic-ref", "--short", "HEAD"]).await {
+        Ok(output) if output.status.success() => {
+            let branch = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            if branch.is_empty() {
+                "main".to_string()
+            } else {
+                branch
+            }
+        }
+        _ => "main".to_string(),
     }
+}
 
-    let output = run_git_command(
-        Some(repo_path),
-        &["commit", "-m", "chore: update vv state", "--", state_path_str.as_ref()],
-    )
-    .await?;
-    if !output.status.success() {
-        return Err(AppError::GitHub(
-            "failed to commit .vv state file".into(),
-        ));
+async fn record_branch(record: &RepoRecord, repo_path: &StdPath) -> String {
+    match record.branch.as_deref() {
+        Some(branch) => branch.to_string(),
+        None => repo_branch(repo_path).await,
+    }
+}
+
+fn validate_git_ref(field: &str, value: Option<&str>) -> Result<Option<String>, AppError> {
+    let Some(value) = value.map(str::trim).filter(|value| !value.is_empty()) else {
+        return Ok(None);
+    };
+    if value.starts_with('-')
+        || value.contains("..")
+        || value.chars().any(|c| c.is_whitespace() || c.is_control())
+    {
+        return Err(AppError::InvalidRequest(format!(
+            "{field} is not a valid git ref: {value:?}"
+        )));
     }
+    Ok(Some(value.to_string()))
+}
 
+async fn checkout_repo_ref(
+    repo_path: &StdPath,
+    record: &RepoRecord,
+    git_ref: &str,
+) -> Result<(), String> {
+    let git_error = |step: &str, output: &std::process::Output| {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        format!("Git {step} of {git_ref} failed: {}", stderr.trim())
+    };
+    let fetch = run_git_command(Some(repo_path), &["fetch", "origin", git_ref])
+        .await
+        .map_err(|err| format!("Git fetch of {git_ref} failed: {err}"))?;
+    let target = if fetch.status.success() {
+        "FETCH_HEAD".to_string()
+    } else {
+        warn!("{}; trying the local ref", git_error("fetch", &fetch));
+        format!("{git_ref}^{{commit}}")
+    };
+    let resolved = run_git_command(Some(repo_path), &["rev-parse", "--verify", &target])
+        .await
+        .map_err(|err| format!("Git rev-parse of {git_ref} failed: {err}"))?;
+    if !resolved.status.success() {
+        return Err(git_error("rev-parse", &resolved));
+    }
+    let sha = String::from_utf8_lossy(&resolved.stdout).trim().to_string();
+    let args: Vec<&str> = match (is_bare_repo(repo_path), record.commit.as_deref()) {
+        (true, _) => vec!["update-ref", "--no-deref", "HEAD", &sha],
+        (false, None) => vec!["checkout", "--force", "-B", git_ref, &sha],
+        (false, Some(_)) => vec!["checkout", "--force", "--detach", &sha],
+    };
+    let output = run_git_command(Some(repo_path), &args)
+        .await
+        .map_err(|err| format!("Git checkout of {git_ref} failed: {err}"))?;
+    if !output.status.success() {
+        return Err(git_error("checkout", &output));
+    }
+    info!("checked out {} at {} for repo {}", git_ref, sha, record.id);
     Ok(())
 }
 
-async fn ingest_repo(
-    state: AppState,
-    record: RepoRecord,
-    repo_path: PathBuf,
-    vv_path: PathBuf,
-) -> Result<(), AppError> {
-    write_status(
-        &state,
-        &vv_path,
-        &record.id,
-        "in_progress",
-        Some("Cloning repository".into()),
-    )
-    .await?;
+async fn repo_head_sha(repo_path: &StdPath) -> Option<String> {
+    if is_plain_checkout(repo_path) {
+        return None;
+    }
+    match run_git_command(Some(repo_path), &["rev-parse", "HEAD"]).await {
+        Ok(output) if output.status.success() => {
+            let sha = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            (!sha.is_empty()).then_some(sha)
+        }
+        _ => None,
+    }
+}
 
-    if repo_path.exists() && !repo_path.join(".git").exists() {
-        if is_dir_empty(&repo_path).await? {
-            fs::remove_dir(&repo_path).await?;
-        } else if dir_contains_only_vv(&repo_path).await? {
+async fn repo_blob_shas(repo_path: &StdPath) -> HashMap<String, String> {
+    if is_plain_checkout(repo_path) {
+        return HashMap::new();
+    }
+    let output = match run_git_command(Some(repo_path), &["ls-tree", "-r", "-z", "HEAD"]).await {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
             warn!(
-                "repo path {} contains only vv artifacts, removing for re-clone",
-                repo_path.display()
+                "git ls-tree failed for {}: {}",
+                repo_path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
             );
-            fs::remove_dir_all(&vv_path).await.ok();
-            if is_dir_empty(&repo_path).await? {
-                fs::remove_dir(&repo_path).await?;
-            }
+            return HashMap::new();
         }
-
-        if repo_path.exists() {
-            write_status(
-                &state,
-                &vv_path,
-                &record.id,
-                "error",
-                Some("Repo path exists but is not a git repository".into()),
-            )
-            .await?;
-            return Err(AppError::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "repo path exists but is not a git repository",
-            )));
+        Err(err) => {
+            warn!("git ls-tree failed for {}: {}", repo_path.display(), err);
+            return HashMap::new();
         }
-    }
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter_map(|entry| {
+            let (meta, path) = entry.split_once('\t')?;
+            let mut parts = meta.split_whitespace();
+            let (_mode, kind, sha) = (parts.next()?, parts.next()?, parts.next()?);
+            (kind == "blob").then(|| (path.to_string(), sha.to_string()))
+        })
+        .collect()
+}
 
-    if !repo_path.exists() {
-        fs::create_dir_all(repo_path.parent().unwrap()).await?;
-        let repo_path_str = repo_path.to_string_lossy();
-        let output = run_git_command(
-            None,
-            &["clone", &record.repo_url, repo_path_str.as_ref()],
-        )
-        .await?;
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            let message = format!("Git clone failed: {}", stderr.trim());
-            write_status(&state, &vv_path, &record.id, "error", Some(message)).await?;
-            return Err(AppError::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "git clone failed",
-            )));
+async fn repo_file_commit_times(repo_path: &StdPath) -> HashMap<String, i64> {
+    if is_plain_checkout(repo_path) {
+        return HashMap::new();
+    }
+    let limit = COMMIT_TIME_SCAN_LIMIT.to_string();
+    let args = [
+        "-c",
+        "core.quotePath=false",
+        "log",
+        "-n",
+        &limit,
+        "--format=%x1e%ct",
+        "--name-only",
+        "HEAD",
+    ];
+    let output = match run_git_command(Some(repo_path), &args).await {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            warn!(
+                "git log failed for {}: {}",
+                repo_path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            return HashMap::new();
+        }
+        Err(err) => {
+            warn!("git log failed for {}: {}", repo_path.display(), err);
+            return HashMap::new();
+        }
+    };
+    let mut times = HashMap::new();
+    for entry in String::from_utf8_lossy(&output.stdout).split('\u{1e}') {
+        let mut lines = entry.lines();
+        let Some(seconds) = lines
+            .next()
+            .and_then(|line| line.trim().parse::<i64>().ok())
+        else {
+            continue;
+        };
+        for path in lines.filter(|line| !line.is_empty()) {
+            times.entry(path.to_string()).or_insert(seconds * 1000);
         }
     }
+    times
+}
 
-    let vv_state_path = write_vv_state(&repo_path, &record).await?;
-    commit_vv_state(&repo_path, &vv_state_path).await?;
-
-    write_status(
-        &state,
-        &vv_path,
-        &record.id,
-        "mirroring",
-        Some("Mirroring repository to GitHub".into()),
-    )
-    .await?;
-    mirror_repo_to_github(&state, &record, &repo_path).await?;
-
-    fs::create_dir_all(&vv_path).await?;
-    fs::create_dir_all(vv_path.join("vectors")).await?;
-    fs::create_dir_all(vv_path.join("wiki")).await?;
-
-    let manifest = serde_json::json!({
-        "repo_url": record.repo_url,
-        "owner": record.owner,
-        "name": record.name,
-        "indexed_at": Utc::now().to_rfc3339(),
-    });
-    fs::write(
-        vv_path.join("manifest.json"),
-        serde_json::to_vec_pretty(&manifest)?,
-    )
-    .await?;
-    fs::write(vv_path.join("chunks.jsonl"), "").await?;
-
-    let wiki_content = format!(
-        "# CodeWiki for {}/{}\n\nThis is a placeholder wiki generated during ingestion.\n",
-        record.owner, record.name
-    );
-    fs::write(vv_path.join("wiki/index.md"), wiki_content).await?;
+fn is_plain_checkout(repo_path: &StdPath) -> bool {
+    repo_path.exists() && !repo_path.join(".git").exists() && !is_bare_repo(repo_path)
+}
 
-    write_status(
-        &state,
-        &vv_path,
-        &record.id,
-        "indexing",
-        Some("Feeding documents to Vespa".into()),
-    )
-    .await?;
-    let indexed = feed_repo_to_vespa(&state, &record, &repo_path, &vv_path).await?;
-    info!(
-        "vespa feed completed for repo {} ({} documents)",
-        record.id, indexed
-    );
+fn is_bare_repo(repo_path: &StdPath) -> bool {
+    !repo_path.join(".git").exists()
+        && repo_path.join("HEAD").is_file()
+        && repo_path.join("objects").is_dir()
+}
 
-    write_status(
-        &state,
-        &vv_path,
-        &record.id,
-        "summarizing",
-        Some("Generating repository summary".into()),
-    )
-    .await?;
-    if let Err(err) = generate_repo_summary(&state, &record, &repo_path, &vv_path).await {
-        warn!(
-            "failed to generate summary for repo {}: {}",
-            record.id, err
-        );
+async fn read_repo_file(repo_path: &StdPath, file_path: &StdPath) -> std::io::Result<Vec<u8>> {
+    if !is_bare_repo(repo_path) {
+        return fs::read(repo_path.join(file_path)).await;
+    }
+    let object = format!("HEAD:{}", file_path.to_string_lossy());
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo_path)
+        .args(["cat-file", "blob", &object])
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
     }
+    Ok(output.stdout)
+}
 
-    write_status(
-        &state,
-        &vv_path,
-        &record.id,
-        "complete",
-        Some("Ingestion complete".into()),
-    )
-    .await?;
+async fn repo_file_size(repo_path: &StdPath, file_path: &StdPath) -> std::io::Result<u64> {
+    if !is_bare_repo(repo_path) {
+        return Ok(fs::metadata(repo_path.join(file_path)).await?.len());
+    }
+    let object = format!("HEAD:{}", file_path.to_string_lossy());
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo_path)
+        .args(["cat-file", "-s", &object])
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse::<u64>()
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+}
 
-    Ok(())
+async fn read_repo_file_within(
+    repo_path: &StdPath,
+    file_path: &StdPath,
+    max_bytes: u64,
+) -> std::io::Result<Option<Vec<u8>>> {
+    if repo_file_size(repo_path, file_path).await? > max_bytes {
+        return Ok(None);
+    }
+    if is_bare_repo(repo_path) {
+        return read_repo_file(repo_path, file_path).await.map(Some);
+    }
+    let file = fs::File::open(repo_path.join(file_path)).await?;
+    let mut bytes = Vec::new();
+    file.take(max_bytes + 1).read_to_end(&mut bytes).await?;
+    if bytes.len() as u64 > max_bytes {
+        return Ok(None);
+    }
+    Ok(Some(bytes))
 }
 
-async fn feed_repo_to_vespa(
-    state: &AppState,
-    record: &RepoRecord,
+async fn list_repo_files(
     repo_path: &StdPath,
-    vv_path: &StdPath,
-) -> Result<usize, AppError> {
-    const MAX_CONTENT_BYTES: usize = 200_000;
-
-    let files = list_repo_files(repo_path).await?;
-    let mut indexed = 0usize;
+    extra_ignore_patterns: &[String],
+) -> Result<Vec<PathBuf>, AppError> {
+    let files = list_tracked_files(repo_path).await?;
+    let ignore = load_index_ignore(repo_path, extra_ignore_patterns).await;
+    if ignore.is_empty() {
+        return Ok(files);
+    }
+    Ok(files
+        .into_iter()
+        .filter(|file_path| !ignore.is_ignored(file_path))
+        .collect())
+}
 
-    let chunks_path = vv_path.join("chunks.jsonl");
-    let mut chunks_file = fs::OpenOptions::new()
-        .append(true)
-        .create(true)
-        .open(&chunks_path)
-        .await?;
+#[derive(Default)]
+struct IndexIgnore {
+    exclude: Vec<String>,
+    include: Vec<String>,
+}
 
-    for file_path in files {
-        let absolute_path = repo_path.join(&file_path);
-        let content_bytes = match fs::read(&absolute_path).await {
-            Ok(bytes) => bytes,
-            Err(err) => {
-                error!(
-                    "skipping file {} due to read error: {}",
-                    file_path.display(),
-                    err
-                );
+impl IndexIgnore {
+    fn from_patterns<'a>(patterns: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut ignore = IndexIgnore::default();
+        for pattern in patterns {
+            let pattern = pattern.trim();
+            if pattern.is_empty() || pattern.starts_with('#') {
                 continue;
             }
-        };
-
-        if content_bytes.is_empty()
-            || content_bytes.len() > MAX_CONTENT_BYTES
-            || content_bytes.iter().any(|byte| *byte == 0)
-        {
-            continue;
-        }
-
-        let content_lossy = String::from_utf8_lossy(&content_bytes);
-        let content = sanitize_vespa_content(&content_lossy);
-        if content.trim().is_empty() {
-            continue;
-        }
-        let line_end = content.lines().count().max(1) as i32;
-        let content_sha = sha256_hex(content.as_bytes());
-        let chunk_id = sha256_hex(format!("{}:{}", record.id, file_path.display()).as_bytes());
-        let chunk_hash = sha256_hex(content.as_bytes());
-        let language = guess_language(&file_path);
-        let last_indexed_at = Utc::now().timestamp_millis();
-        let chunk_id_for_chunk = chunk_id.clone();
-        let content_sha_for_chunk = content_sha.clone();
-        let embedding_values =
-            embed_content_with_cache(state, vv_path, &content, &content_sha).await?;
-
-        let doc_id = format!("{}-{}", record.id, chunk_id);
-        let put = VespaPut {
-            fields: VespaFields {
-                repo_id: record.id.clone(),
-                repo_url: record.repo_url.clone(),
-                repo_name: record.name.clone(),
-                repo_owner: record.owner.clone(),
-                commit_sha: "unknown".to_string(),
-                branch: "main".to_string(),
-                file_path: file_path.to_string_lossy().to_string(),
-                language,
-                license_spdx: "unknown".to_string(),
-                chunk_id,
-                chunk_hash,
-                line_start: 1,
-                line_end,
-                symbol_names: Vec::new(),
-                content,
-                content_sha,
-                embedding: VespaEmbedding {
-                    values: embedding_values,
-                },
-                last_indexed_at,
-            },
-        };
-        let body_bytes = serde_json::to_vec(&put)?;
-        let document_url = vespa_document_url(state, &doc_id)?;
-        let response = state
-            .http_client
-            .post(document_url)
-            .header(reqwest::header::CONTENT_TYPE, "application/json")
-            .header(reqwest::header::ACCEPT, "application/json")
-            .body(body_bytes.clone())
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await.unwrap_or_default();
-            let preview_len = body_bytes.len().min(1024);
-            let preview = String::from_utf8_lossy(&body_bytes[..preview_len]);
-            let response_preview: String = body.chars().take(1024).collect();
-            error!(
-                "vespa feed rejected (status {}), request preview: {}, response: {}",
-                status, preview, response_preview
-            );
-            return Err(AppError::VespaRejected(body));
+            let (target, pattern) = match pattern.strip_prefix('!') {
+                Some(rest) => (&mut ignore.include, rest),
+                None => (&mut ignore.exclude, pattern),
+            };
+            let pattern = pattern.trim_end_matches('/');
+            let glob = match pattern.strip_prefix('/') {
+                Some(anchored) => anchored.to_string(),
+                None if pattern.contains('/') => pattern.to_string(),
+                None => format!("**/{pattern}"),
+            };
+            if glob.is_empty() || glob == "**/" {
+                continue;
+            }
+            if !glob.ends_with("/**") {
+                target.push(format!("{glob}/**"));
+            }
+            target.push(glob);
         }
-
-        let chunk_entry = serde_json::json!({
-            "repo_id": record.id.clone(),
-            "file_path": file_path.to_string_lossy(),
-            "chunk_id": chunk_id_for_chunk,
-            "line_start": 1,
-            "line_end": line_end,
-            "content_sha": content_sha_for_chunk,
-        });
-        let serialized = serde_json::to_string(&chunk_entry)?;
-        chunks_file.write_all(serialized.as_bytes()).await?;
-        chunks_file.write_all(b"\n").await?;
-        indexed += 1;
+        ignore
     }
 
-    Ok(indexed)
-}
+    fn is_empty(&self) -> bool {
+        self.exclude.is_empty()
+    }
 
-async fn is_dir_empty(path: &StdPath) -> Result<bool, AppError> {
-    let mut entries = fs::read_dir(path).await?;
-    Ok(entries.next_entry().await?.is_none())
+    fn is_ignored(&self, file_path: &StdPath) -> bool {
+        let path = file_path.to_string_lossy();
+        let matches = |globs: &[String]| globs.iter().any(|glob| glob_match(glob, &path));
+        matches(&self.exclude) && !matches(&self.include)
+    }
 }
 
-async fn dir_contains_only_vv(path: &StdPath) -> Result<bool, AppError> {
-    let mut entries = fs::read_dir(path).await?;
-    let mut saw_entry = false;
-    while let Some(entry) = entries.next_entry().await? {
-        saw_entry = true;
-        let name = entry.file_name();
-        if name != "vv" && name != ".vv" {
-            return Ok(false);
+async fn load_index_ignore(repo_path: &StdPath, extra_patterns: &[String]) -> IndexIgnore {
+    let mut text = String::new();
+    if is_plain_checkout(repo_path) {
+        if let Ok(gitignore) = fs::read_to_string(repo_path.join(".gitignore")).await {
+            text.push_str(&gitignore);
+            text.push('\n');
         }
     }
-    Ok(saw_entry)
+    if let Ok(bytes) = read_repo_file(repo_path, StdPath::new(".vv/indexignore")).await {
+        text.push_str(&String::from_utf8_lossy(&bytes));
+    }
+    IndexIgnore::from_patterns(
+        text.lines()
+            .chain(extra_patterns.iter().map(String::as_str)),
+    )
 }
 
-async fn list_repo_files(repo_path: &StdPath) -> Result<Vec<PathBuf>, AppError> {
-    let output = Command::new("git")
-        .arg("-C")
-        .arg(repo_path)
-        .arg("ls-files")
-        .output()
-        .await;
+async fn list_tracked_files(repo_path: &StdPath) -> Result<Vec<PathBuf>, AppError> {
+    if is_plain_checkout(repo_path) {
+        return walk_repo_files(repo_path).await;
+    }
+    let bare = is_bare_repo(repo_path);
+    let mut command = Command::new("git");
+    command.arg("-C").arg(repo_path);
+    if bare {
+        command.args(["ls-tree", "-r", "--name-only", "HEAD"]);
+    } else {
+        command.arg("ls-files");
+    }
+    let output = command.output().await;
 
     if let Ok(output) = output {
         if output.status.success() {
@@ -1509,6 +7625,9 @@ async fn list_repo_files(repo_path: &StdPath) -> Result<Vec<PathBuf>, AppError>
     } else if let Err(err) = output {
         warn!("git ls-files failed for {}: {}", repo_path.display(), err);
     }
+    if bare {
+        return Ok(Vec::new());
+    }
 
     walk_repo_files(repo_path).await
 }
@@ -1562,81 +7681,438 @@ fn should_skip_dir(name: &str) -> bool {
     )
 }
 
+fn validate_vespa_identifier(variable: &str, value: &str) -> Result<String, AppError> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(AppError::Config(format!("{variable} must not be empty")));
+    }
+    if let Some(invalid) = value
+        .chars()
+        .find(|ch| !(ch.is_ascii_alphanumeric() || matches!(ch, '_' | '-' | '.')))
+    {
+        return Err(AppError::Config(format!(
+            "{variable} contains invalid character {invalid:?} (allowed: letters, digits, '_', '-', '.'; got {value:?})"
+        )));
+    }
+    Ok(value.to_string())
+}
+
+fn validate_vespa_endpoint(variable: &str, value: &str) -> Result<String, AppError> {
+    let value = value.trim().trim_end_matches('/');
+    if value.is_empty() {
+        return Ok(String::new());
+    }
+    let valid = reqwest::Url::parse(value)
+        .map(|url| matches!(url.scheme(), "http" | "https") && url.host_str().is_some())
+        .unwrap_or(false);
+    if !valid {
+        return Err(AppError::Config(format!(
+            "{variable} must be an http(s) URL such as http://localhost:8080 (got {value:?})"
+        )));
+    }
+    Ok(value.to_string())
+}
+
 fn vespa_document_url(state: &AppState, doc_id: &str) -> Result<String, AppError> {
     if state.vespa_document_endpoint.trim().is_empty() {
         return Err(AppError::Config(
             "VESPA_DOCUMENT_ENDPOINT or VESPA_ENDPOINT must be set".into(),
         ));
     }
-    Ok(format!(
+    Ok(document_v1_url(
+        &state.vespa_document_endpoint,
+        &state.vespa_namespace,
+        &state.vespa_document_type,
+        doc_id,
+    ))
+}
+
+fn document_v1_url(endpoint: &str, namespace: &str, document_type: &str, doc_id: &str) -> String {
+    format!(
         "{}/document/v1/{}/{}/docid/{}",
-        state.vespa_document_endpoint.trim_end_matches('/'),
-        state.vespa_namespace,
-        state.vespa_document_type,
+        endpoint.trim_end_matches('/'),
+        namespace,
+        document_type,
         urlencoding::encode(doc_id)
+    )
+}
+
+fn vespa_search_url(state: &AppState) -> Result<String, AppError> {
+    if state.vespa_endpoint.trim().is_empty() {
+        return Err(AppError::Config("VESPA_ENDPOINT must be set".into()));
+    }
+    Ok(format!(
+        "{}{}",
+        state.vespa_endpoint.trim_end_matches('/'),
+        state.vespa_search_path
     ))
 }
 
-fn vespa_search_url(state: &AppState) -> Result<String, AppError> {
-    if state.vespa_endpoint.trim().is_empty() {
-        return Err(AppError::Config(
-            "VESPA_ENDPOINT must be set".into(),
-        ));
+fn with_query_profile(state: &AppState, mut body: serde_json::Value) -> serde_json::Value {
+    if let (Some(profile), Some(object)) = (&state.vespa_query_profile, body.as_object_mut()) {
+        object.insert("queryProfile".to_string(), profile.clone().into());
+    }
+    body
+}
+
+async fn repo_indexed_in_vespa(state: &AppState, repo_id: &str) -> Result<bool, AppError> {
+    if state.search_backend == SearchBackend::Local {
+        return Ok(state.local_index.read().await.contains_repo(repo_id));
+    }
+    if state.vespa_endpoint.trim().is_empty() {
+        return Ok(false);
+    }
+    Ok(count_vespa_repo_documents(state, repo_id, None).await? > 0)
+}
+
+async fn count_vespa_repo_documents(
+    state: &AppState,
+    repo_id: &str,
+    doc_type: Option<&str>,
+) -> Result<i64, AppError> {
+    let search_url = vespa_search_url(state)?;
+    let escaped = repo_id.replace('"', "");
+    let doc_type_clause = doc_type
+        .map(|doc_type| format!(" and doc_type contains {}", yql_quote(doc_type)))
+        .unwrap_or_default();
+    let yql = format!(
+        "select repo_id from sources * where repo_id = \"{}\"{};",
+        escaped, doc_type_clause
+    );
+    let body = with_query_profile(
+        state,
+        serde_json::json!({
+            "yql": yql,
+            "hits": 0
+        }),
+    );
+    let response = state
+        .http_client
+        .post(search_url)
+        .json(&body)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(AppError::VespaRejected(body));
+    }
+    let status = response.status();
+    let text = response.text().await?;
+    let body = parse_vespa_json(status, &text)?;
+    let total = body
+        .pointer("/root/fields/totalCount")
+        .and_then(|value| value.as_i64())
+        .unwrap_or(0);
+    Ok(total)
+}
+
+async fn verify_vespa_feed(
+    state: &AppState,
+    record: &RepoRecord,
+    vv_path: &StdPath,
+    indexed: usize,
+) -> Result<(), AppError> {
+    let chunks = fs::read_to_string(vv_path.join("chunks.jsonl"))
+        .await
+        .unwrap_or_default();
+    let chunk_ids: Vec<String> = chunks
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .filter(|entry| entry.get("duplicate_of").is_none())
+        .filter_map(|entry| entry.get("chunk_id")?.as_str().map(str::to_string))
+        .collect();
+    let step = (chunk_ids.len() / VERIFY_FEED_SAMPLE_SIZE).max(1);
+    let mut missing = Vec::new();
+    for chunk_id in chunk_ids.iter().step_by(step).take(VERIFY_FEED_SAMPLE_SIZE) {
+        let doc_id = format!("{}-{}", record.id, chunk_id);
+        let response = state
+            .http_client
+            .get(vespa_document_url(state, &doc_id)?)
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            missing.push(doc_id);
+        } else if !response.status().is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(AppError::VespaRejected(body));
+        }
+    }
+    if !missing.is_empty() {
+        return Err(AppError::VespaRejected(format!(
+            "feed verification failed: {} sampled documents not found ({})",
+            missing.len(),
+            missing.join(", ")
+        )));
+    }
+
+    let expected = indexed as i64;
+    let minimum = expected - (expected as f64 * VERIFY_FEED_TOLERANCE).floor() as i64;
+    let mut visible = 0;
+    for attempt in 1..=VERIFY_FEED_ATTEMPTS {
+        visible = count_vespa_repo_documents(state, &record.id, Some(DOC_TYPE_CODE)).await?;
+        if visible >= minimum {
+            info!(
+                "feed verified for repo {}: {} of {} documents visible",
+                record.id, visible, expected
+            );
+            return Ok(());
+        }
+        warn!(
+            "feed verification for repo {}: {} of {} documents visible (attempt {}/{})",
+            record.id, visible, expected, attempt, VERIFY_FEED_ATTEMPTS
+        );
+        if attempt < VERIFY_FEED_ATTEMPTS {
+            tokio::time::sleep(Duration::from_secs(VERIFY_FEED_RETRY_SECS)).await;
+        }
+    }
+    Err(AppError::VespaRejected(format!(
+        "feed verification failed: only {visible} of {expected} fed documents are searchable"
+    )))
+}
+
+fn parse_vespa_json(status: StatusCode, body: &str) -> Result<serde_json::Value, AppError> {
+    serde_json::from_str(body).map_err(|err| {
+        let preview: String = body.chars().take(1024).collect();
+        error!(
+            "vespa returned an unparseable body (status {}): {}, response: {}",
+            status, err, preview
+        );
+        AppError::VespaRejected(format!(
+            "unparseable response from Vespa (status {status}): {err}; body preview: {preview}"
+        ))
+    })
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    hex::encode(hasher.finalize())
+}
+
+fn sanitize_vespa_content(input: &str) -> String {
+    input
+        .chars()
+        .filter(|ch| match ch {
+            '\n' | '\r' | '\t' => true,
+            _ => !ch.is_control(),
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SnippetMode {
+    Clean,
+    Raw,
+}
+
+impl SnippetMode {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "clean" => Some(SnippetMode::Clean),
+            "raw" => Some(SnippetMode::Raw),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct SearchFilters {
+    repo_ids: Option<Vec<String>>,
+    doc_types: Vec<String>,
+    commit_sha: Option<String>,
+    languages: Vec<String>,
+    /// Applied to hit scores rather than in YQL.
+    min_score: Option<f64>,
+}
+
+impl SearchFilters {
+    fn yql_clauses(&self) -> Vec<String> {
+        let mut clauses = Vec::new();
+        if let Some(repo_ids) = self.repo_ids.as_deref() {
+            clauses.push(format!("repo_id in ({})", yql_string_list(repo_ids)));
+        }
+        if !self.doc_types.is_empty() {
+            clauses.push(format!(
+                "doc_type in ({})",
+                yql_string_list(&self.doc_types)
+            ));
+        }
+        if let Some(commit_sha) = self.commit_sha.as_deref() {
+            clauses.push(format!("commit_sha contains {}", yql_quote(commit_sha)));
+        }
+        if !self.languages.is_empty() {
+            clauses.push(format!(
+                "language in ({})",
+                yql_string_list(&self.languages)
+            ));
+        }
+        clauses
+    }
+
+    fn matches(&self, document: &LocalDocument) -> bool {
+        if self
+            .repo_ids
+            .as_ref()
+            .is_some_and(|repo_ids| !repo_ids.contains(&document.repo_id))
+        {
+            return false;
+        }
+        if self
+            .commit_sha
+            .as_ref()
+            .is_some_and(|commit_sha| document.commit_sha.as_ref() != Some(commit_sha))
+        {
+            return false;
+        }
+        if !self.languages.is_empty()
+            && !self
+                .languages
+                .contains(&guess_language(StdPath::new(&document.file_path)))
+        {
+            return false;
+        }
+        self.doc_types.is_empty() || self.doc_types.contains(&document.doc_type)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ResultOptions {
+    include_embedding_model: bool,
+    snippet_mode: SnippetMode,
+    snippet_lines: usize,
+    explain: bool,
+    rich: bool,
+    rank_features: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum SearchMode {
+    Hybrid,
+    Semantic,
+    Bm25,
+    Path,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SearchBackend {
+    Vespa,
+    Local,
+}
+
+fn parse_search_backend(value: &str) -> Option<SearchBackend> {
+    match value.trim().to_lowercase().as_str() {
+        "vespa" => Some(SearchBackend::Vespa),
+        "local" => Some(SearchBackend::Local),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RepoPathLayout {
+    OwnerName,
+    Flat,
+    Id,
+}
+
+impl RepoPathLayout {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "owner-name" | "owner/name" => Some(RepoPathLayout::OwnerName),
+            "flat" => Some(RepoPathLayout::Flat),
+            "id" => Some(RepoPathLayout::Id),
+            _ => None,
+        }
+    }
+
+    fn repo_path(self, repos_path: &StdPath, record: &RepoRecord) -> PathBuf {
+        match self {
+            RepoPathLayout::OwnerName => repos_path.join(&record.owner).join(&record.name),
+            RepoPathLayout::Flat => {
+                let sanitize = |value: &str| -> String {
+                    value
+                        .chars()
+                        .map(|ch| {
+                            if ch.is_ascii_alphanumeric() || matches!(ch, '-' | '.') {
+                                ch
+                            } else {
+                                '_'
+                            }
+                        })
+                        .collect()
+                };
+                repos_path.join(format!(
+                    "{}__{}",
+                    sanitize(&record.owner),
+                    sanitize(&record.name)
+                ))
+            }
+            RepoPathLayout::Id => repos_path.join(&record.id),
+        }
+    }
+}
+
+fn repo_dir(state: &AppState, record: &RepoRecord) -> PathBuf {
+    state.repo_path_layout.repo_path(&state.repos_path, record)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SummaryFailureMode {
+    Ignore,
+    WarnStatus,
+    Fail,
+}
+
+fn parse_summary_failure_mode(value: &str) -> Option<SummaryFailureMode> {
+    match value.trim().to_lowercase().as_str() {
+        "ignore" => Some(SummaryFailureMode::Ignore),
+        "warn_status" => Some(SummaryFailureMode::WarnStatus),
+        "fail" => Some(SummaryFailureMode::Fail),
+        _ => None,
     }
-    Ok(format!(
-        "{}/search/",
-        state.vespa_endpoint.trim_end_matches('/')
-    ))
 }
 
-async fn repo_indexed_in_vespa(state: &AppState, repo_id: &str) -> Result<bool, AppError> {
-    if state.vespa_endpoint.trim().is_empty() {
-        return Ok(false);
-    }
-    let search_url = vespa_search_url(state)?;
-    let escaped = repo_id.replace('"', "");
-    let yql = format!(
-        "select repo_id from sources * where repo_id = \"{}\";",
-        escaped
-    );
-    let body = serde_json::json!({
-        "yql": yql,
-        "hits": 0
-    });
-    let response = state.http_client.post(search_url).json(&body).send().await?;
-    if !response.status().is_success() {
-        let body = response.text().await.unwrap_or_default();
-        return Err(AppError::VespaRejected(body));
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EmbeddingHashMode {
+    Raw,
+    Normalized,
+}
+
+fn parse_embedding_hash_mode(value: &str) -> Option<EmbeddingHashMode> {
+    match value.trim().to_lowercase().as_str() {
+        "raw" => Some(EmbeddingHashMode::Raw),
+        "normalized" => Some(EmbeddingHashMode::Normalized),
+        _ => None,
     }
-    let body: serde_json::Value = response.json().await?;
-    let total = body
-        .pointer("/root/fields/totalCount")
-        .and_then(|value| value.as_i64())
-        .unwrap_or(0);
-    Ok(total > 0)
 }
 
-fn sha256_hex(data: &[u8]) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(data);
-    hex::encode(hasher.finalize())
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VespaTensorEncoding {
+    Json,
+    Hex,
 }
 
-fn sanitize_vespa_content(input: &str) -> String {
-    input
-        .chars()
-        .filter(|ch| match ch {
-            '\n' | '\r' | '\t' => true,
-            _ => !ch.is_control(),
-        })
-        .collect()
+fn parse_vespa_tensor_encoding(value: &str) -> Option<VespaTensorEncoding> {
+    match value.trim().to_lowercase().as_str() {
+        "json" => Some(VespaTensorEncoding::Json),
+        "hex" => Some(VespaTensorEncoding::Hex),
+        _ => None,
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
-enum SearchMode {
-    Hybrid,
-    Semantic,
-    Bm25,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WikiMarkdownMode {
+    Overwrite,
+    Preserve,
+    Append,
+}
+
+fn parse_wiki_markdown_mode(value: &str) -> Option<WikiMarkdownMode> {
+    match value.trim().to_lowercase().as_str() {
+        "overwrite" => Some(WikiMarkdownMode::Overwrite),
+        "preserve" => Some(WikiMarkdownMode::Preserve),
+        "append" => Some(WikiMarkdownMode::Append),
+        _ => None,
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -1646,21 +8122,39 @@ enum SummaryProvider {
 }
 
 impl SearchMode {
+    fn name(self) -> &'static str {
+        match self {
+            SearchMode::Hybrid => "hybrid",
+            SearchMode::Semantic => "semantic",
+            SearchMode::Bm25 => "bm25",
+            SearchMode::Path => "path",
+        }
+    }
+
     fn profile_name(self) -> Option<&'static str> {
         match self {
             SearchMode::Hybrid => Some("hybrid"),
             SearchMode::Semantic => Some("semantic"),
-            SearchMode::Bm25 => None,
+            SearchMode::Bm25 => Some("bm25"),
+            SearchMode::Path => Some("path"),
         }
     }
 }
 
-fn resolve_search_mode(value: Option<&str>) -> SearchMode {
-    let mode = value.unwrap_or("bm25").trim().to_lowercase();
-    match mode.as_str() {
-        "semantic" => SearchMode::Semantic,
-        "bm25" => SearchMode::Bm25,
-        _ => SearchMode::Hybrid,
+fn parse_search_mode(value: &str) -> Option<SearchMode> {
+    match value.trim().to_lowercase().as_str() {
+        "hybrid" => Some(SearchMode::Hybrid),
+        "semantic" => Some(SearchMode::Semantic),
+        "bm25" => Some(SearchMode::Bm25),
+        "path" => Some(SearchMode::Path),
+        _ => None,
+    }
+}
+
+fn resolve_search_mode(value: Option<&str>, default: SearchMode) -> SearchMode {
+    match value.map(str::trim).filter(|value| !value.is_empty()) {
+        Some(value) => parse_search_mode(value).unwrap_or(SearchMode::Hybrid),
+        None => default,
     }
 }
 
@@ -1668,7 +8162,7 @@ fn resolve_summary_provider(value: Option<&str>) -> SummaryProvider {
     let mode = value.unwrap_or(SUMMARY_PROVIDER_HF).trim().to_lowercase();
     match mode.as_str() {
         "hf" | "huggingface" => SummaryProvider::HuggingFace,
-        "colab" | "google" | "google-colab" => SummaryProvider::Colab,
+        SUMMARY_PROVIDER_COLAB | "google" | "google-colab" => SummaryProvider::Colab,
         _ => SummaryProvider::HuggingFace,
     }
 }
@@ -1680,41 +8174,132 @@ fn truncate_for_embedding<'a>(input: &'a str, max_chars: usize) -> Cow<'a, str>
     Cow::Owned(input.chars().take(max_chars).collect())
 }
 
+fn truncate_for_embedding_tokens<'a>(
+    tokenizer: &tokenizers::Tokenizer,
+    input: &'a str,
+    max_tokens: usize,
+) -> Option<Cow<'a, str>> {
+    let encoding = tokenizer.encode(input, false).ok()?;
+    let special_tokens = tokenizer
+        .get_post_processor()
+        .map(|processor| tokenizers::PostProcessor::added_tokens(processor, false))
+        .unwrap_or(0);
+    let budget = max_tokens.saturating_sub(special_tokens).max(1);
+    let offsets = encoding.get_offsets();
+    if offsets.len() <= budget {
+        return Some(Cow::Borrowed(input));
+    }
+    let mut end = offsets[budget - 1].1.min(input.len());
+    while !input.is_char_boundary(end) {
+        end -= 1;
+    }
+    Some(Cow::Owned(input[..end].to_string()))
+}
+
+async fn load_embedding_tokenizer(
+    client: &reqwest::Client,
+    model: &str,
+    token: Option<&str>,
+) -> Result<tokenizers::Tokenizer, AppError> {
+    let bytes = match std::env::var("EMBEDDING_TOKENIZER_PATH") {
+        Ok(path) => fs::read(&path).await?,
+        Err(_) => {
+            let url = format!("https://huggingface.co/{model}/resolve/main/tokenizer.json");
+            let mut request = client.get(&url);
+            if let Some(token) = token {
+                request = request.bearer_auth(token);
+            }
+            let response = request.send().await?;
+            if !response.status().is_success() {
+                return Err(AppError::HuggingFace(format!(
+                    "tokenizer download from {url} failed with status {}",
+                    response.status()
+                )));
+            }
+            response.bytes().await?.to_vec()
+        }
+    };
+    tokenizers::Tokenizer::from_bytes(&bytes)
+        .map_err(|err| AppError::Config(format!("invalid tokenizer.json: {err}")))
+}
+
 fn truncate_for_summary<'a>(input: &'a str, max_chars: usize) -> Cow<'a, str> {
     truncate_for_embedding(input, max_chars)
 }
 
-fn normalize_embedding(mut values: Vec<f32>) -> Vec<f32> {
-    if values.len() == EMBEDDING_DIM {
+fn normalize_embedding(mut values: Vec<f32>, dimension: usize) -> Vec<f32> {
+    if values.len() == dimension {
         return values;
     }
     warn!(
         "embedding dimension mismatch: got {}, expected {}",
         values.len(),
-        EMBEDDING_DIM
+        dimension
     );
-    if values.len() > EMBEDDING_DIM {
-        values.truncate(EMBEDDING_DIM);
+    if values.len() > dimension {
+        values.truncate(dimension);
     } else {
-        values.resize(EMBEDDING_DIM, 0.0);
+        values.resize(dimension, 0.0);
+    }
+    values
+}
+
+fn l2_normalize(values: &mut [f32]) {
+    let norm = values.iter().map(|value| value * value).sum::<f32>().sqrt();
+    if norm == 0.0 || !norm.is_finite() {
+        warn!("embedding has zero or non-finite norm; leaving it unnormalized");
+        return;
+    }
+    for value in values.iter_mut() {
+        *value /= norm;
+    }
+}
+
+/// A pinned `query_embedding` gets the same `NORMALIZE_EMBEDDINGS` treatment as embedded queries,
+/// so closeness against normalized document vectors stays comparable.
+fn pinned_query_embedding(mut values: Vec<f32>, normalize: bool) -> Vec<f32> {
+    if normalize {
+        l2_normalize(&mut values);
     }
     values
 }
 
+fn record_embedding_dimension(state: &AppState, model: &str, dimension: usize) {
+    if dimension == 0
+        || state
+            .detected_embedding_dim
+            .compare_exchange(0, dimension, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+    {
+        return;
+    }
+    let expected = state.embedding_provider.dimension();
+    if dimension == expected {
+        info!("embedding model {} returns {} dimensions", model, dimension);
+        return;
+    }
+    warn!(
+        "embedding model {} returns {} dimensions but {} are configured; vectors will be truncated or zero-padded",
+        model, dimension, expected
+    );
+    warn!(
+        "schema hint: your Vespa schema must declare tensor<float>(x[N]) with N={} for the embedding field and query(query_embedding) input",
+        dimension
+    );
+}
+
 fn parse_hf_embedding(value: serde_json::Value) -> Result<Vec<f32>, AppError> {
     match value {
         serde_json::Value::Array(values) => {
             if values.is_empty() {
-                return Err(AppError::HuggingFace(
-                    "empty embedding response".into(),
-                ));
+                return Err(AppError::HuggingFace("empty embedding response".into()));
             }
             if values[0].is_number() {
                 let mut embedding = Vec::with_capacity(values.len());
                 for value in values {
-                    let number = value.as_f64().ok_or_else(|| {
-                        AppError::HuggingFace("invalid embedding value".into())
-                    })?;
+                    let number = value
+                        .as_f64()
+                        .ok_or_else(|| AppError::HuggingFace("invalid embedding value".into()))?;
                     embedding.push(number as f32);
                 }
                 return Ok(embedding);
@@ -1724,9 +8309,9 @@ fn parse_hf_embedding(value: serde_json::Value) -> Result<Vec<f32>, AppError> {
                 let mut summed: Vec<f32> = Vec::new();
                 let mut count = 0usize;
                 for row in values {
-                    let row_values = row.as_array().ok_or_else(|| {
-                        AppError::HuggingFace("invalid embedding row".into())
-                    })?;
+                    let row_values = row
+                        .as_array()
+                        .ok_or_else(|| AppError::HuggingFace("invalid embedding row".into()))?;
                     if summed.is_empty() {
                         summed = vec![0.0; row_values.len()];
                     }
@@ -1741,9 +8326,7 @@ fn parse_hf_embedding(value: serde_json::Value) -> Result<Vec<f32>, AppError> {
                     count += 1;
                 }
                 if count == 0 {
-                    return Err(AppError::HuggingFace(
-                        "empty embedding response".into(),
-                    ));
+                    return Err(AppError::HuggingFace("empty embedding response".into()));
                 }
                 for value in &mut summed {
                     *value /= count as f32;
@@ -1755,10 +8338,27 @@ fn parse_hf_embedding(value: serde_json::Value) -> Result<Vec<f32>, AppError> {
                 "unsupported embedding response format".into(),
             ))
         }
-        serde_json::Value::Object(map) => {
+        serde_json::Value::Object(mut map) => {
             if let Some(error) = map.get("error").and_then(|value| value.as_str()) {
                 return Err(AppError::HuggingFace(error.to_string()));
             }
+            if let Some(embeddings) = map.remove("embeddings").or_else(|| map.remove("embedding")) {
+                return parse_hf_embedding(embeddings);
+            }
+            if let Some(serde_json::Value::Array(mut data)) = map.remove("data") {
+                if data.is_empty() {
+                    return Err(AppError::HuggingFace("empty embedding response".into()));
+                }
+                return match data.swap_remove(0) {
+                    serde_json::Value::Object(mut item) => match item.remove("embedding") {
+                        Some(embedding) => parse_hf_embedding(embedding),
+                        None => Err(AppError::HuggingFace(
+                            "embedding response data[0] has no \"embedding\" key".into(),
+                        )),
+                    },
+                    other => parse_hf_embedding(other),
+                };
+            }
             Err(AppError::HuggingFace(
                 "unexpected embedding response".into(),
             ))
@@ -1769,43 +8369,176 @@ fn parse_hf_embedding(value: serde_json::Value) -> Result<Vec<f32>, AppError> {
     }
 }
 
-async fn fetch_hf_embedding(state: &AppState, text: &str) -> Result<Vec<f32>, AppError> {
-    let base_url = state.huggingface_base_url.trim_end_matches('/');
-    let url = format!(
-        "{}/{}/pipeline/feature-extraction",
-        base_url, state.huggingface_model
-    );
-    let payload = serde_json::json!({
-        "inputs": text,
-        "options": { "wait_for_model": true }
-    });
+#[derive(Clone, Copy)]
+struct EmbeddingRetryPolicy {
+    max_retries: usize,
+    backoff_ms: u64,
+    backoff_max_ms: u64,
+}
 
-    let max_retries = state.huggingface_max_retries;
-    let mut backoff = Duration::from_millis(state.huggingface_backoff_ms);
-    let backoff_max = Duration::from_millis(state.huggingface_backoff_max_ms);
+#[derive(Clone, Copy)]
+enum EmbeddingProviderKind {
+    HuggingFace,
+    OpenAi,
+}
 
-    for attempt in 0..=max_retries {
-        let mut request = state.hf_client.post(&url).json(&payload);
-        if let Some(token) = state.huggingface_token.as_deref() {
-            request = request.bearer_auth(token);
-        }
+fn parse_embedding_provider(value: Option<&str>) -> Result<EmbeddingProviderKind, AppError> {
+    let Some(value) = value.map(str::trim).filter(|value| !value.is_empty()) else {
+        return Ok(EmbeddingProviderKind::HuggingFace);
+    };
+    match value.to_lowercase().as_str() {
+        "huggingface" | "hf" => Ok(EmbeddingProviderKind::HuggingFace),
+        "openai" => Ok(EmbeddingProviderKind::OpenAi),
+        _ => Err(AppError::Config(format!(
+            "EMBEDDING_PROVIDER must be one of huggingface, openai (got {value:?})"
+        ))),
+    }
+}
 
-        match request.send().await {
+trait EmbeddingProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn default_model(&self) -> &str;
+    fn dimension(&self) -> usize;
+    fn embed<'a>(
+        &'a self,
+        model: &'a str,
+        text: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<f32>, AppError>>;
+}
+
+struct HuggingFaceProvider {
+    client: reqwest::Client,
+    base_url: String,
+    token: Option<String>,
+    model: String,
+    dimension: usize,
+    retry: EmbeddingRetryPolicy,
+}
+
+impl EmbeddingProvider for HuggingFaceProvider {
+    fn name(&self) -> &'static str {
+        "huggingface"
+    }
+
+    fn default_model(&self) -> &str {
+        &self.model
+    }
+
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn embed<'a>(
+        &'a self,
+        model: &'a str,
+        text: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<f32>, AppError>> {
+        Box::pin(async move {
+            let base_url = self.base_url.trim_end_matches('/');
+            let url = format!("{}/{}/pipeline/feature-extraction", base_url, model);
+            let payload = serde_json::json!({
+                "inputs": text,
+                "options": { "wait_for_model": true }
+            });
+            let value =
+                send_embedding_request(self.name(), self.retry, AppError::HuggingFace, || {
+                    let request = self.client.post(&url).json(&payload);
+                    match self.token.as_deref() {
+                        Some(token) => request.bearer_auth(token),
+                        None => request,
+                    }
+                })
+                .await?;
+            parse_hf_embedding(value)
+        })
+    }
+}
+
+struct OpenAiProvider {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: String,
+    model: String,
+    dimension: usize,
+    retry: EmbeddingRetryPolicy,
+}
+
+impl EmbeddingProvider for OpenAiProvider {
+    fn name(&self) -> &'static str {
+        "openai"
+    }
+
+    fn default_model(&self) -> &str {
+        &self.model
+    }
+
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn embed<'a>(
+        &'a self,
+        model: &'a str,
+        text: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<f32>, AppError>> {
+        Box::pin(async move {
+            let url = format!("{}/embeddings", self.base_url.trim_end_matches('/'));
+            let payload = serde_json::json!({
+                "model": model,
+                "input": text,
+                "dimensions": self.dimension,
+            });
+            let value = send_embedding_request(self.name(), self.retry, AppError::OpenAi, || {
+                self.client
+                    .post(&url)
+                    .bearer_auth(&self.api_key)
+                    .json(&payload)
+            })
+            .await?;
+            let values = value
+                .pointer("/data/0/embedding")
+                .and_then(|embedding| embedding.as_array())
+                .filter(|values| !values.is_empty())
+                .ok_or_else(|| AppError::OpenAi("unexpected embedding response".into()))?;
+            values
+                .iter()
+                .map(|value| {
+                    value
+                        .as_f64()
+                        .map(|number| number as f32)
+                        .ok_or_else(|| AppError::OpenAi("invalid embedding value".into()))
+                })
+                .collect()
+        })
+    }
+}
+
+async fn send_embedding_request(
+    provider: &'static str,
+    retry: EmbeddingRetryPolicy,
+    provider_error: fn(String) -> AppError,
+    build_request: impl Fn() -> reqwest::RequestBuilder,
+) -> Result<serde_json::Value, AppError> {
+    let max_retries = retry.max_retries;
+    let mut backoff = Duration::from_millis(retry.backoff_ms);
+    let backoff_max = Duration::from_millis(retry.backoff_max_ms);
+
+    for attempt in 0..=max_retries {
+        match build_request().send().await {
             Ok(response) => {
                 if response.status().is_success() {
-                    let value: serde_json::Value = response
+                    return response
                         .json()
                         .await
-                        .map_err(|err| AppError::HuggingFace(err.to_string()))?;
-                    let embedding = parse_hf_embedding(value)?;
-                    return Ok(normalize_embedding(embedding));
+                        .map_err(|err| provider_error(err.to_string()));
                 }
 
                 let status = response.status();
                 let body = response.text().await.unwrap_or_default();
                 if attempt < max_retries && should_retry_status(status) {
+                    counter!("embedding_retries_total", "provider" => provider).increment(1);
                     warn!(
-                        "huggingface embedding request failed with {status}; retrying in {:?} (attempt {}/{})",
+                        "{provider} embedding request failed with {status}; retrying in {:?} (attempt {}/{})",
                         backoff,
                         attempt + 1,
                         max_retries
@@ -1815,15 +8548,16 @@ async fn fetch_hf_embedding(state: &AppState, text: &str) -> Result<Vec<f32>, Ap
                     continue;
                 }
 
-                return Err(AppError::HuggingFace(format!(
+                return Err(provider_error(format!(
                     "embedding request failed: {status} {body}"
                 )));
             }
             Err(err) => {
                 let detail = format_reqwest_error(&err);
                 if attempt < max_retries {
+                    counter!("embedding_retries_total", "provider" => provider).increment(1);
                     warn!(
-                        "huggingface embedding request failed to send: {detail}; retrying in {:?} (attempt {}/{})",
+                        "{provider} embedding request failed to send: {detail}; retrying in {:?} (attempt {}/{})",
                         backoff,
                         attempt + 1,
                         max_retries
@@ -1833,16 +8567,14 @@ async fn fetch_hf_embedding(state: &AppState, text: &str) -> Result<Vec<f32>, Ap
                     continue;
                 }
 
-                return Err(AppError::HuggingFace(format!(
+                return Err(provider_error(format!(
                     "embedding request failed to send: {detail}"
                 )));
             }
         }
     }
 
-    Err(AppError::HuggingFace(
-        "embedding request exhausted retries".into(),
-    ))
+    Err(provider_error("embedding request exhausted retries".into()))
 }
 
 fn should_retry_status(status: StatusCode) -> bool {
@@ -1856,11 +8588,11 @@ fn format_reqwest_error(err: &reqwest::Error) -> String {
     if let Some(url) = err.url() {
         parts.push(format!("url={url}"));
     }
-    if err.is_timeout() {
-        parts.push("timeout".into());
-    }
-    if err.is_connect() {
-        parts.push("connect".into());
+    match (err.is_connect(), err.is_timeout()) {
+        (true, true) => parts.push("connect timeout".into()),
+        (true, false) => parts.push("connect".into()),
+        (false, true) => parts.push("request timeout".into()),
+        (false, false) => {}
     }
     if err.is_request() {
         parts.push("request".into());
@@ -1884,43 +8616,373 @@ fn format_reqwest_error(err: &reqwest::Error) -> String {
     } else {
         format!("{err} ({})", parts.join(", "))
     }
-}
+}
+
+fn describe_rate_limit_reset(reset_at: Option<i64>) -> String {
+    match reset_at.and_then(|reset_at| chrono::DateTime::from_timestamp(reset_at, 0)) {
+        Some(reset_at) => format!(" (resets at {})", reset_at.to_rfc3339()),
+        None => String::new(),
+    }
+}
+
+fn github_rate_limit(response: &reqwest::Response) -> Option<AppError> {
+    let status = response.status();
+    if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<i64>().ok())
+    };
+    let remaining = header("x-ratelimit-remaining");
+    let retry_after = header("retry-after");
+    if status == StatusCode::FORBIDDEN && remaining != Some(0) && retry_after.is_none() {
+        return None;
+    }
+    let reset_at = header("x-ratelimit-reset")
+        .or_else(|| retry_after.map(|seconds| Utc::now().timestamp() + seconds));
+    Some(AppError::GitHubRateLimited(reset_at))
+}
+
+async fn retry_github_rate_limited<T, F, Fut>(label: &str, mut call: F) -> Result<T, AppError>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, AppError>>,
+{
+    let mut attempt = 0;
+    loop {
+        match call().await {
+            Err(AppError::GitHubRateLimited(reset_at)) if attempt < GITHUB_RATE_LIMIT_RETRIES => {
+                let wait_secs = reset_at
+                    .map(|reset_at| reset_at - Utc::now().timestamp() + 1)
+                    .unwrap_or(60)
+                    .max(1) as u64;
+                if wait_secs > GITHUB_RATE_LIMIT_MAX_WAIT_SECS {
+                    return Err(AppError::GitHubRateLimited(reset_at));
+                }
+                warn!(
+                    "{}: GitHub rate limit hit{}, retrying in {}s",
+                    label,
+                    describe_rate_limit_reset(reset_at),
+                    wait_secs
+                );
+                tokio::time::sleep(Duration::from_secs(wait_secs)).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn describe_vespa_error(err: &reqwest::Error) -> String {
+    let detail = format_reqwest_error(err);
+    if err.is_connect() {
+        format!("Vespa unreachable: {detail}")
+    } else if err.is_timeout() {
+        format!("Vespa slow to respond (request timed out): {detail}")
+    } else {
+        detail
+    }
+}
+
+async fn embed_query(state: &AppState, model: &str, query: &str) -> Result<Vec<f32>, AppError> {
+    let cached = state.query_embedding_cache.lock().await.get(model, query);
+    if let Some(values) = cached {
+        counter!("query_embedding_cache_requests_total", "result" => "hit").increment(1);
+        return Ok(values);
+    }
+    counter!("query_embedding_cache_requests_total", "result" => "miss").increment(1);
+    let values = embed_text(state, model, query).await?;
+    state
+        .query_embedding_cache
+        .lock()
+        .await
+        .insert(model, query, values.clone());
+    Ok(values)
+}
+
+async fn embed_text(state: &AppState, model: &str, text: &str) -> Result<Vec<f32>, AppError> {
+    let token_truncated = match (&state.embedding_tokenizer, state.embedding_max_tokens) {
+        (Some(tokenizer), Some(max_tokens)) if model == state.huggingface_model => {
+            truncate_for_embedding_tokens(tokenizer, text, max_tokens)
+        }
+        _ => None,
+    };
+    let truncated = token_truncated
+        .unwrap_or_else(|| truncate_for_embedding(text, state.huggingface_max_chars));
+    let _permit = match state.embedding_semaphore.clone().try_acquire_owned() {
+        Ok(permit) => permit,
+        Err(_) => {
+            info!(
+                "waiting for an embedding slot (EMBEDDING_MAX_CONCURRENCY={})",
+                state.embedding_max_concurrency
+            );
+            state
+                .embedding_semaphore
+                .clone()
+                .acquire_owned()
+                .await
+                .map_err(|err| AppError::HuggingFace(err.to_string()))?
+        }
+    };
+    let provider = state.embedding_provider.as_ref();
+    let embedding = provider.embed(model, truncated.as_ref()).await?;
+    record_embedding_dimension(state, model, embedding.len());
+    let mut embedding = normalize_embedding(embedding, provider.dimension());
+    if state.normalize_embeddings {
+        l2_normalize(&mut embedding);
+    }
+    Ok(embedding)
+}
+
+fn repo_embedding_model<'a>(state: &'a AppState, record: &'a RepoRecord) -> &'a str {
+    record
+        .embedding_model
+        .as_deref()
+        .unwrap_or(state.embedding_provider.default_model())
+}
+
+fn embedding_cache_key(model: &str, content_sha: &str) -> String {
+    sha256_hex(format!("{model}:{content_sha}").as_bytes())
+}
+
+fn embedding_content_sha(state: &AppState, content: &str, content_sha: &str) -> String {
+    match state.embedding_hash_mode {
+        EmbeddingHashMode::Raw => content_sha.to_string(),
+        EmbeddingHashMode::Normalized => {
+            let normalized = content.split_whitespace().collect::<Vec<_>>().join(" ");
+            sha256_hex(normalized.as_bytes())
+        }
+    }
+}
+
+async fn resolve_query_embedding_model(state: &AppState, repo_scope: Option<&[String]>) -> String {
+    let registry = state.registry.read().await;
+    let default_model = state.embedding_provider.default_model();
+    match scoped_embedding_model(&registry, repo_scope, default_model) {
+        Ok(model) => model.to_string(),
+        Err(models) => {
+            warn!(
+                "search spans repos with different embedding models ({}); using default {}",
+                models.join(", "),
+                default_model
+            );
+            default_model.to_string()
+        }
+    }
+}
+
+fn scoped_embedding_model<'a>(
+    records: &'a [RepoRecord],
+    repo_scope: Option<&[String]>,
+    default_model: &'a str,
+) -> Result<&'a str, Vec<&'a str>> {
+    let mut models: Vec<&str> = Vec::new();
+    for record in records {
+        if repo_scope.is_some_and(|repo_ids| !repo_ids.contains(&record.id)) {
+            continue;
+        }
+        let model = record.embedding_model.as_deref().unwrap_or(default_model);
+        if !models.contains(&model) {
+            models.push(model);
+        }
+    }
+    match models.as_slice() {
+        [model] => Ok(model),
+        [] => Ok(default_model),
+        _ => Err(models),
+    }
+}
+
+async fn embed_content_with_cache(
+    state: &AppState,
+    vv_path: &StdPath,
+    model: &str,
+    content: &str,
+    content_sha: &str,
+) -> Result<Vec<f32>, AppError> {
+    let vectors_path = vv_path.join("vectors");
+    fs::create_dir_all(&vectors_path).await?;
+    let embedding_sha = embedding_content_sha(state, content, content_sha);
+    let cache_key = embedding_cache_key(model, &embedding_sha);
+    // Older versions keyed the default model's vectors by the bare content hash.
+    let legacy_key = (model == state.huggingface_model).then_some(embedding_sha.as_str());
+    let lock_key = vectors_path.join(&cache_key).to_string_lossy().to_string();
+    let lock = state.embedding_locks.acquire(&lock_key);
+    let result = {
+        let _guard = lock.lock().await;
+        embed_content_locked(
+            &vectors_path,
+            &cache_key,
+            legacy_key,
+            state.embedding_provider.dimension(),
+            state.normalize_embeddings,
+            embed_text(state, model, content),
+        )
+        .await
+    };
+    state.embedding_locks.release(&lock_key, lock);
+    let (embedding, cached) = result?;
+    let outcome = if cached { "hit" } else { "miss" };
+    counter!("embedding_cache_requests_total", "result" => outcome).increment(1);
+    Ok(embedding)
+}
+
+async fn embed_content_locked(
+    vectors_path: &StdPath,
+    cache_key: &str,
+    legacy_key: Option<&str>,
+    dimension: usize,
+    normalize: bool,
+    embed: impl std::future::Future<Output = Result<Vec<f32>, AppError>>,
+) -> Result<(Vec<f32>, bool), AppError> {
+    for key in std::iter::once(cache_key).chain(legacy_key) {
+        let Some(mut values) = read_cached_embedding(vectors_path, key).await else {
+            continue;
+        };
+        if values.len() != dimension {
+            warn!(
+                "cached embedding dimension mismatch for {} (got {}, expected {})",
+                key,
+                values.len(),
+                dimension
+            );
+            continue;
+        }
+        if key != cache_key && write_cached_embedding(vectors_path, cache_key, &values).await {
+            fs::remove_file(vectors_path.join(format!("{key}.f32")))
+                .await
+                .ok();
+        }
+        if normalize {
+            l2_normalize(&mut values);
+        }
+        return Ok((values, true));
+    }
+
+    let embedding = embed.await?;
+    write_cached_embedding(vectors_path, cache_key, &embedding).await;
+    Ok((embedding, false))
+}
+
+async fn gc_vectors_cache(
+    state: &AppState,
+    record: &RepoRecord,
+    vv_path: &StdPath,
+) -> Result<VectorsGcReport, AppError> {
+    let vectors_path = vv_path.join("vectors");
+    let mut report = VectorsGcReport::default();
+    if !vectors_path.exists() {
+        return Ok(report);
+    }
+    let model = repo_embedding_model(state, record);
+    let chunks = fs::read_to_string(vv_path.join("chunks.jsonl"))
+        .await
+        .unwrap_or_default();
+    let referenced: HashSet<String> = chunks
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .filter_map(|entry| {
+            let sha = entry
+                .get("embedding_sha")
+                .or_else(|| entry.get("content_sha"))?;
+            Some(embedding_cache_key(model, sha.as_str()?))
+        })
+        .collect();
+
+    let mut kept = Vec::new();
+    let mut entries = fs::read_dir(&vectors_path).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let metadata = entry.metadata().await?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let key = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if referenced.contains(&key) {
+            let used_at = metadata.accessed().or_else(|_| metadata.modified()).ok();
+            kept.push((used_at, metadata.len(), path));
+            continue;
+        }
+        fs::remove_file(&path).await?;
+        report.removed_unreferenced += 1;
+        report.bytes_reclaimed += metadata.len();
+    }
 
-async fn embed_text(state: &AppState, text: &str) -> Result<Vec<f32>, AppError> {
-    let truncated = truncate_for_embedding(text, state.huggingface_max_chars);
-    fetch_hf_embedding(state, truncated.as_ref()).await
+    report.remaining_bytes = kept.iter().map(|(_, size, _)| size).sum();
+    if let Some(max_bytes) = state.vectors_cache_max_bytes {
+        kept.sort_by_key(|(used_at, _, _)| *used_at);
+        for (_, size, path) in kept {
+            if report.remaining_bytes <= max_bytes {
+                break;
+            }
+            fs::remove_file(&path).await?;
+            report.removed_for_size += 1;
+            report.bytes_reclaimed += size;
+            report.remaining_bytes -= size;
+        }
+    }
+    info!(
+        "vectors cache gc for repo {}: removed {} unreferenced and {} for size, {} bytes reclaimed, {} bytes remaining",
+        record.id,
+        report.removed_unreferenced,
+        report.removed_for_size,
+        report.bytes_reclaimed,
+        report.remaining_bytes
+    );
+    Ok(report)
 }
 
-async fn embed_content_with_cache(
-    state: &AppState,
-    vv_path: &StdPath,
-    content: &str,
-    content_sha: &str,
-) -> Result<Vec<f32>, AppError> {
-    let vectors_path = vv_path.join("vectors");
-    fs::create_dir_all(&vectors_path).await?;
-    let cache_path = vectors_path.join(format!("{content_sha}.json"));
-    if let Ok(data) = fs::read(&cache_path).await {
-        if let Ok(values) = serde_json::from_slice::<Vec<f32>>(&data) {
-            if values.len() == EMBEDDING_DIM {
-                return Ok(values);
-            }
-            warn!(
-                "cached embedding dimension mismatch for {} (got {}, expected {})",
-                cache_path.display(),
-                values.len(),
-                EMBEDDING_DIM
+async fn read_cached_embedding(vectors_path: &StdPath, content_sha: &str) -> Option<Vec<f32>> {
+    let binary_path = vectors_path.join(format!("{content_sha}.f32"));
+    if let Ok(data) = fs::read(&binary_path).await {
+        if !data.is_empty() && data.len() % 4 == 0 {
+            return Some(
+                data.chunks_exact(4)
+                    .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                    .collect(),
             );
         }
+        warn!("ignoring corrupt embedding cache {}", binary_path.display());
     }
 
-    let embedding = embed_text(state, content).await?;
-    if let Ok(serialized) = serde_json::to_vec(&embedding) {
-        if let Err(err) = fs::write(&cache_path, serialized).await {
-            warn!("failed to cache embedding at {}: {err}", cache_path.display());
+    let legacy_path = vectors_path.join(format!("{content_sha}.json"));
+    let data = fs::read(&legacy_path).await.ok()?;
+    let values = serde_json::from_slice::<Vec<f32>>(&data).ok()?;
+    if write_cached_embedding(vectors_path, content_sha, &values).await {
+        fs::remove_file(&legacy_path).await.ok();
+    }
+    Some(values)
+}
+
+async fn write_cached_embedding(vectors_path: &StdPath, content_sha: &str, values: &[f32]) -> bool {
+    let cache_path = vectors_path.join(format!("{content_sha}.f32"));
+    let temp_path = vectors_path.join(format!("{content_sha}.{}.tmp", Uuid::new_v4()));
+    let bytes: Vec<u8> = values
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect();
+    let written = match fs::write(&temp_path, bytes).await {
+        Ok(()) => fs::rename(&temp_path, &cache_path).await,
+        Err(err) => Err(err),
+    };
+    match written {
+        Ok(()) => true,
+        Err(err) => {
+            let _ = fs::remove_file(&temp_path).await;
+            warn!(
+                "failed to cache embedding at {}: {err}",
+                cache_path.display()
+            );
+            false
         }
     }
-    Ok(embedding)
 }
 
 async fn read_repo_readme(repo_path: &StdPath) -> Option<String> {
@@ -1934,9 +8996,8 @@ async fn read_repo_readme(repo_path: &StdPath) -> Option<String> {
         "readme",
     ];
     for name in candidates {
-        let path = repo_path.join(name);
-        if fs::metadata(&path).await.is_ok() {
-            if let Ok(content) = fs::read_to_string(&path).await {
+        if let Ok(bytes) = read_repo_file(repo_path, StdPath::new(name)).await {
+            if let Ok(content) = String::from_utf8(bytes) {
                 return Some(content);
             }
         }
@@ -1948,21 +9009,46 @@ async fn build_repo_summary_input(
     state: &AppState,
     record: &RepoRecord,
     repo_path: &StdPath,
+    summary_limit: usize,
 ) -> Result<String, AppError> {
-    let files = list_repo_files(repo_path).await?;
-    let mut language_counts: HashMap<String, usize> = HashMap::new();
-    let mut file_lines = Vec::new();
+    let files = list_repo_files(repo_path, &state.index_ignore_patterns).await?;
     let top_files = state.huggingface_summary_top_files;
-    for file in &files {
-        let language = guess_language(file);
-        *language_counts.entry(language).or_insert(0) += 1;
-        if file_lines.len() < top_files {
-            file_lines.push(format!("- {}", file.to_string_lossy()));
-        }
+    let file_lines: Vec<String> = files
+        .iter()
+        .take(top_files)
+        .map(|file| format!("- {}", file.to_string_lossy()))
+        .collect();
+
+    let mut input = summary_header(record, &files);
+    if !file_lines.is_empty() {
+        input.push_str(&format!("\nFile tree (first {} files):\n", top_files));
+        input.push_str(&file_lines.join("\n"));
+        input.push('\n');
+    }
+    if let Some(readme) = read_repo_readme(repo_path).await {
+        let cleaned = sanitize_vespa_content(readme.as_str());
+        let excerpt = truncate_for_summary(&cleaned, (summary_limit / 2).min(1600));
+        input.push_str("\nREADME excerpt:\n");
+        input.push_str(excerpt.as_ref());
+        input.push('\n');
+    }
+    if let Some(release) = read_latest_release_notes(repo_path).await {
+        let excerpt = truncate_for_summary(&release, (summary_limit / 4).min(800));
+        input.push_str("\nLatest release:\n");
+        input.push_str(excerpt.as_ref());
+        input.push('\n');
     }
 
+    Ok(truncate_for_summary(&input, summary_limit).into_owned())
+}
+
+fn summary_header(record: &RepoRecord, files: &[PathBuf]) -> String {
+    let mut language_counts: HashMap<String, usize> = HashMap::new();
+    for file in files {
+        *language_counts.entry(guess_language(file)).or_insert(0) += 1;
+    }
     let mut languages: Vec<(String, usize)> = language_counts.into_iter().collect();
-    languages.sort_by(|a, b| b.1.cmp(&a.1));
+    languages.sort_by_key(|entry| std::cmp::Reverse(entry.1));
     let language_summary = languages
         .into_iter()
         .take(8)
@@ -1970,29 +9056,118 @@ async fn build_repo_summary_input(
         .collect::<Vec<_>>()
         .join(", ");
 
-    let mut input = String::new();
-    input.push_str(&format!("Repository: {}/{}\n", record.owner, record.name));
+    let mut header = String::new();
+    header.push_str(&format!("Repository: {}/{}\n", record.owner, record.name));
     if !language_summary.is_empty() {
-        input.push_str(&format!("\nLanguages: {language_summary}\n"));
+        header.push_str(&format!("\nLanguages: {language_summary}\n"));
     }
-    if !file_lines.is_empty() {
-        input.push_str(&format!(
-            "\nFile tree (first {} files):\n",
-            top_files
-        ));
-        input.push_str(&file_lines.join("\n"));
-        input.push('\n');
+    header
+}
+
+fn split_summary_sections(title: &str, text: &str, max_chars: usize, sections: &mut Vec<String>) {
+    let mut current = String::new();
+    for line in text.lines() {
+        let line = truncate_for_summary(line, max_chars / 2);
+        if !current.is_empty() && current.chars().count() + line.chars().count() + 1 > max_chars {
+            sections.push(std::mem::take(&mut current));
+        }
+        if current.is_empty() {
+            current.push_str(title);
+            current.push('\n');
+        }
+        current.push_str(line.as_ref());
+        current.push('\n');
+    }
+    if !current.is_empty() {
+        sections.push(current);
     }
-    let summary_limit = state.huggingface_summary_max_chars;
+}
+
+async fn build_repo_summary_sections(
+    state: &AppState,
+    record: &RepoRecord,
+    repo_path: &StdPath,
+    max_chars: usize,
+) -> Result<Vec<String>, AppError> {
+    let files = list_repo_files(repo_path, &state.index_ignore_patterns).await?;
+    let max_chars = max_chars.max(200);
+    let mut sections = Vec::new();
+    let file_tree = files
+        .iter()
+        .map(|file| format!("- {}", file.to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join("\n");
+    split_summary_sections(
+        &format!("File tree of {}/{}:", record.owner, record.name),
+        &file_tree,
+        max_chars,
+        &mut sections,
+    );
     if let Some(readme) = read_repo_readme(repo_path).await {
         let cleaned = sanitize_vespa_content(readme.as_str());
-        let excerpt = truncate_for_summary(&cleaned, (summary_limit / 2).min(1600));
-        input.push_str("\nREADME excerpt:\n");
-        input.push_str(excerpt.as_ref());
-        input.push('\n');
+        split_summary_sections(
+            &format!("README of {}/{}:", record.owner, record.name),
+            &cleaned,
+            max_chars,
+            &mut sections,
+        );
+    }
+    if let Some(release) = read_latest_release_notes(repo_path).await {
+        split_summary_sections(
+            &format!("Latest release of {}/{}:", record.owner, record.name),
+            &release,
+            max_chars,
+            &mut sections,
+        );
     }
+    sections.truncate(SUMMARY_MAX_SECTIONS);
+    Ok(sections)
+}
 
-    Ok(truncate_for_summary(&input, summary_limit).into_owned())
+async fn reduce_summary_sections(
+    state: &AppState,
+    record: &RepoRecord,
+    repo_path: &StdPath,
+    sections: Vec<String>,
+    max_chars: usize,
+) -> Result<String, AppError> {
+    let section_count = sections.len();
+    let mut partials: Vec<(usize, String)> = stream::iter(sections.into_iter().enumerate())
+        .map(|(position, section)| async move {
+            let result = fetch_summary_with_params(state, &section, 120, 30).await;
+            (position, result)
+        })
+        .buffer_unordered(state.summary_concurrency)
+        .filter_map(|(position, result)| async move {
+            match result {
+                Ok(summary) => Some((position, summary)),
+                Err(err) => {
+                    warn!(
+                        "dropping summary section {} for repo {}: {}",
+                        position + 1,
+                        record.id,
+                        err
+                    );
+                    None
+                }
+            }
+        })
+        .collect()
+        .await;
+    if partials.is_empty() {
+        return Err(AppError::HuggingFace(format!(
+            "all {section_count} summary sections failed"
+        )));
+    }
+    partials.sort_by_key(|(position, _)| *position);
+
+    let files = list_repo_files(repo_path, &state.index_ignore_patterns).await?;
+    let mut input = summary_header(record, &files);
+    input.push_str("\nSection summaries:\n");
+    for (_, summary) in partials {
+        input.push_str(&format!("- {}\n", summary.trim()));
+    }
+    Ok(truncate_for_summary(&input, max_chars).into_owned())
 }
 
 fn extract_summary_text(value: &serde_json::Value) -> Option<String> {
@@ -2023,9 +9198,9 @@ fn extract_summary_text(value: &serde_json::Value) -> Option<String> {
 fn parse_summary_response(value: serde_json::Value) -> Result<String, AppError> {
     match value {
         serde_json::Value::Array(values) => {
-            let first = values.get(0).ok_or_else(|| {
-                AppError::HuggingFace("empty summary response".into())
-            })?;
+            let first = values
+                .first()
+                .ok_or_else(|| AppError::HuggingFace("empty summary response".into()))?;
             if let Some(summary) = extract_summary_text(first) {
                 return Ok(summary);
             }
@@ -2040,20 +9215,12 @@ fn parse_summary_response(value: serde_json::Value) -> Result<String, AppError>
             if let Some(summary) = extract_summary_text(&serde_json::Value::Object(map.clone())) {
                 return Ok(summary);
             }
-            Err(AppError::HuggingFace(
-                "unexpected summary response".into(),
-            ))
+            Err(AppError::HuggingFace("unexpected summary response".into()))
         }
-        _ => Err(AppError::HuggingFace(
-            "unexpected summary response".into(),
-        )),
+        _ => Err(AppError::HuggingFace("unexpected summary response".into())),
     }
 }
 
-async fn fetch_hf_summary(state: &AppState, text: &str) -> Result<String, AppError> {
-    fetch_hf_summary_with_params(state, text, 160, 40).await
-}
-
 async fn fetch_hf_summary_with_params(
     state: &AppState,
     text: &str,
@@ -2099,6 +9266,7 @@ async fn fetch_hf_summary_with_params(
                 let status = response.status();
                 let body = response.text().await.unwrap_or_default();
                 if attempt < max_retries && should_retry_status(status) {
+                    counter!("summary_retries_total", "provider" => "huggingface").increment(1);
                     warn!(
                         "huggingface summary request failed with {status}; retrying in {:?} (attempt {}/{})",
                         backoff,
@@ -2117,6 +9285,7 @@ async fn fetch_hf_summary_with_params(
             Err(err) => {
                 let detail = format_reqwest_error(&err);
                 if attempt < max_retries {
+                    counter!("summary_retries_total", "provider" => "huggingface").increment(1);
                     warn!(
                         "huggingface summary request failed to send: {detail}; retrying in {:?} (attempt {}/{})",
                         backoff,
@@ -2182,6 +9351,7 @@ async fn fetch_colab_summary_with_params(
                 let status = response.status();
                 let body = response.text().await.unwrap_or_default();
                 if attempt < max_retries && should_retry_status(status) {
+                    counter!("summary_retries_total", "provider" => "colab").increment(1);
                     warn!(
                         "colab summary request failed with {status}; retrying in {:?} (attempt {}/{})",
                         backoff,
@@ -2200,6 +9370,7 @@ async fn fetch_colab_summary_with_params(
             Err(err) => {
                 let detail = format_reqwest_error(&err);
                 if attempt < max_retries {
+                    counter!("summary_retries_total", "provider" => "colab").increment(1);
                     warn!(
                         "colab summary request failed to send: {detail}; retrying in {:?} (attempt {}/{})",
                         backoff,
@@ -2239,49 +9410,522 @@ async fn fetch_summary_with_params(
     }
 }
 
+fn summary_model_name(state: &AppState) -> String {
+    match state.summary_provider {
+        SummaryProvider::HuggingFace => state.huggingface_summary_model.clone(),
+        SummaryProvider::Colab => SUMMARY_PROVIDER_COLAB.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SummaryDetail {
+    Short,
+    Standard,
+    Detailed,
+}
+
+impl SummaryDetail {
+    fn parse(value: &str) -> Option<SummaryDetail> {
+        match value.trim().to_lowercase().as_str() {
+            "short" => Some(SummaryDetail::Short),
+            "standard" => Some(SummaryDetail::Standard),
+            "detailed" => Some(SummaryDetail::Detailed),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SummaryDetail::Short => "short",
+            SummaryDetail::Standard => "standard",
+            SummaryDetail::Detailed => "detailed",
+        }
+    }
+
+    fn input_chars(self, base: usize) -> usize {
+        match self {
+            SummaryDetail::Short => base / 2,
+            SummaryDetail::Standard => base,
+            SummaryDetail::Detailed => base * 2,
+        }
+    }
+
+    fn summary_lengths(self) -> (u32, u32) {
+        match self {
+            SummaryDetail::Short => (80, 20),
+            SummaryDetail::Standard => (160, 40),
+            SummaryDetail::Detailed => (220, 80),
+        }
+    }
+
+    fn long_summary_lengths(self) -> (u32, u32) {
+        match self {
+            SummaryDetail::Short => (160, 40),
+            SummaryDetail::Standard => (280, 90),
+            SummaryDetail::Detailed => (480, 160),
+        }
+    }
+}
+
 async fn generate_repo_summary(
     state: &AppState,
     record: &RepoRecord,
     repo_path: &StdPath,
     vv_path: &StdPath,
 ) -> Result<SummaryStore, AppError> {
-    let input = build_repo_summary_input(state, record, repo_path).await?;
-    let summary = match fetch_summary_with_params(state, input.as_ref(), 160, 40).await {
-        Ok(summary) => summary,
+    generate_repo_summary_with_detail(state, record, repo_path, vv_path, SummaryDetail::Standard)
+        .await
+}
+
+async fn fetch_summary_with_fallback(
+    state: &AppState,
+    input: &str,
+    max_length: u32,
+    min_length: u32,
+) -> Result<String, AppError> {
+    match fetch_summary_with_params(state, input, max_length, min_length).await {
+        Ok(summary) => Ok(summary),
         Err(AppError::HuggingFace(message))
-            if message.contains("index out of range")
-                || message.contains("Bad Request") =>
+            if message.contains("index out of range") || message.contains("Bad Request") =>
         {
-            let shorter = truncate_for_summary(input.as_ref(), 1600);
-            fetch_summary_with_params(state, shorter.as_ref(), 160, 40).await?
+            let shorter = truncate_for_summary(input, 1600);
+            fetch_summary_with_params(state, shorter.as_ref(), max_length, min_length).await
         }
-        Err(err) => return Err(err),
+        Err(err) => Err(err),
+    }
+}
+
+async fn generate_repo_summary_with_detail(
+    state: &AppState,
+    record: &RepoRecord,
+    repo_path: &StdPath,
+    vv_path: &StdPath,
+    detail: SummaryDetail,
+) -> Result<SummaryStore, AppError> {
+    let max_chars = detail.input_chars(state.huggingface_summary_max_chars);
+    let sections = build_repo_summary_sections(state, record, repo_path, max_chars).await?;
+    let section_chars: usize = sections.iter().map(|section| section.chars().count()).sum();
+    let input = if sections.len() > 1 && section_chars > max_chars {
+        info!(
+            "summarizing repo {} in {} sections (concurrency {})",
+            record.id,
+            sections.len(),
+            state.summary_concurrency
+        );
+        reduce_summary_sections(state, record, repo_path, sections, max_chars).await?
+    } else {
+        build_repo_summary_input(state, record, repo_path, max_chars).await?
     };
-    let long_summary = match fetch_summary_with_params(state, input.as_ref(), 280, 90).await {
-        Ok(summary) => summary,
-        Err(AppError::HuggingFace(message))
-            if message.contains("index out of range")
-                || message.contains("Bad Request") =>
-        {
-            let shorter = truncate_for_summary(input.as_ref(), 1600);
-            fetch_summary_with_params(state, shorter.as_ref(), 280, 90).await?
+    let (max_length, min_length) = detail.summary_lengths();
+    let summary = fetch_summary_with_fallback(state, &input, max_length, min_length).await?;
+    let (max_length, min_length) = detail.long_summary_lengths();
+    let long_summary = fetch_summary_with_fallback(state, &input, max_length, min_length).await?;
+    let mut store = read_summary_store(vv_path).await.unwrap_or_default();
+    let entry = SummaryEntry {
+        version: store.next_version(),
+        created_at: Utc::now().timestamp_millis(),
+        summary: summary.clone(),
+        long_summary: long_summary.clone(),
+        model: Some(summary_model_name(state)),
+        detail: Some(detail.name().to_string()),
+    };
+    let previous = store.clone();
+    store.entries.push(entry);
+    write_summary_store(vv_path, &store).await?;
+    if let Err(err) = write_wiki_markdown(state, record, vv_path, &previous, &summary).await {
+        warn!(
+            "failed to update wiki markdown for repo {}: {}",
+            record.id, err
+        );
+    }
+    Ok(store)
+}
+
+fn placeholder_wiki_markdown(record: &RepoRecord) -> String {
+    format!(
+        "# CodeWiki for {}/{}\n\nThis is a placeholder wiki generated during ingestion.\n",
+        record.owner, record.name
+    )
+}
+
+fn is_generated_wiki_markdown(record: &RepoRecord, store: &SummaryStore, content: &str) -> bool {
+    let content = content.trim();
+    content.is_empty()
+        || content == placeholder_wiki_markdown(record).trim()
+        || store
+            .entries
+            .iter()
+            .any(|entry| entry.summary.trim() == content)
+}
+
+async fn write_wiki_markdown(
+    state: &AppState,
+    record: &RepoRecord,
+    vv_path: &StdPath,
+    previous: &SummaryStore,
+    summary: &str,
+) -> Result<(), AppError> {
+    let wiki_path = vv_path.join("wiki/index.md");
+    let existing = fs::read_to_string(&wiki_path)
+        .await
+        .ok()
+        .filter(|content| !is_generated_wiki_markdown(record, previous, content));
+    match (state.wiki_markdown_mode, existing) {
+        (WikiMarkdownMode::Overwrite, _) | (_, None) => {
+            fs::write(&wiki_path, summary).await?;
+        }
+        (WikiMarkdownMode::Preserve, Some(_)) => {
+            info!("keeping hand-edited wiki markdown for repo {}", record.id);
+        }
+        (WikiMarkdownMode::Append, Some(content)) => {
+            let section = format!(
+                "{}\n\n## Generated summary ({})\n\n{}\n",
+                content.trim_end(),
+                Utc::now().format("%Y-%m-%d"),
+                summary.trim()
+            );
+            fs::write(&wiki_path, section).await?;
+        }
+    }
+    Ok(())
+}
+
+fn tokenize_for_local_index(text: &str) -> Vec<String> {
+    text.split(|ch: char| !(ch.is_alphanumeric() || ch == '_'))
+        .filter(|term| !term.is_empty())
+        .map(|term| term.to_lowercase())
+        .collect()
+}
+
+fn path_search_tokens(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    for word in text.split(|ch: char| !ch.is_alphanumeric()) {
+        let chars: Vec<char> = word.chars().collect();
+        let mut start = 0;
+        for index in 1..chars.len() {
+            let (previous, current) = (chars[index - 1], chars[index]);
+            let next_is_lower = chars.get(index + 1).is_some_and(|next| next.is_lowercase());
+            let boundary = (previous.is_lowercase() && current.is_uppercase())
+                || (previous.is_uppercase() && current.is_uppercase() && next_is_lower)
+                || (previous.is_alphabetic() != current.is_alphabetic());
+            if boundary {
+                tokens.push(
+                    chars[start..index]
+                        .iter()
+                        .collect::<String>()
+                        .to_lowercase(),
+                );
+                start = index;
+            }
+        }
+        if start < chars.len() {
+            tokens.push(chars[start..].iter().collect::<String>().to_lowercase());
+        }
+    }
+    tokens
+}
+
+fn path_match_score(query_terms: &[String], file_path: &str) -> Option<f64> {
+    if query_terms.is_empty() {
+        return None;
+    }
+    let path_terms = path_search_tokens(file_path);
+    let matched = query_terms
+        .iter()
+        .filter(|term| path_terms.contains(term))
+        .count();
+    if matched == 0 {
+        return None;
+    }
+    let file_name = file_path.rsplit('/').next().unwrap_or(file_path);
+    let name_terms = path_search_tokens(file_name);
+    let name_matched = name_terms
+        .iter()
+        .filter(|term| query_terms.contains(term))
+        .count();
+    Some(
+        matched as f64 / query_terms.len() as f64
+            + 0.5 * name_matched as f64 / name_terms.len().max(1) as f64,
+    )
+}
+
+fn cosine_similarity(left: &[f32], right: &[f32]) -> f64 {
+    let mut dot = 0.0f64;
+    let mut left_norm = 0.0f64;
+    let mut right_norm = 0.0f64;
+    for (a, b) in left.iter().zip(right.iter()) {
+        dot += (*a as f64) * (*b as f64);
+        left_norm += (*a as f64) * (*a as f64);
+        right_norm += (*b as f64) * (*b as f64);
+    }
+    if left_norm == 0.0 || right_norm == 0.0 {
+        return 0.0;
+    }
+    dot / (left_norm.sqrt() * right_norm.sqrt())
+}
+
+async fn search_local_index(
+    state: &AppState,
+    query: &str,
+    mode: SearchMode,
+    filters: &SearchFilters,
+    pinned_embedding: Option<Vec<f32>>,
+    page: SearchPage,
+    options: ResultOptions,
+) -> Result<(Vec<SearchResult>, u64), AppError> {
+    let query_model = resolve_query_embedding_model(state, filters.repo_ids.as_deref()).await;
+    let query_embedding = match mode {
+        SearchMode::Bm25 | SearchMode::Path => None,
+        _ if pinned_embedding.is_some() => pinned_embedding,
+        SearchMode::Semantic => Some(embed_query(state, &query_model, query).await?),
+        SearchMode::Hybrid => match embed_query(state, &query_model, query).await {
+            Ok(values) => Some(values),
+            Err(err) => {
+                warn!("local search: query embedding failed, using keyword scores only: {err}");
+                None
+            }
+        },
+    };
+    let index = state.local_index.read().await;
+    let bm25 = if matches!(mode, SearchMode::Hybrid | SearchMode::Bm25) {
+        index.bm25_scores(query)
+    } else {
+        HashMap::new()
+    };
+    let mut path_terms = path_search_tokens(query);
+    path_terms.sort();
+    path_terms.dedup();
+
+    let mut scored: Vec<(f64, &String, &LocalDocument, BTreeMap<String, f64>)> = Vec::new();
+    for (doc_id, document) in &index.documents {
+        if !filters.matches(document) {
+            continue;
+        }
+        if matches!(mode, SearchMode::Path) {
+            if document.doc_type != DOC_TYPE_CODE {
+                continue;
+            }
+            if let Some(score) = path_match_score(&path_terms, &document.file_path) {
+                let features = BTreeMap::from([("path_match".to_string(), score)]);
+                scored.push((score, doc_id, document, features));
+            }
+            continue;
+        }
+        let keyword = bm25.get(doc_id).copied();
+        let semantic = match (&query_embedding, &document.embedding) {
+            (Some(query), Some(embedding)) if document.embedding_model == query_model => {
+                Some(cosine_similarity(query, embedding))
+            }
+            _ => None,
+        };
+        let score = match mode {
+            SearchMode::Bm25 => keyword,
+            SearchMode::Semantic => semantic,
+            SearchMode::Path => None,
+            SearchMode::Hybrid => match (keyword, semantic) {
+                (None, None) => None,
+                (keyword, semantic) => Some(keyword.unwrap_or(0.0) + semantic.unwrap_or(0.0)),
+            },
+        };
+        let above_min_score = |score: &f64| filters.min_score.is_none_or(|min| *score >= min);
+        if let Some(score) = score.filter(above_min_score) {
+            let mut features = BTreeMap::new();
+            if let Some(keyword) = keyword {
+                features.insert("bm25(content)".to_string(), keyword);
+            }
+            if let Some(semantic) = semantic {
+                features.insert("cosine(embedding)".to_string(), semantic);
+            }
+            scored.push((score, doc_id, document, features));
+        }
+    }
+    scored.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    let total_count = scored.len() as u64;
+    let mut query_terms = if matches!(mode, SearchMode::Hybrid | SearchMode::Bm25) {
+        tokenize_for_local_index(query)
+    } else {
+        Vec::new()
+    };
+    let mut seen_terms = HashSet::new();
+    query_terms.retain(|term| seen_terms.insert(term.clone()));
+
+    let results = scored
+        .into_iter()
+        .skip(page.offset)
+        .take(page.hits)
+        .map(|(score, doc_id, document, features)| SearchResult {
+            doc_id: Some(doc_id.clone()),
+            repo_id: document.repo_id.clone(),
+            file_path: document.file_path.clone(),
+            line_start: document.line_start,
+            line_end: document.line_end,
+            snippet: build_snippet(
+                &document.content,
+                StdPath::new(&document.file_path),
+                document.line_start,
+                query,
+                options,
+            ),
+            doc_type: Some(document.doc_type.clone()),
+            commit_sha: document.commit_sha.clone(),
+            blob_sha: document.blob_sha.clone(),
+            committed_at: document.committed_at,
+            embedding_model: options
+                .include_embedding_model
+                .then(|| document.embedding_model.clone()),
+            explanation: options
+                .explain
+                .then(|| ResultExplanation::new(score, features.clone())),
+            score,
+            language: options
+                .rich
+                .then(|| guess_language(StdPath::new(&document.file_path))),
+            lines: options.rich.then(|| {
+                snippet_lines(
+                    &document.content,
+                    StdPath::new(&document.file_path),
+                    document.line_start,
+                    query,
+                    options,
+                )
+            }),
+            highlights: {
+                let content_terms: HashSet<String> = tokenize_for_local_index(&document.content)
+                    .into_iter()
+                    .collect();
+                query_terms
+                    .iter()
+                    .filter(|term| content_terms.contains(*term))
+                    .cloned()
+                    .collect()
+            },
+            relevance: options.rank_features.then_some(score),
+            rank_features: options.rank_features.then_some(features),
+            also_in: document.also_in.clone(),
+        })
+        .collect();
+    Ok((results, total_count))
+}
+
+async fn rebuild_local_index(state: &AppState) -> usize {
+    let records = state.registry.read().await.clone();
+    let mut loaded = 0usize;
+    for record in records {
+        let repo_path = repo_dir(state, &record);
+        let vv_path = repo_path.join("vv");
+        let chunks = match fs::read_to_string(vv_path.join("chunks.jsonl")).await {
+            Ok(chunks) => chunks,
+            Err(_) => continue,
+        };
+        let embedding_model = repo_embedding_model(state, &record);
+        let entries: Vec<serde_json::Value> = chunks
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect();
+        let mut index = state.local_index.write().await;
+        for entry in &entries {
+            if entry.get("duplicate_of").is_some() {
+                continue;
+            }
+            let chunk_field = |name: &str| {
+                entry
+                    .get(name)
+                    .and_then(|value| value.as_str())
+                    .map(str::to_string)
+            };
+            let file_path = entry
+                .get("file_path")
+                .and_then(|value| value.as_str())
+                .unwrap_or_default();
+            let chunk_id = entry
+                .get("chunk_id")
+                .and_then(|value| value.as_str())
+                .unwrap_or_default();
+            if file_path.is_empty() || chunk_id.is_empty() {
+                continue;
+            }
+            let line_start = entry
+                .get("line_start")
+                .and_then(|value| value.as_u64())
+                .unwrap_or(1)
+                .max(1) as usize;
+            let line_end = entry
+                .get("line_end")
+                .and_then(|value| value.as_u64())
+                .unwrap_or(line_start as u64) as usize;
+            let Ok(bytes) = read_repo_file(&repo_path, StdPath::new(file_path)).await else {
+                continue;
+            };
+            let (decoded, _) = decode_source_bytes(&bytes, state.detect_encoding);
+            let text = sanitize_vespa_content(&decoded);
+            let content = text
+                .lines()
+                .skip(line_start - 1)
+                .take(line_end.saturating_sub(line_start) + 1)
+                .collect::<Vec<_>>()
+                .join("\n");
+            let embedding_sha = entry
+                .get("embedding_sha")
+                .or_else(|| entry.get("content_sha"))
+                .and_then(|value| value.as_str());
+            let embedding = match embedding_sha {
+                Some(sha) => {
+                    read_cached_embedding(
+                        &vv_path.join("vectors"),
+                        &embedding_cache_key(embedding_model, sha),
+                    )
+                    .await
+                }
+                None => None,
+            };
+            index.upsert(
+                format!("{}-{}", record.id, chunk_id),
+                LocalDocument {
+                    doc_type: DOC_TYPE_CODE.to_string(),
+                    repo_id: record.id.clone(),
+                    file_path: file_path.to_string(),
+                    line_start,
+                    line_end,
+                    content,
+                    commit_sha: chunk_field("commit_sha"),
+                    blob_sha: chunk_field("blob_sha"),
+                    committed_at: entry.get("committed_at").and_then(|value| value.as_i64()),
+                    embedding,
+                    embedding_model: embedding_model.to_string(),
+                    term_count: 0,
+                    also_in: Vec::new(),
+                },
+            );
+            loaded += 1;
+        }
+        for (chunk_id, also_in) in chunk_duplicates(entries.iter()) {
+            let doc_id = format!("{}-{}", record.id, chunk_id);
+            if let Some(document) = index.documents.get_mut(&doc_id) {
+                document.also_in = also_in;
+            }
         }
-        Err(err) => return Err(err),
-    };
-    let mut store = read_summary_store(vv_path).await.unwrap_or_default();
-    let entry = SummaryEntry {
-        version: store.next_version(),
-        created_at: Utc::now().timestamp_millis(),
-        summary: summary.clone(),
-        long_summary: long_summary.clone(),
-    };
-    store.entries.push(entry);
-    write_summary_store(vv_path, &store).await?;
-    let _ = fs::write(vv_path.join("wiki/index.md"), summary).await;
-    Ok(store)
+    }
+    loaded
+}
+
+fn yql_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn yql_string_list(values: &[String]) -> String {
+    values
+        .iter()
+        .map(|value| yql_quote(value))
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
-fn build_search_yql(_repo_filter: Option<&str>, mode: SearchMode) -> String {
+fn build_search_yql(
+    mode: SearchMode,
+    extra_fields: &[&str],
+    filters: &[String],
+    all_summary_fields: bool,
+) -> String {
     let mut clauses = Vec::new();
     if matches!(mode, SearchMode::Hybrid | SearchMode::Semantic) {
         clauses.push("{targetHits:100}nearestNeighbor(embedding, query_embedding)".to_string());
@@ -2289,23 +9933,95 @@ fn build_search_yql(_repo_filter: Option<&str>, mode: SearchMode) -> String {
     if matches!(mode, SearchMode::Hybrid | SearchMode::Bm25) {
         clauses.push("userInput(@query)".to_string());
     }
+    if matches!(mode, SearchMode::Path) {
+        clauses.push("{defaultIndex:\"path_tokens\"}userInput(@query)".to_string());
+    }
 
-    let clause = if clauses.len() == 1 {
+    let mut clause = if clauses.len() == 1 {
         clauses[0].clone()
     } else {
         format!("({})", clauses.join(" or "))
     };
+    for filter in filters {
+        clause.push_str(" and ");
+        clause.push_str(filter);
+    }
+
+    let mut fields = vec![
+        "doc_type",
+        "repo_id",
+        "commit_sha",
+        "blob_sha",
+        "committed_at",
+        "file_path",
+        "line_start",
+        "line_end",
+        "content",
+        "also_in",
+    ];
+    if matches!(mode, SearchMode::Hybrid | SearchMode::Bm25) {
+        fields.push("content_highlight");
+    }
+    fields.extend_from_slice(extra_fields);
+    if all_summary_fields {
+        fields = vec!["*"];
+    }
 
     format!(
-        "select repo_id, file_path, line_start, line_end, content from sources * where {};",
+        "select {} from sources * where {};",
+        fields.join(", "),
         clause
     )
 }
 
-fn build_snippet(content: &str) -> String {
+fn parse_highlights(dynamic_summary: &str) -> Vec<String> {
+    let mut highlights: Vec<String> = Vec::new();
+    let mut rest = dynamic_summary;
+    while let Some(start) = rest.find("<hi>") {
+        rest = &rest[start + "<hi>".len()..];
+        let Some(end) = rest.find("</hi>") else {
+            break;
+        };
+        let term = rest[..end].trim();
+        if !term.is_empty()
+            && !highlights
+                .iter()
+                .any(|known| known.eq_ignore_ascii_case(term))
+        {
+            highlights.push(term.to_string());
+        }
+        rest = &rest[end + "</hi>".len()..];
+    }
+    highlights
+}
+
+fn build_snippet(
+    content: &str,
+    file_path: &StdPath,
+    line_start: usize,
+    query: &str,
+    options: ResultOptions,
+) -> String {
     const MAX_CHARS: usize = 400;
-    let trimmed = content.trim();
-    let mut chars = trimmed.chars();
+    let all_lines: Vec<&str> = content.lines().collect();
+    let window = best_snippet_window(&all_lines, query, options.snippet_lines)
+        .map(|start| &all_lines[start..(start + options.snippet_lines).min(all_lines.len())]);
+    let cleaned = match (options.snippet_mode, window) {
+        (SnippetMode::Raw, None) => content.trim().to_string(),
+        (SnippetMode::Raw, Some(lines)) => lines.join("\n").trim_matches('\n').to_string(),
+        (SnippetMode::Clean, Some(lines)) => dedent_lines(lines).trim_matches('\n').to_string(),
+        (SnippetMode::Clean, None) => {
+            let mut lines = all_lines;
+            if line_start <= 1 {
+                let skipped = leading_boilerplate_lines(&lines, &guess_language(file_path));
+                if skipped < lines.len() {
+                    lines.drain(..skipped);
+                }
+            }
+            dedent_lines(&lines).trim_matches('\n').to_string()
+        }
+    };
+    let mut chars = cleaned.chars();
     let snippet: String = chars.by_ref().take(MAX_CHARS).collect();
     if chars.next().is_some() {
         let mut limited = snippet;
@@ -2316,6 +10032,264 @@ fn build_snippet(content: &str) -> String {
     }
 }
 
+fn snippet_lines(
+    content: &str,
+    file_path: &StdPath,
+    line_start: usize,
+    query: &str,
+    options: ResultOptions,
+) -> Vec<SnippetLine> {
+    let all_lines: Vec<&str> = content.lines().collect();
+    let start = match best_snippet_window(&all_lines, query, options.snippet_lines) {
+        Some(start) => start,
+        None if options.snippet_mode == SnippetMode::Clean && line_start <= 1 => {
+            let skipped = leading_boilerplate_lines(&all_lines, &guess_language(file_path));
+            if skipped < all_lines.len() {
+                skipped
+            } else {
+                0
+            }
+        }
+        None => 0,
+    };
+    all_lines
+        .iter()
+        .enumerate()
+        .skip(start)
+        .take(options.snippet_lines)
+        .map(|(index, text)| SnippetLine {
+            number: line_start + index,
+            text: text.to_string(),
+        })
+        .collect()
+}
+
+fn best_snippet_window(lines: &[&str], query: &str, window: usize) -> Option<usize> {
+    let mut terms = tokenize_for_local_index(query);
+    terms.sort();
+    terms.dedup();
+    if terms.is_empty() || lines.is_empty() {
+        return None;
+    }
+    let line_terms: Vec<Vec<String>> = lines
+        .iter()
+        .map(|line| tokenize_for_local_index(line))
+        .collect();
+    let line_count = lines.len() as f64;
+    let weights: Vec<f64> = terms
+        .iter()
+        .map(|term| {
+            let frequency = line_terms
+                .iter()
+                .filter(|tokens| tokens.contains(term))
+                .count() as f64;
+            ((line_count - frequency + 0.5) / (frequency + 0.5) + 1.0).ln()
+        })
+        .collect();
+
+    let window = window.max(1).min(lines.len());
+    let mut best: Option<(f64, usize)> = None;
+    for start in 0..=(lines.len() - window) {
+        let score: f64 = terms
+            .iter()
+            .zip(&weights)
+            .map(|(term, weight)| {
+                let count = line_terms[start..start + window]
+                    .iter()
+                    .flatten()
+                    .filter(|token| *token == term)
+                    .count() as f64;
+                weight * (count * 2.2) / (count + 1.2)
+            })
+            .sum();
+        if score > 0.0 && best.is_none_or(|(best_score, _)| score > best_score) {
+            best = Some((score, start));
+        }
+    }
+    best.map(|(_, start)| start)
+}
+
+fn dedent_lines(lines: &[&str]) -> String {
+    let indent = lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    lines
+        .iter()
+        .map(|line| {
+            line.get(indent..)
+                .unwrap_or_else(|| line.trim_start())
+                .trim_end()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn leading_boilerplate_lines(lines: &[&str], language: &str) -> usize {
+    let (comment_prefixes, import_prefixes): (&[&str], &[&str]) = match language {
+        "rust" => (&["//", "#!["], &["use ", "pub use ", "extern crate "]),
+        "typescript" | "javascript" => (
+            &["//", "\"use ", "'use "],
+            &["import ", "export * from ", "const ", "require("],
+        ),
+        "python" => (&["#"], &["import ", "from "]),
+        "go" => (&["//"], &["package ", "import "]),
+        "java" => (&["//"], &["package ", "import "]),
+        "ruby" => (&["#"], &["require ", "require_relative "]),
+        _ => return 0,
+    };
+
+    let mut index = 0;
+    let mut closing: Option<&str> = None;
+    while index < lines.len() {
+        let line = lines[index].trim();
+        index += 1;
+        if let Some(close) = closing {
+            if line.contains(close) {
+                closing = None;
+            }
+            continue;
+        }
+        if line.is_empty()
+            || comment_prefixes
+                .iter()
+                .any(|prefix| line.starts_with(prefix))
+        {
+            continue;
+        }
+        if line.starts_with("/*") {
+            if !line.contains("*/") {
+                closing = Some("*/");
+            }
+            continue;
+        }
+        let is_import = import_prefixes
+            .iter()
+            .any(|prefix| line.starts_with(prefix))
+            && (!line.starts_with("const ") || line.contains("require("));
+        if !is_import {
+            return index - 1;
+        }
+        if line.contains('{') && !line.contains('}') {
+            closing = Some("}");
+        } else if line.ends_with('(') {
+            closing = Some(")");
+        }
+    }
+    index
+}
+
+fn extract_symbols(path: &StdPath, content: &str) -> Vec<(String, usize)> {
+    let language: tree_sitter::Language = match guess_language(path).as_str() {
+        "rust" => tree_sitter_rust::LANGUAGE.into(),
+        "python" => tree_sitter_python::LANGUAGE.into(),
+        "javascript" => tree_sitter_javascript::LANGUAGE.into(),
+        "go" => tree_sitter_go::LANGUAGE.into(),
+        _ => return Vec::new(),
+    };
+    let mut parser = tree_sitter::Parser::new();
+    if parser.set_language(&language).is_err() {
+        return Vec::new();
+    }
+    let Some(tree) = parser.parse(content, None) else {
+        return Vec::new();
+    };
+    let mut symbols = Vec::new();
+    collect_symbols(tree.root_node(), content.as_bytes(), 0, &mut symbols);
+    symbols
+}
+
+fn collect_symbols(
+    node: tree_sitter::Node,
+    source: &[u8],
+    depth: usize,
+    symbols: &mut Vec<(String, usize)>,
+) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        let named = match child.kind() {
+            "function_item"
+            | "function_signature_item"
+            | "struct_item"
+            | "enum_item"
+            | "trait_item"
+            | "type_item" => true,
+            "function_definition" | "class_definition" => true,
+            "function_declaration"
+            | "generator_function_declaration"
+            | "class_declaration"
+            | "method_definition" => true,
+            "method_declaration" | "type_spec" => true,
+            "variable_declarator" => child.child_by_field_name("value").is_some_and(|value| {
+                matches!(
+                    value.kind(),
+                    "arrow_function" | "function_expression" | "function"
+                )
+            }),
+            _ => false,
+        };
+        if named {
+            if let Some(name) = child
+                .child_by_field_name("name")
+                .and_then(|name| name.utf8_text(source).ok())
+            {
+                symbols.push((name.to_string(), child.start_position().row + 1));
+            }
+        }
+        let container = matches!(
+            child.kind(),
+            "impl_item"
+                | "trait_item"
+                | "mod_item"
+                | "declaration_list"
+                | "class_definition"
+                | "decorated_definition"
+                | "block"
+                | "class_declaration"
+                | "class_body"
+                | "export_statement"
+                | "lexical_declaration"
+                | "type_declaration"
+        );
+        if container && depth < MAX_SYMBOL_DEPTH {
+            collect_symbols(child, source, depth + 1, symbols);
+        }
+    }
+}
+
+/// Gives each symbol to the one chunk whose line range holds its definition line. Overlapping
+/// chunks repeat the previous chunk's tail, so a definition in the overlap goes to the later chunk,
+/// which carries more of its body.
+fn chunk_symbol_names(symbols: &[(String, usize)], chunks: &[FileChunk]) -> Vec<Vec<String>> {
+    let mut names = vec![Vec::<String>::new(); chunks.len()];
+    for (name, line) in symbols {
+        let owner = chunks
+            .iter()
+            .rposition(|chunk| (chunk.line_start..=chunk.line_end).contains(line));
+        if let Some(owner) = owner {
+            if !names[owner].contains(name) {
+                names[owner].push(name.clone());
+            }
+        }
+    }
+    names
+}
+
+const KNOWN_LANGUAGES: &[&str] = &[
+    "rust",
+    "typescript",
+    "javascript",
+    "python",
+    "go",
+    "java",
+    "ruby",
+    "markdown",
+    "json",
+    "yaml",
+];
+
 fn guess_language(path: &StdPath) -> String {
     let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
     match extension {
@@ -2336,20 +10310,810 @@ fn guess_language(path: &StdPath) -> String {
     .to_string()
 }
 
-fn parse_repo_url(repo_url: &str) -> Result<(String, String), AppError> {
+fn default_true() -> bool {
+    true
+}
+
+fn default_repo_host() -> String {
+    GITHUB_HOST.to_string()
+}
+
+fn is_safe_path_segment(value: &str) -> bool {
+    !value.is_empty() && value != "." && value != ".." && !value.contains(['/', '\\'])
+}
+
+fn parse_repo_url(repo_url: &str) -> Result<(String, String, String), AppError> {
     let trimmed = repo_url
         .trim()
         .trim_end_matches('/')
         .trim_end_matches(".git");
 
-    let cleaned = trimmed
-        .strip_prefix("https://github.com/")
-        .or_else(|| trimmed.strip_prefix("http://github.com/"))
-        .or_else(|| trimmed.strip_prefix("git@github.com:"))
+    let (host, cleaned) = [GITHUB_HOST, GITLAB_HOST]
+        .into_iter()
+        .find_map(|host| {
+            trimmed
+                .strip_prefix(&format!("https://{host}/"))
+                .or_else(|| trimmed.strip_prefix(&format!("http://{host}/")))
+                .or_else(|| trimmed.strip_prefix(&format!("git@{host}:")))
+                .map(|rest| (host, rest))
+        })
         .ok_or(AppError::InvalidRepoUrl)?;
 
-    let mut parts = cleaned.split('/');
-    let owner = parts.next().ok_or(AppError::InvalidRepoUrl)?;
-    let name = parts.next().ok_or(AppError::InvalidRepoUrl)?;
-    Ok((owner.to_string(), name.to_string()))
+    // Exactly owner/name: nested GitLab groups and tree/blob links would otherwise be cut down to
+    // their first two segments and point at a different project.
+    let parts: Vec<&str> = cleaned.split('/').filter(|part| !part.is_empty()).collect();
+    let [owner, name] = parts[..] else {
+        return Err(AppError::InvalidRepoUrl);
+    };
+    if !is_safe_path_segment(owner) || !is_safe_path_segment(name) {
+        return Err(AppError::InvalidRepoUrl);
+    }
+    Ok((host.to_string(), owner.to_string(), name.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repo_record(repo_url: &str, host: &str, owner: &str, name: &str) -> RepoRecord {
+        RepoRecord {
+            id: "9b2f5c1e-3f5d-4c4b-8a7e-1d2c3b4a5f60".to_string(),
+            repo_url: repo_url.to_string(),
+            host: host.to_string(),
+            owner: owner.to_string(),
+            name: name.to_string(),
+            tags: Vec::new(),
+            include_generated: None,
+            embedding_model: None,
+            branch: None,
+            commit: None,
+        }
+    }
+
+    #[test]
+    fn imported_record_matching_its_url_is_kept() {
+        let record = repo_record(
+            "https://github.com/acme/widgets",
+            "github.com",
+            "acme",
+            "widgets",
+        );
+        let imported = validate_imported_record(record).unwrap();
+        assert_eq!(imported.id, "9b2f5c1e-3f5d-4c4b-8a7e-1d2c3b4a5f60");
+        assert_eq!(
+            (imported.owner.as_str(), imported.name.as_str()),
+            ("acme", "widgets")
+        );
+    }
+
+    #[test]
+    fn imported_record_with_unsafe_path_segments_is_rejected() {
+        for (owner, name) in [
+            ("..", "widgets"),
+            ("acme", "."),
+            ("", "widgets"),
+            ("a/b", "c"),
+        ] {
+            let record = repo_record("https://github.com/acme/widgets", "github.com", owner, name);
+            assert!(
+                validate_imported_record(record).is_err(),
+                "{owner:?}/{name:?}"
+            );
+        }
+        let record = repo_record(
+            "https://github.com/acme/widgets",
+            "github.com",
+            "acme",
+            "w\\x",
+        );
+        assert!(validate_imported_record(record).is_err());
+    }
+
+    #[test]
+    fn imported_record_must_match_its_repo_url() {
+        let record = repo_record(
+            "https://github.com/acme/widgets",
+            "github.com",
+            "evil",
+            "widgets",
+        );
+        assert!(validate_imported_record(record).is_err());
+        let record = repo_record(
+            "https://github.com/acme/widgets",
+            "gitlab.com",
+            "acme",
+            "widgets",
+        );
+        assert!(validate_imported_record(record).is_err());
+        let record = repo_record(
+            "https://example.com/acme/widgets",
+            "example.com",
+            "acme",
+            "widgets",
+        );
+        assert!(validate_imported_record(record).is_err());
+    }
+
+    #[test]
+    fn imported_record_id_is_recomputed() {
+        let mut record = repo_record(
+            "https://github.com/acme/widgets",
+            "github.com",
+            "acme",
+            "widgets",
+        );
+        record.id = "../../etc".to_string();
+        let imported = validate_imported_record(record).unwrap();
+        assert!(Uuid::parse_str(&imported.id).is_ok());
+
+        let mut record = repo_record(
+            "https://github.com/acme/widgets",
+            "github.com",
+            "acme",
+            "widgets",
+        );
+        record.id = "9B2F5C1E-3F5D-4C4B-8A7E-1D2C3B4A5F60".to_string();
+        let imported = validate_imported_record(record).unwrap();
+        assert_eq!(imported.id, "9b2f5c1e-3f5d-4c4b-8a7e-1d2c3b4a5f60");
+    }
+
+    #[test]
+    fn scoped_search_uses_the_repo_embedding_model() {
+        let mut code = repo_record("https://github.com/acme/code", "github.com", "acme", "code");
+        code.id = "code".to_string();
+        code.embedding_model = Some("code-model".to_string());
+        let mut docs = repo_record("https://github.com/acme/docs", "github.com", "acme", "docs");
+        docs.id = "docs".to_string();
+        let records = vec![code, docs];
+
+        let scope = vec!["code".to_string()];
+        assert_eq!(
+            scoped_embedding_model(&records, Some(&scope), "default-model"),
+            Ok("code-model")
+        );
+        let scope = vec!["docs".to_string()];
+        assert_eq!(
+            scoped_embedding_model(&records, Some(&scope), "default-model"),
+            Ok("default-model")
+        );
+        assert_eq!(
+            scoped_embedding_model(&records, None, "default-model"),
+            Err(vec!["code-model", "default-model"])
+        );
+    }
+
+    #[tokio::test]
+    async fn changing_the_model_calls_the_provider_again() {
+        let vectors_path = std::env::temp_dir().join(format!("vv-vectors-{}", Uuid::new_v4()));
+        fs::create_dir_all(&vectors_path).await.unwrap();
+        let sha = sha256_hex(b"fn main() {}");
+        let calls = AtomicUsize::new(0);
+        let provider = |value: f32| {
+            let calls = &calls;
+            async move {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Ok(vec![value; 4])
+            }
+        };
+
+        let old_key = embedding_cache_key("old-model", &sha);
+        let (_, cached) =
+            embed_content_locked(&vectors_path, &old_key, None, 4, false, provider(1.0))
+                .await
+                .unwrap();
+        assert!(!cached);
+        let (_, cached) =
+            embed_content_locked(&vectors_path, &old_key, None, 4, false, provider(1.0))
+                .await
+                .unwrap();
+        assert!(cached);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let new_key = embedding_cache_key("new-model", &sha);
+        let (values, cached) =
+            embed_content_locked(&vectors_path, &new_key, None, 4, false, provider(2.0))
+                .await
+                .unwrap();
+        assert!(!cached);
+        assert_eq!(values, vec![2.0; 4]);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        fs::remove_dir_all(&vectors_path).await.ok();
+    }
+
+    #[test]
+    fn embedding_cache_key_depends_on_the_model() {
+        let sha = sha256_hex(b"fn main() {}");
+        assert_ne!(
+            embedding_cache_key("model-a", &sha),
+            embedding_cache_key("model-b", &sha)
+        );
+        assert_ne!(embedding_cache_key("model-a", &sha), sha);
+        assert_eq!(
+            embedding_cache_key("model-a", &sha),
+            embedding_cache_key("model-a", &sha)
+        );
+    }
+
+    #[test]
+    fn chunk_lines_by_language_parses_sizes_and_sentinels() {
+        let sizes =
+            parse_chunk_lines_by_language("python=80, YAML=file,markdown=headings").unwrap();
+        assert_eq!(sizes.get("python"), Some(&ChunkSize::Lines(80)));
+        assert_eq!(sizes.get("yaml"), Some(&ChunkSize::WholeFile));
+        assert_eq!(sizes.get("markdown"), Some(&ChunkSize::Headings));
+        assert!(parse_chunk_lines_by_language("").unwrap().is_empty());
+        assert_eq!(
+            parse_chunk_lines_by_language("python=0").unwrap_err(),
+            "python=0"
+        );
+        assert!(parse_chunk_lines_by_language("python=headings").is_err());
+        assert!(parse_chunk_lines_by_language("cobol=50").is_err());
+        assert!(parse_chunk_lines_by_language("python").is_err());
+    }
+
+    #[test]
+    fn chunk_content_uses_the_language_size() {
+        let content: String = (1..=10).map(|line| format!("line {line}\n")).collect();
+        let chunks = chunk_content(&content, ChunkSize::Lines(4), 20);
+        assert_eq!(chunks.len(), 7);
+        assert_eq!((chunks[0].line_start, chunks[0].line_end), (1, 4));
+        assert_eq!((chunks[1].line_start, chunks[1].line_end), (2, 5));
+
+        let chunks = chunk_content(&content, ChunkSize::WholeFile, 2);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!((chunks[0].line_start, chunks[0].line_end), (1, 10));
+        assert_eq!(chunks[0].content, content);
+    }
+
+    #[test]
+    fn markdown_headings_start_new_chunks() {
+        let content = "intro\n# Title\ntext\n```sh\n# not a heading\n```\n## Usage\n#hashtag\n";
+        let chunks = chunk_content(content, ChunkSize::Headings, 20);
+        let ranges: Vec<_> = chunks
+            .iter()
+            .map(|chunk| (chunk.line_start, chunk.line_end))
+            .collect();
+        assert_eq!(ranges, vec![(1, 1), (2, 6), (7, 8)]);
+        assert_eq!(chunks[2].content, "## Usage\n#hashtag\n");
+    }
+
+    #[test]
+    fn chunk_limit_truncates_or_skips_large_files() {
+        let content: String = (1..=10).map(|line| format!("line {line}\n")).collect();
+        let chunks = chunk_file(&content, 2, 0);
+        assert_eq!(chunks.len(), 5);
+
+        let (kept, overflowed) =
+            apply_chunk_limit(chunks.clone(), 5, ChunkOverflowPolicy::Truncate);
+        assert!(!overflowed);
+        assert_eq!(kept.len(), 5);
+
+        let (kept, overflowed) =
+            apply_chunk_limit(chunks.clone(), 3, ChunkOverflowPolicy::Truncate);
+        assert!(overflowed);
+        assert_eq!(kept, chunks[..3].to_vec());
+
+        let (kept, overflowed) = apply_chunk_limit(chunks, 3, ChunkOverflowPolicy::Skip);
+        assert!(overflowed);
+        assert!(kept.is_empty());
+
+        assert_eq!(
+            parse_chunk_overflow_policy(" Skip "),
+            Some(ChunkOverflowPolicy::Skip)
+        );
+        assert_eq!(parse_chunk_overflow_policy("drop"), None);
+    }
+
+    #[tokio::test]
+    async fn full_size_batch_fits_the_bulk_body_limit() {
+        let routes = Router::new().route(
+            "/search/batch",
+            post(|Json(payload): Json<BatchSearchRequest>| async move {
+                payload.queries.len().to_string()
+            }),
+        );
+        let app = limit_request_body(routes, DEFAULT_MAX_BULK_REQUEST_BODY_BYTES).merge(
+            limit_request_body(
+                Router::new().route("/search", post(|_: Bytes| async { "ok" })),
+                DEFAULT_MAX_REQUEST_BODY_BYTES,
+            ),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let queries: Vec<_> = (0..MAX_BATCH_QUERIES)
+            .map(|index| {
+                serde_json::json!({
+                    "query": format!("query {index}"),
+                    "search_mode": "semantic",
+                    "query_embedding": vec![-0.123_456_78_f32; 3072],
+                })
+            })
+            .collect();
+        let body = serde_json::to_vec(&serde_json::json!({ "queries": queries })).unwrap();
+        assert!(body.len() > 2 * 1024 * 1024);
+
+        let client = reqwest::Client::new();
+        let response = client
+            .post(format!("http://{address}/search/batch"))
+            .header("content-type", "application/json")
+            .body(body.clone())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.text().await.unwrap(),
+            MAX_BATCH_QUERIES.to_string()
+        );
+
+        let response = client
+            .post(format!("http://{address}/search"))
+            .body(body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let error: serde_json::Value = response.json().await.unwrap();
+        assert!(error["error"].as_str().unwrap().contains("262144"));
+    }
+
+    #[test]
+    fn repo_urls_parse_for_github_and_gitlab() {
+        let parsed = |url: &str| parse_repo_url(url).unwrap();
+        let expected = |host: &str| (host.to_string(), "acme".to_string(), "widgets".to_string());
+        assert_eq!(
+            parsed("https://github.com/acme/widgets"),
+            expected(GITHUB_HOST)
+        );
+        assert_eq!(
+            parsed("http://github.com/acme/widgets/"),
+            expected(GITHUB_HOST)
+        );
+        assert_eq!(
+            parsed("git@github.com:acme/widgets.git"),
+            expected(GITHUB_HOST)
+        );
+        assert_eq!(
+            parsed("https://gitlab.com/acme/widgets.git"),
+            expected(GITLAB_HOST)
+        );
+        assert_eq!(
+            parsed("git@gitlab.com:acme/widgets.git"),
+            expected(GITLAB_HOST)
+        );
+    }
+
+    #[test]
+    fn repo_urls_with_extra_segments_are_rejected() {
+        for url in [
+            "https://gitlab.com/acme/platform/widgets",
+            "git@gitlab.com:acme/platform/widgets.git",
+            "https://github.com/acme/widgets/tree/main",
+            "https://github.com/acme",
+            "https://github.com/acme/..",
+            "https://bitbucket.org/acme/widgets",
+        ] {
+            assert!(
+                matches!(parse_repo_url(url), Err(AppError::InvalidRepoUrl)),
+                "{url}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn api_key_guards_routes_but_not_the_search_page() {
+        let api_key: Arc<str> = Arc::from("s3cret");
+        let app = Router::new()
+            .route("/", get(|| async { "page" }))
+            .route("/repos", get(|| async { "repos" }))
+            .layer(middleware::from_fn_with_state(api_key, require_api_key));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let client = reqwest::Client::new();
+        let get = |path: &str, key: Option<&str>| {
+            let mut request = client.get(format!("http://{address}{path}"));
+            if let Some(key) = key {
+                request = request.header("authorization", format!("Bearer {key}"));
+            }
+            request.send()
+        };
+        assert_eq!(
+            get("/repos", None).await.unwrap().status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            get("/repos", Some("wrong")).await.unwrap().status(),
+            StatusCode::UNAUTHORIZED
+        );
+        let response = get("/repos", Some("s3cret")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), "repos");
+        assert_eq!(get("/", None).await.unwrap().status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn l2_normalize_scales_to_unit_length() {
+        let mut values = vec![3.0, -4.0, 12.0];
+        l2_normalize(&mut values);
+        let magnitude = values.iter().map(|value| value * value).sum::<f32>().sqrt();
+        assert!((magnitude - 1.0).abs() < 1e-6);
+        assert!((values[0] - 3.0 / 13.0).abs() < 1e-6);
+
+        let mut zero = vec![0.0; 4];
+        l2_normalize(&mut zero);
+        assert_eq!(zero, vec![0.0; 4]);
+    }
+
+    #[test]
+    fn symbols_are_extracted_and_assigned_to_chunks() {
+        let source =
+            "fn foo() {}\n\nstruct Bar {\n    x: u32,\n}\n\nimpl Bar {\n    fn baz(&self) {}\n}\n";
+        let symbols = extract_symbols(StdPath::new("src/lib.rs"), source);
+        assert_eq!(
+            symbols,
+            vec![
+                ("foo".to_string(), 1),
+                ("Bar".to_string(), 3),
+                ("baz".to_string(), 8),
+            ]
+        );
+        let chunks = chunk_content(source, ChunkSize::Lines(4), 0);
+        assert_eq!(
+            chunk_symbol_names(&symbols, &chunks),
+            vec![vec!["foo", "Bar"], vec!["baz"], vec![]]
+        );
+        // With two lines of overlap, `Bar` (line 3) sits in both of the first two chunks and is
+        // only attributed to the second.
+        let overlapping = chunk_content(source, ChunkSize::Lines(4), 2);
+        assert_eq!(
+            overlapping
+                .iter()
+                .map(|chunk| (chunk.line_start, chunk.line_end))
+                .collect::<Vec<_>>(),
+            vec![(1, 4), (3, 6), (5, 8), (7, 9)]
+        );
+        assert_eq!(
+            chunk_symbol_names(&symbols, &overlapping),
+            vec![vec!["foo"], vec!["Bar"], vec![], vec!["baz"]]
+        );
+
+        let python = extract_symbols(
+            StdPath::new("app.py"),
+            "class Store:\n    def load(self):\n        pass\n",
+        );
+        assert_eq!(
+            python,
+            vec![("Store".to_string(), 1), ("load".to_string(), 2)]
+        );
+        assert!(extract_symbols(StdPath::new("notes.md"), "# fn foo").is_empty());
+    }
+
+    #[test]
+    fn embedding_responses_parse_in_every_accepted_shape() {
+        let parse = |value: serde_json::Value| parse_hf_embedding(value).unwrap();
+        let expected = vec![0.5_f32, -1.0];
+        assert_eq!(parse(serde_json::json!([0.5, -1.0])), expected);
+        assert_eq!(
+            parse(serde_json::json!([[0.0, -2.0], [1.0, 0.0]])),
+            expected
+        );
+        assert_eq!(
+            parse(serde_json::json!({ "embeddings": [0.5, -1.0] })),
+            expected
+        );
+        assert_eq!(
+            parse(serde_json::json!({ "embedding": [0.5, -1.0] })),
+            expected
+        );
+        assert_eq!(
+            parse(serde_json::json!({ "data": [{ "embedding": [0.5, -1.0] }] })),
+            expected
+        );
+        assert_eq!(
+            parse(serde_json::json!({ "data": [[0.5, -1.0]] })),
+            expected
+        );
+    }
+
+    #[test]
+    fn malformed_embedding_responses_are_errors() {
+        for value in [
+            serde_json::json!([]),
+            serde_json::json!(["a", "b"]),
+            serde_json::json!({ "error": "model is loading" }),
+            serde_json::json!({ "data": [] }),
+            serde_json::json!({ "data": [{ "index": 0 }] }),
+            serde_json::json!({ "vectors": [0.5] }),
+            serde_json::json!("0.5"),
+        ] {
+            assert!(
+                matches!(
+                    parse_hf_embedding(value.clone()),
+                    Err(AppError::HuggingFace(_))
+                ),
+                "{value}"
+            );
+        }
+    }
+
+    #[test]
+    fn document_urls_percent_encode_the_doc_id() {
+        assert_eq!(
+            document_v1_url(
+                "https://vespa.example:8080/",
+                "code",
+                "codesearch",
+                "repo-abc123"
+            ),
+            "https://vespa.example:8080/document/v1/code/codesearch/docid/repo-abc123"
+        );
+        assert_eq!(
+            document_v1_url("http://localhost:8080", "code", "codesearch", "a/b:c d?#%"),
+            "http://localhost:8080/document/v1/code/codesearch/docid/a%2Fb%3Ac%20d%3F%23%25"
+        );
+    }
+
+    #[tokio::test]
+    async fn duplicated_registry_entries_keep_the_indexed_record() {
+        let repos_path = std::env::temp_dir().join(format!("vv-dedupe-{}", Uuid::new_v4()));
+        let record = |id: &str, owner: &str, name: &str| RepoRecord {
+            id: id.to_string(),
+            ..repo_record(
+                &format!("https://github.com/{owner}/{name}"),
+                GITHUB_HOST,
+                owner,
+                name,
+            )
+        };
+        let registry = vec![
+            record("first", "acme", "widgets"),
+            record("other", "acme", "gadgets"),
+            record("indexed", "Acme", "Widgets"),
+            record("last", "acme", "widgets"),
+        ];
+        let vv_path = repos_path.join("indexed").join("vv");
+        fs::create_dir_all(&vv_path).await.unwrap();
+        fs::write(
+            vv_path.join("manifest.json"),
+            r#"{"repo_id": "indexed", "indexed_at": "2026-01-02T03:04:05Z"}"#,
+        )
+        .await
+        .unwrap();
+
+        let deduped = dedupe_registry(registry.clone(), &repos_path, RepoPathLayout::Id).await;
+        let ids: Vec<&str> = deduped.iter().map(|record| record.id.as_str()).collect();
+        assert_eq!(ids, vec!["indexed", "other"]);
+
+        fs::remove_dir_all(&repos_path).await.unwrap();
+        let deduped = dedupe_registry(registry, &repos_path, RepoPathLayout::Id).await;
+        let ids: Vec<&str> = deduped.iter().map(|record| record.id.as_str()).collect();
+        assert_eq!(ids, vec!["last", "other"]);
+    }
+
+    #[test]
+    fn commit_sha_filter_is_added_to_the_yql() {
+        let sha = "0123456789abcdef0123456789abcdef01234567";
+        let filters = SearchFilters {
+            repo_ids: Some(vec!["repo-1".to_string()]),
+            commit_sha: Some(sha.to_string()),
+            ..SearchFilters::default()
+        };
+        let yql = build_search_yql(SearchMode::Bm25, &[], &filters.yql_clauses(), false);
+        assert!(
+            yql.ends_with(&format!(
+                "where userInput(@query) and repo_id in (\"repo-1\") and commit_sha contains \"{sha}\";"
+            )),
+            "{yql}"
+        );
+
+        let unfiltered = build_search_yql(
+            SearchMode::Bm25,
+            &[],
+            &SearchFilters::default().yql_clauses(),
+            false,
+        );
+        assert!(!unfiltered.contains("commit_sha contains"));
+    }
+
+    #[test]
+    fn embeddings_serialize_in_both_tensor_encodings() {
+        let json = VespaEmbedding::new(vec![1.0, -2.0, 0.5], VespaTensorEncoding::Json);
+        assert_eq!(
+            serde_json::to_value(&json).unwrap(),
+            serde_json::json!({ "values": [1.0, -2.0, 0.5] })
+        );
+        let hex = VespaEmbedding::new(vec![1.0, -2.0, 0.5], VespaTensorEncoding::Hex);
+        assert_eq!(
+            serde_json::to_value(&hex).unwrap(),
+            serde_json::json!({ "values": "3F800000C00000003F000000" })
+        );
+    }
+
+    #[test]
+    fn path_tokens_split_case_separators_and_extensions() {
+        let tokens = |path: &str| path_search_tokens(path).join(" ");
+        assert_eq!(
+            tokens("src/UserRepository.java"),
+            "src user repository java"
+        );
+        assert_eq!(tokens("lib/snake_case_file.rs"), "lib snake case file rs");
+        assert_eq!(
+            tokens("web/HTTPServer2Config.tsx"),
+            "web http server 2 config tsx"
+        );
+        assert_eq!(tokens("docs/read-me.v2.md"), "docs read me v 2 md");
+    }
+
+    #[test]
+    fn index_ignore_globs_exclude_and_reinclude_paths() {
+        let ignore = IndexIgnore::from_patterns([
+            "# generated code",
+            "*.min.js",
+            "vendor/",
+            "/build",
+            "docs/**/*.png",
+            "!vendor/keep.rs",
+            "",
+        ]);
+        let ignored = |path: &str| ignore.is_ignored(StdPath::new(path));
+        assert!(ignored("app.min.js"));
+        assert!(ignored("web/static/app.min.js"));
+        assert!(ignored("vendor/lib.rs"));
+        assert!(ignored("crates/foo/vendor/nested/lib.rs"));
+        assert!(ignored("build/output.txt"));
+        assert!(ignored("docs/guide/img/diagram.png"));
+        assert!(!ignored("vendor/keep.rs"));
+        assert!(!ignored("src/build/mod.rs"));
+        assert!(!ignored("web/static/app.js"));
+        assert!(!ignored("docs/guide/index.md"));
+        assert!(IndexIgnore::from_patterns(["# only comments", "!src/"]).is_empty());
+    }
+
+    #[test]
+    fn min_score_corrects_the_total_count() {
+        assert_eq!(min_score_total_count(250, 0, 10, 10, None), (250, true));
+        assert_eq!(min_score_total_count(250, 20, 10, 4, Some(0.5)), (24, true));
+        assert_eq!(
+            min_score_total_count(250, 20, 10, 0, Some(0.5)),
+            (20, false)
+        );
+        assert_eq!(min_score_total_count(250, 0, 10, 0, Some(0.5)), (0, true));
+        assert_eq!(
+            min_score_total_count(250, 20, 0, 0, Some(0.5)),
+            (250, false)
+        );
+        assert_eq!(
+            min_score_total_count(250, 0, 10, 10, Some(0.5)),
+            (250, false)
+        );
+    }
+
+    #[tokio::test]
+    async fn bare_sha_json_cache_is_still_a_hit() {
+        let vectors_path = std::env::temp_dir().join(format!("vv-vectors-{}", Uuid::new_v4()));
+        fs::create_dir_all(&vectors_path).await.unwrap();
+        let sha = sha256_hex(b"fn main() {}");
+        fs::write(
+            vectors_path.join(format!("{sha}.json")),
+            "[0.5, 0.25, 0.0, 1.0]",
+        )
+        .await
+        .unwrap();
+        let key = embedding_cache_key("default-model", &sha);
+        let provider = async { Err(AppError::HuggingFace("provider must not be called".into())) };
+
+        let (values, cached) =
+            embed_content_locked(&vectors_path, &key, Some(&sha), 4, false, provider)
+                .await
+                .unwrap();
+        assert!(cached);
+        assert_eq!(values, vec![0.5, 0.25, 0.0, 1.0]);
+        assert!(vectors_path.join(format!("{key}.f32")).exists());
+        assert!(!vectors_path.join(format!("{sha}.json")).exists());
+        assert!(!vectors_path.join(format!("{sha}.f32")).exists());
+
+        let mismatched = embedding_cache_key("default-model", "other");
+        fs::write(vectors_path.join("other.json"), "[1.0, 2.0]")
+            .await
+            .unwrap();
+        let (values, cached) =
+            embed_content_locked(&vectors_path, &mismatched, Some("other"), 4, false, async {
+                Ok(vec![3.0; 4])
+            })
+            .await
+            .unwrap();
+        assert!(!cached);
+        assert_eq!(values, vec![3.0; 4]);
+
+        fs::remove_dir_all(&vectors_path).await.ok();
+    }
+
+    #[test]
+    fn webhook_secrets_rotate_without_downtime() {
+        let body = br#"{"ref":"refs/heads/main"}"#;
+        let sign = |secret: &str| {
+            format!(
+                "sha256={}",
+                hex::encode(hmac_sha256(secret.as_bytes(), body))
+            )
+        };
+        let secrets = parse_webhook_secrets(" old-secret , new-secret ,,");
+        assert_eq!(
+            secrets,
+            vec!["old-secret".to_string(), "new-secret".to_string()]
+        );
+
+        assert!(verify_github_signature(&secrets, body, &sign("old-secret")));
+        assert!(verify_github_signature(&secrets, body, &sign("new-secret")));
+        assert!(verify_github_signature(
+            &secrets,
+            body,
+            &sign("new-secret")
+                .to_uppercase()
+                .replacen("SHA256", "sha256", 1)
+        ));
+        assert!(!verify_github_signature(
+            &secrets,
+            body,
+            &sign("wrong-secret")
+        ));
+        assert!(!verify_github_signature(
+            &secrets,
+            body,
+            &sign("old-secret")[7..]
+        ));
+        assert!(!verify_github_signature(
+            &secrets,
+            b"tampered",
+            &sign("new-secret")
+        ));
+
+        let rotated = parse_webhook_secrets("new-secret");
+        assert!(!verify_github_signature(
+            &rotated,
+            body,
+            &sign("old-secret")
+        ));
+        assert!(verify_github_signature(&rotated, body, &sign("new-secret")));
+        assert!(parse_webhook_secrets(" , ").is_empty());
+    }
+
+    #[test]
+    fn pinned_query_embedding_follows_normalize_embeddings() {
+        assert_eq!(
+            pinned_query_embedding(vec![3.0, 4.0], false),
+            vec![3.0, 4.0]
+        );
+        let normalized = pinned_query_embedding(vec![3.0, 4.0], true);
+        assert!((normalized[0] - 0.6).abs() < 1e-6);
+        assert!((normalized[1] - 0.8).abs() < 1e-6);
+        assert_eq!(pinned_query_embedding(vec![0.0, 0.0], true), vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn batch_results_keep_failed_queries_in_place() {
+        let results: Vec<BatchSearchResult> = vec![
+            Ok(SearchResponse {
+                results: vec![],
+                total_count: 3,
+                total_count_exact: true,
+            })
+            .into(),
+            Err(AppError::InvalidRequest("query is too long".into())).into(),
+        ];
+        let value = serde_json::to_value(&results).unwrap();
+        assert_eq!(value[0]["total_count"], 3);
+        assert_eq!(value[0]["results"], serde_json::json!([]));
+        assert_eq!(
+            value[1],
+            serde_json::json!({
+                "error": AppError::InvalidRequest("query is too long".into()).to_string()
+            })
+        );
+    }
 }